@@ -0,0 +1,32 @@
+/* C interface of ohmydb, built with `cargo build --features ffi`.
+ *
+ * Records are passed as JSON strings. Strings returned by the library must be released
+ * with ohmydb_string_free. Failures, panics included, return NULL (or -1), ohmydb_last_error describing them.
+ */
+#ifndef OHMYDB_H
+#define OHMYDB_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct OhMyDb OhMyDb;
+
+OhMyDb *ohmydb_open(const char *name);
+void ohmydb_close(OhMyDb *db);
+
+int ohmydb_add_table(OhMyDb *db, const char *table);
+char *ohmydb_find(OhMyDb *db, const char *table);
+char *ohmydb_query(OhMyDb *db, const char *sql);
+char *ohmydb_insert(OhMyDb *db, const char *table, const char *record);
+char *ohmydb_update(OhMyDb *db, const char *table, const char *record);
+int ohmydb_delete(OhMyDb *db, const char *table, const char *id);
+
+const char *ohmydb_last_error(void);
+void ohmydb_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* OHMYDB_H */
@@ -0,0 +1,78 @@
+use crate::error::OhMyDbError;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+/// The version of the archive layout written by `JsonDB::export_archive` and `JsonDB::backup_incremental`.
+pub(crate) const ARCHIVE_FORMAT: u32 = 1;
+
+/// A whole database, as stored (gzip-compressed JSON) in an archive file.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Archive {
+    /// The version of the archive layout.
+    pub format: u32,
+    /// When the archive was created, in milliseconds since the Unix epoch.
+    pub exported_at: u64,
+    /// The path of the database file the archive was exported from.
+    pub source: String,
+    /// Every table of the database, with its records exactly as stored.
+    pub tables: HashMap<String, HashSet<Value>>,
+}
+
+impl Archive {
+    /// Serializes and compresses the archive.
+    pub(crate) fn encode(&self) -> Result<Vec<u8>, OhMyDbError> {
+        encode(self)
+    }
+
+    /// Decompresses and deserializes an archive, rejecting unsupported formats.
+    pub(crate) fn decode(bytes: &[u8]) -> Result<Self, OhMyDbError> {
+        let archive: Archive = decode(bytes)?;
+        check_format(archive.format)?;
+
+        Ok(archive)
+    }
+}
+
+/// Serializes a value to JSON and gzip-compresses it.
+pub(crate) fn encode<T>(value: &T) -> Result<Vec<u8>, OhMyDbError>
+where
+    T: Serialize,
+{
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+
+    serde_json::to_writer(&mut encoder, value)?;
+
+    Ok(encoder.finish()?)
+}
+
+/// Decompresses gzip-compressed JSON and deserializes it, reporting failures as `OhMyDbError::InvalidArchive`.
+pub(crate) fn decode<T>(bytes: &[u8]) -> Result<T, OhMyDbError>
+where
+    T: DeserializeOwned,
+{
+    let mut json = Vec::new();
+
+    GzDecoder::new(bytes)
+        .read_to_end(&mut json)
+        .map_err(|e| OhMyDbError::InvalidArchive(e.to_string()))?;
+
+    serde_json::from_slice(&json).map_err(|e| OhMyDbError::InvalidArchive(e.to_string()))
+}
+
+/// Rejects archives written with an unsupported layout version.
+pub(crate) fn check_format(format: u32) -> Result<(), OhMyDbError> {
+    if format != ARCHIVE_FORMAT {
+        return Err(OhMyDbError::InvalidArchive(format!(
+            "unsupported format version {}",
+            format
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use crate::archive::{self, Archive, ARCHIVE_FORMAT};
+use crate::error::OhMyDbError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// The file name of the full backup a backup chain starts with.
+const FULL_BACKUP: &str = "full.ohmy.gz";
+
+/// The changes made to a database since the previous backup of a chain.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Increment {
+    /// The version of the archive layout.
+    pub format: u32,
+    /// When the increment was created, in milliseconds since the Unix epoch.
+    pub exported_at: u64,
+    /// The names of every table existing when the increment was created.
+    pub tables: Vec<String>,
+    /// The records added to each table since the previous backup (updated records included).
+    pub added: HashMap<String, Vec<Value>>,
+    /// The records removed from each table since the previous backup (the old versions of updated records included).
+    pub removed: HashMap<String, Vec<Value>>,
+}
+
+impl Increment {
+    /// Computes the changes turning `previous` into `current`.
+    pub(crate) fn between(
+        previous: &HashMap<String, HashSet<Value>>,
+        current: &HashMap<String, HashSet<Value>>,
+        exported_at: u64,
+    ) -> Self {
+        let empty = HashSet::new();
+        let mut added = HashMap::new();
+        let mut removed = HashMap::new();
+
+        for name in previous.keys().chain(current.keys()) {
+            let before = previous.get(name).unwrap_or(&empty);
+            let after = current.get(name).unwrap_or(&empty);
+            let new: Vec<Value> = after.difference(before).cloned().collect();
+            let gone: Vec<Value> = before.difference(after).cloned().collect();
+
+            if !new.is_empty() {
+                added.insert(name.clone(), new);
+            }
+            if !gone.is_empty() {
+                removed.insert(name.clone(), gone);
+            }
+        }
+
+        Self {
+            format: ARCHIVE_FORMAT,
+            exported_at,
+            tables: current.keys().cloned().collect(),
+            added,
+            removed,
+        }
+    }
+
+    /// Applies the changes to a database state.
+    pub(crate) fn apply(self, state: &mut HashMap<String, HashSet<Value>>) {
+        let tables: HashSet<String> = self.tables.into_iter().collect();
+
+        state.retain(|name, _| tables.contains(name));
+        for name in tables {
+            state.entry(name).or_default();
+        }
+        for (name, records) in self.removed {
+            if let Some(table) = state.get_mut(&name) {
+                records.iter().for_each(|record| {
+                    table.remove(record);
+                });
+            }
+        }
+        for (name, records) in self.added {
+            state.entry(name).or_default().extend(records);
+        }
+    }
+}
+
+/// A chain of backups stored in a directory: one full backup followed by numbered increments.
+pub(crate) struct BackupChain {
+    dir: PathBuf,
+}
+
+impl BackupChain {
+    pub(crate) fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Returns the path of the full backup of the chain.
+    pub(crate) fn full_path(&self) -> PathBuf {
+        self.dir.join(FULL_BACKUP)
+    }
+
+    /// Returns the path of the increment with the given sequence number.
+    pub(crate) fn increment_path(&self, sequence: usize) -> PathBuf {
+        self.dir.join(format!("incr-{:06}.ohmy.gz", sequence))
+    }
+
+    /// Returns whether the chain already has a full backup.
+    pub(crate) async fn exists(&self) -> bool {
+        tokio::fs::try_exists(self.full_path())
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Rebuilds the database state of the last backup of the chain, returning it along with the
+    /// number of increments it was made of.
+    pub(crate) async fn restore(
+        &self,
+    ) -> Result<(HashMap<String, HashSet<Value>>, usize), OhMyDbError> {
+        let full = Archive::decode(&tokio::fs::read(self.full_path()).await?)?;
+        let mut state = full.tables;
+        let mut sequence = 0;
+
+        loop {
+            let path = self.increment_path(sequence + 1);
+            if !tokio::fs::try_exists(&path).await? {
+                break;
+            }
+
+            let increment: Increment = archive::decode(&tokio::fs::read(&path).await?)?;
+            archive::check_format(increment.format)?;
+            increment.apply(&mut state);
+            sequence += 1;
+        }
+
+        Ok((state, sequence))
+    }
+}
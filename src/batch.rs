@@ -0,0 +1,18 @@
+use crate::error::OhMyDbError;
+use serde_json::Value;
+
+/// The outcome of `JsonDB::insert_many`: the records that were inserted and the items that were not.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// The inserted records, in the order of the items, as a read would return them.
+    pub inserted: Vec<Value>,
+    /// The items that were not inserted, by index in the batch, with the error their insert failed with.
+    pub failures: Vec<(usize, OhMyDbError)>,
+}
+
+impl BatchReport {
+    /// Returns whether every item of the batch was inserted.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
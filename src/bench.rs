@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+/// Latency statistics of a query run repeatedly by `JsonDB::bench`.
+///
+/// Percentiles use the nearest-rank method over the per-iteration latencies.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BenchReport {
+    /// The number of times the query was run.
+    pub iterations: usize,
+    /// The number of records the query returned (the same on every iteration).
+    pub rows: usize,
+    /// The fastest iteration.
+    pub min: Duration,
+    /// The mean latency across all iterations.
+    pub mean: Duration,
+    /// The median latency.
+    pub p50: Duration,
+    /// The latency 90% of the iterations were faster than or equal to.
+    pub p90: Duration,
+    /// The latency 99% of the iterations were faster than or equal to.
+    pub p99: Duration,
+    /// The slowest iteration.
+    pub max: Duration,
+}
+
+impl BenchReport {
+    /// Computes the report of the per-iteration latencies `samples` of a query returning `rows` records.
+    pub(crate) fn from_samples(mut samples: Vec<Duration>, rows: usize) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+
+        samples.sort();
+        let total: Duration = samples.iter().sum();
+        let percentile = |p: usize| samples[(samples.len() * p).div_ceil(100).max(1) - 1];
+
+        Self {
+            iterations: samples.len(),
+            rows,
+            min: samples[0],
+            mean: total / samples.len() as u32,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
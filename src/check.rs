@@ -0,0 +1,305 @@
+use crate::utils::{compare_numbers, get_nested_ref, values_equal};
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+
+/// A check constraint added with `JsonDB::add_check`: a boolean expression every record written to the table must satisfy.
+#[derive(Clone, Debug)]
+pub(crate) struct Check {
+    expression: String,
+    expr: Expr,
+}
+
+impl Check {
+    /// Parses a check expression, returning why it is invalid if it cannot be parsed.
+    ///
+    /// Expressions combine comparisons (`==`, `=`, `!=`, `<`, `<=`, `>`, `>=`) with `&&`, `||`, `!` and parentheses.
+    /// Operands are dot-separated fields of the record, literals (numbers, `'...'` or `"..."` strings, `true`, `false`
+    /// and `null`) and arithmetic over them (`+`, `-`, `*`, `/`, `%`), e.g. `qty > 0 && total >= qty * price`.
+    pub(crate) fn parse(expression: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(expression)?,
+            pos: 0,
+        };
+        let expr = parser.or()?;
+
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Self {
+                expression: expression.to_string(),
+                expr,
+            }),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    /// Returns the expression, as it was added.
+    pub(crate) fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    /// Returns whether the user data of a record satisfies the check, i.e. the expression evaluates to `true`.
+    ///
+    /// Missing fields evaluate to `null`. Ordering comparisons only hold between two numbers or two strings,
+    /// and arithmetic on anything but numbers yields `null`.
+    pub(crate) fn holds(&self, data: &Value) -> bool {
+        self.expr.eval(data) == Value::Bool(true)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Field(String),
+    Str(String),
+    Num(Number),
+    Symbol(&'static str),
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Literal(Value),
+    Field(String),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Box<Expr>, &'static str, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, data: &Value) -> Value {
+        match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Field(field) => get_nested_ref(data, field).cloned().unwrap_or(Value::Null),
+            Expr::Not(expr) => Value::Bool(expr.eval(data) != Value::Bool(true)),
+            Expr::Negate(expr) => arithmetic(&Value::from(0), "-", &expr.eval(data)),
+            Expr::Binary(left, "&&", right) => Value::Bool(
+                left.eval(data) == Value::Bool(true) && right.eval(data) == Value::Bool(true),
+            ),
+            Expr::Binary(left, "||", right) => Value::Bool(
+                left.eval(data) == Value::Bool(true) || right.eval(data) == Value::Bool(true),
+            ),
+            Expr::Binary(left, op, right) => {
+                let (left, right) = (left.eval(data), right.eval(data));
+                match *op {
+                    "==" => Value::Bool(values_equal(&left, &right)),
+                    "!=" => Value::Bool(!values_equal(&left, &right)),
+                    "<" | "<=" | ">" | ">=" => {
+                        Value::Bool(ordering(&left, &right).is_some_and(|ordering| match *op {
+                            "<" => ordering.is_lt(),
+                            "<=" => ordering.is_le(),
+                            ">" => ordering.is_gt(),
+                            _ => ordering.is_ge(),
+                        }))
+                    }
+                    op => arithmetic(&left, op, &right),
+                }
+            }
+        }
+    }
+}
+
+/// Orders two numbers numerically or two strings lexicographically, and nothing else.
+fn ordering(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(x), Value::Number(y)) => Some(compare_numbers(x, y)),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+fn arithmetic(left: &Value, op: &str, right: &Value) -> Value {
+    let (Some(x), Some(y)) = (left.as_f64(), right.as_f64()) else {
+        return Value::Null;
+    };
+    let result = match op {
+        "+" => x + y,
+        "-" => x - y,
+        "*" => x * y,
+        "/" => x / y,
+        _ => x % y,
+    };
+
+    Number::from_f64(result).map_or(Value::Null, Value::Number)
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string literal".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_ascii_digit() => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                let number = serde_json::from_str::<Number>(&s)
+                    .map_err(|_| format!("invalid number {}", s))?;
+                tokens.push(Token::Num(number));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Field(s));
+            }
+            _ => {
+                chars.next();
+                let next = chars.peek().copied();
+                let symbol = match (c, next) {
+                    ('&', Some('&')) => "&&",
+                    ('|', Some('|')) => "||",
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('=', _) => "=",
+                    ('!', _) => "!",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('+', _) => "+",
+                    ('-', _) => "-",
+                    ('*', _) => "*",
+                    ('/', _) => "/",
+                    ('%', _) => "%",
+                    ('(', _) => "(",
+                    (')', _) => ")",
+                    (c, _) => return Err(format!("unexpected character '{}'", c)),
+                };
+                if symbol.len() == 2 {
+                    chars.next();
+                }
+                tokens.push(Token::Symbol(symbol));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.symbol(&["||"]).is_some() {
+            expr = Expr::Binary(Box::new(expr), "||", Box::new(self.and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.symbol(&["&&"]).is_some() {
+            expr = Expr::Binary(Box::new(expr), "&&", Box::new(self.not()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        match self.symbol(&["!"]) {
+            Some(_) => Ok(Expr::Not(Box::new(self.not()?))),
+            None => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let expr = self.sum()?;
+
+        match self.symbol(&["==", "=", "!=", "<", "<=", ">", ">="]) {
+            Some(op) => {
+                let op = if op == "=" { "==" } else { op };
+                Ok(Expr::Binary(Box::new(expr), op, Box::new(self.sum()?)))
+            }
+            None => Ok(expr),
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.symbol(&["+", "-"]) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(op) = self.symbol(&["*", "/", "%"]) {
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.unary()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.symbol(&["-"]) {
+            Some(_) => Ok(Expr::Negate(Box::new(self.unary()?))),
+            None => self.operand(),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expr, String> {
+        if self.symbol(&["("]).is_some() {
+            let expr = self.or()?;
+            return match self.symbol(&[")"]) {
+                Some(_) => Ok(expr),
+                None => Err(format!(
+                    "expected ')', found {:?}",
+                    self.tokens.get(self.pos)
+                )),
+            };
+        }
+
+        let expr = match self.tokens.get(self.pos) {
+            Some(Token::Str(s)) => Expr::Literal(Value::String(s.clone())),
+            Some(Token::Num(n)) => Expr::Literal(Value::Number(n.clone())),
+            Some(Token::Field(w)) if w == "true" => Expr::Literal(Value::Bool(true)),
+            Some(Token::Field(w)) if w == "false" => Expr::Literal(Value::Bool(false)),
+            Some(Token::Field(w)) if w == "null" => Expr::Literal(Value::Null),
+            Some(Token::Field(field)) => Expr::Field(field.clone()),
+            other => return Err(format!("expected a field or a literal, found {:?}", other)),
+        };
+        self.pos += 1;
+
+        Ok(expr)
+    }
+
+    /// Consumes the next token if it is one of the provided symbols, returning it.
+    fn symbol(&mut self, symbols: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Symbol(s)) if symbols.contains(s) => {
+                self.pos += 1;
+                Some(*s)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::error::OhMyDbError;
+use crate::json_db::JsonDB;
+use crate::utils::record_id;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::marker::PhantomData;
+
+/// The reserved table persisting the configuration documents.
+const CONFIG_TABLE: &str = "_config";
+
+/// A typed configuration document persisted in the database file, returned by `JsonDB::config`.
+///
+/// Each configuration type is stored as a single document named after the type (e.g. `AppConfig`),
+/// so renaming the type starts from its default value. Changes are saved right away and reported
+/// to the callback registered with `JsonDB::on_config_change`.
+pub struct Config<'a, T> {
+    db: &'a mut JsonDB,
+    name: &'static str,
+    config: PhantomData<T>,
+}
+
+impl<'a, T> Config<'a, T>
+where
+    T: Serialize + DeserializeOwned + Default,
+{
+    pub(crate) fn new(db: &'a mut JsonDB) -> Self {
+        let name = std::any::type_name::<T>();
+        let name = name.split('<').next().unwrap_or(name);
+
+        Self {
+            db,
+            name: name.rsplit("::").next().unwrap_or(name),
+            config: PhantomData,
+        }
+    }
+
+    /// Returns the stored configuration, or the default value of `T` if none was stored yet.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::Serde` error if the stored document cannot be deserialized into `T`.
+    pub fn get(&self) -> Result<T, OhMyDbError> {
+        match self.stored() {
+            Some(config) => Ok(serde_json::from_value(config.clone())?),
+            None => Ok(T::default()),
+        }
+    }
+
+    /// Replaces the stored configuration and saves the database file.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The new configuration.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::Serde` error if the configuration cannot be serialized,
+    /// or the error saving the database file failed with.
+    pub async fn set(&mut self, config: &T) -> Result<(), OhMyDbError> {
+        let config = serde_json::to_value(config)?;
+        if self.stored() == Some(&config) {
+            return Ok(());
+        }
+
+        let table = self.db.reserved_table_mut(CONFIG_TABLE);
+        table.retain(|record| record_id(record, "id").as_deref() != Some(self.name));
+        table.insert(json!({ "id": self.name, "config": config }));
+        self.db.save().await?;
+        self.db.notify_config_change(self.name, &config);
+
+        Ok(())
+    }
+
+    /// Changes the stored configuration (or the default value of `T`) with `f` and saves it like `set`.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - The function changing the configuration, e.g. `|c| c.theme = "dark".to_string()`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the errors of `get` and `set`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the updated configuration.
+    pub async fn update<F>(&mut self, f: F) -> Result<T, OhMyDbError>
+    where
+        F: FnOnce(&mut T),
+    {
+        let mut config = self.get()?;
+        f(&mut config);
+        self.set(&config).await?;
+
+        Ok(config)
+    }
+
+    fn stored(&self) -> Option<&Value> {
+        self.db
+            .reserved_table(CONFIG_TABLE)?
+            .iter()
+            .find(|record| record_id(record, "id").as_deref() == Some(self.name))
+            .map(|record| &record["config"])
+    }
+}
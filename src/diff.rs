@@ -0,0 +1,164 @@
+use crate::archive::Archive;
+use crate::error::OhMyDbError;
+use crate::utils::record_id;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::path::Path;
+
+/// A record present in both databases compared by `JsonDB::diff_files`, with different content.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordChange {
+    /// The id of the record.
+    pub id: String,
+    /// The record in the first database.
+    pub before: Value,
+    /// The record in the second database.
+    pub after: Value,
+}
+
+/// The differences between the records of a table in two databases.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TableDiff {
+    /// The records only in the second database, ordered by id.
+    pub added: Vec<Value>,
+    /// The records only in the first database, ordered by id.
+    pub removed: Vec<Value>,
+    /// The records with the same id in both databases but different content, ordered by id.
+    pub changed: Vec<RecordChange>,
+}
+
+impl TableDiff {
+    /// Returns `true` if the table holds the same records in both databases.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Compares the records of a table, matching them by id. Records without a usable id are compared by content.
+    fn between(before: &HashSet<Value>, after: &HashSet<Value>) -> Self {
+        let by_id = |records: &HashSet<Value>| -> (BTreeMap<String, Value>, Vec<Value>) {
+            let mut ids = BTreeMap::new();
+            let mut anonymous = Vec::new();
+            for record in records {
+                match record_id(record, "id") {
+                    Some(id) => {
+                        ids.insert(id, record.clone());
+                    }
+                    None => anonymous.push(record.clone()),
+                }
+            }
+            (ids, anonymous)
+        };
+        let (mut before_ids, before_anonymous) = by_id(before);
+        let (after_ids, after_anonymous) = by_id(after);
+
+        let mut diff = TableDiff::default();
+        for (id, record) in after_ids {
+            match before_ids.remove(&id) {
+                None => diff.added.push(record),
+                Some(old) if old != record => diff.changed.push(RecordChange {
+                    id,
+                    before: old,
+                    after: record,
+                }),
+                Some(_) => {}
+            }
+        }
+        diff.removed.extend(before_ids.into_values());
+        diff.added.extend(
+            after_anonymous
+                .iter()
+                .filter(|record| !before_anonymous.contains(record))
+                .cloned(),
+        );
+        diff.removed.extend(
+            before_anonymous
+                .iter()
+                .filter(|record| !after_anonymous.contains(record))
+                .cloned(),
+        );
+
+        diff
+    }
+}
+
+/// The per-table report of `JsonDB::diff_files`, listing the tables whose records differ.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatabaseDiff {
+    /// The differences of each table whose records differ, by table name. A table missing from
+    /// one of the databases is compared as an empty table.
+    pub tables: BTreeMap<String, TableDiff>,
+}
+
+impl DatabaseDiff {
+    /// Returns `true` if both databases hold the same records.
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    /// Compares the tables of two databases.
+    pub(crate) fn between(
+        before: &HashMap<String, HashSet<Value>>,
+        after: &HashMap<String, HashSet<Value>>,
+    ) -> Self {
+        let empty = HashSet::new();
+        let tables = before
+            .keys()
+            .chain(after.keys())
+            .map(|name| {
+                let diff = TableDiff::between(
+                    before.get(name).unwrap_or(&empty),
+                    after.get(name).unwrap_or(&empty),
+                );
+                (name.clone(), diff)
+            })
+            .filter(|(_, diff)| !diff.is_empty())
+            .collect();
+
+        Self { tables }
+    }
+}
+
+impl Display for DatabaseDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+
+        let id = |record: &Value| record_id(record, "id").unwrap_or_else(|| record.to_string());
+        for (table, diff) in &self.tables {
+            writeln!(
+                f,
+                "{}: {} added, {} removed, {} changed",
+                table,
+                diff.added.len(),
+                diff.removed.len(),
+                diff.changed.len()
+            )?;
+            for record in &diff.added {
+                writeln!(f, "  + {}", id(record))?;
+            }
+            for record in &diff.removed {
+                writeln!(f, "  - {}", id(record))?;
+            }
+            for change in &diff.changed {
+                writeln!(f, "  ~ {}", change.id)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the tables of a database file, or of an archive written by `JsonDB::export_archive` or `JsonDB::backup_to`.
+pub(crate) async fn read_tables(
+    path: &Path,
+) -> Result<HashMap<String, HashSet<Value>>, OhMyDbError> {
+    let bytes = tokio::fs::read(path).await?;
+
+    match bytes.starts_with(&[0x1f, 0x8b]) {
+        true => Ok(Archive::decode(&bytes)?.tables),
+        false if bytes.is_empty() => Ok(HashMap::new()),
+        false => Ok(serde_json::from_slice(&bytes)?),
+    }
+}
@@ -0,0 +1,180 @@
+use crate::integrity::IntegrityIssue;
+use std::fmt::{self, Display};
+
+/// A single problem found by `JsonDB::doctor` or `JsonDB::doctor_file`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DoctorFinding {
+    /// The file is neither a database file nor an archive that can be read, e.g. it was truncated or edited by hand.
+    UnreadableFile { reason: String },
+    /// The file is an archive written with a layout version this version of the library doesn't read.
+    UnsupportedFormat { format: u32 },
+    /// A problem with the records, see `JsonDB::integrity_report`.
+    Integrity(IntegrityIssue),
+    /// A cold table (see `JsonDB::freeze`) whose file is missing or can't be read, so its records are lost.
+    MissingColdFile {
+        table: String,
+        file: String,
+        reason: String,
+    },
+    /// A cold table file next to the database file that no cold table refers to, e.g. left behind by a crash.
+    OrphanedColdFile { file: String },
+    /// The auto-increment sequence of a table is behind the largest id stored, so the next insert would reuse an id.
+    SequenceBehind {
+        table: String,
+        last: u64,
+        max_id: u64,
+    },
+    /// Cached field statistics computed before the table was modified (see `JsonDB::index_stats`).
+    StaleStatistics { table: String, field: String },
+}
+
+impl DoctorFinding {
+    /// Returns a suggested fix for the problem.
+    pub fn fix(&self) -> String {
+        match self {
+            Self::UnreadableFile { .. } => {
+                "restore the database from a backup (see `JsonDB::restore_chain`)".to_string()
+            }
+            Self::UnsupportedFormat { .. } => {
+                "import the archive with the version of ohmydb that exported it".to_string()
+            }
+            Self::Integrity(IntegrityIssue::MissingId { table, .. }) => format!(
+                "set the id of the record in the database file, or delete it from {}",
+                table
+            ),
+            Self::Integrity(IntegrityIssue::DuplicateId { table, id, .. }) => format!(
+                "keep a single record with id {:?} in {}, e.g. with `delete_by_id` and an insert",
+                id, table
+            ),
+            Self::Integrity(IntegrityIssue::DanglingReference {
+                field,
+                referenced_table,
+                value,
+                ..
+            }) => format!(
+                "insert the record {} into {}, or update {} to a record that exists",
+                value, referenced_table, field
+            ),
+            Self::Integrity(IntegrityIssue::CheckFailure { check, .. }) => {
+                format!("update the record so `{}` holds", check)
+            }
+            Self::Integrity(IntegrityIssue::UniqueViolation { fields, .. }) => format!(
+                "update or delete all but one of the records sharing their {}",
+                fields.join(", ")
+            ),
+            Self::MissingColdFile { table, .. } => format!(
+                "restore the file from a backup, or drop {} with `drop_table`",
+                table
+            ),
+            Self::OrphanedColdFile { file } => format!("delete {}", file),
+            Self::SequenceBehind { table, .. } => format!(
+                "remove the {} entry of the `_sequences` table from the database file, so the sequence restarts after the largest id",
+                table
+            ),
+            Self::StaleStatistics { table, .. } => format!("call `reindex(\"{}\")`", table),
+        }
+    }
+}
+
+impl Display for DoctorFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnreadableFile { reason } => write!(f, "unreadable file: {}", reason),
+            Self::UnsupportedFormat { format } => {
+                write!(f, "unsupported archive format version {}", format)
+            }
+            Self::Integrity(IntegrityIssue::MissingId { table, .. }) => {
+                write!(f, "{}: a record has no usable id", table)
+            }
+            Self::Integrity(IntegrityIssue::DuplicateId { table, id, count }) => {
+                write!(f, "{}: {} records share the id {:?}", table, count, id)
+            }
+            Self::Integrity(IntegrityIssue::DanglingReference {
+                table,
+                id,
+                field,
+                referenced_table,
+                value,
+            }) => write!(
+                f,
+                "{}: record {:?} references {} {} through {}, which doesn't exist",
+                table, id, referenced_table, value, field
+            ),
+            Self::Integrity(IntegrityIssue::CheckFailure { table, id, check }) => {
+                write!(f, "{}: record {:?} fails the check `{}`", table, id, check)
+            }
+            Self::Integrity(IntegrityIssue::UniqueViolation {
+                table,
+                fields,
+                values,
+                count,
+            }) => write!(
+                f,
+                "{}: {} records share the {} {:?}",
+                table,
+                count,
+                fields.join(", "),
+                values
+            ),
+            Self::MissingColdFile {
+                table,
+                file,
+                reason,
+            } => write!(
+                f,
+                "{}: cold table file {} unreadable: {}",
+                table, file, reason
+            ),
+            Self::OrphanedColdFile { file } => {
+                write!(f, "cold table file {} belongs to no table", file)
+            }
+            Self::SequenceBehind {
+                table,
+                last,
+                max_id,
+            } => write!(
+                f,
+                "{}: the id sequence is at {} but the table holds the id {}",
+                table, last, max_id
+            ),
+            Self::StaleStatistics { table, field } => {
+                write!(f, "{}: the statistics of {} are stale", table, field)
+            }
+        }
+    }
+}
+
+/// The diagnostic of a database, listing every problem found with a suggested fix. It prints as a summary.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DoctorReport {
+    /// The layout version of the archive diagnosed, `None` for a database file.
+    pub archive_format: Option<u32>,
+    pub findings: Vec<DoctorFinding>,
+}
+
+impl DoctorReport {
+    /// Returns `true` if no problem was found.
+    pub fn is_ok(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+impl Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.archive_format {
+            Some(format) => writeln!(f, "archive, format version {}", format)?,
+            None => writeln!(f, "database file")?,
+        }
+        if self.is_ok() {
+            return writeln!(f, "no problem found");
+        }
+
+        writeln!(f, "{} problem(s) found", self.findings.len())?;
+        for finding in &self.findings {
+            writeln!(f, "  ✗ {}", finding)?;
+            writeln!(f, "    ✔ {}", finding.fix())?;
+        }
+
+        Ok(())
+    }
+}
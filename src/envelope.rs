@@ -0,0 +1,54 @@
+use serde_json::{Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The key under which an enveloped record stores the user data.
+pub(crate) const DATA: &str = "data";
+
+/// Returns the user data of an enveloped record, i.e. its `data` field.
+pub(crate) fn data(record: &Value) -> &Value {
+    record.get(DATA).unwrap_or(record)
+}
+
+/// Wraps freshly inserted user data into an envelope created at `now`.
+///
+/// The envelope mirrors the record id at its top level, so id lookups work the same in both storage modes.
+pub(crate) fn wrap(data: Value, now: SystemTime) -> Value {
+    let mut envelope = Map::new();
+    let now = Value::from(unix_millis(now));
+
+    if let Some(id) = data.get("id") {
+        envelope.insert("id".to_string(), id.clone());
+    }
+    envelope.insert(DATA.to_string(), data);
+    envelope.insert("created_at".to_string(), now.clone());
+    envelope.insert("updated_at".to_string(), now);
+    envelope.insert("revision".to_string(), Value::from(1u64));
+    envelope.insert("deleted_at".to_string(), Value::Null);
+
+    Value::Object(envelope)
+}
+
+/// Replaces the user data of a stored envelope, bumping its revision and `updated_at` to `now`.
+pub(crate) fn rewrap(envelope: &Value, data: Value, now: SystemTime) -> Value {
+    let mut updated = wrap(data, now);
+    let revision = envelope
+        .get("revision")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+
+    updated["revision"] = Value::from(revision + 1);
+    if let Some(created_at) = envelope.get("created_at") {
+        updated["created_at"] = created_at.clone();
+    }
+    if let Some(deleted_at) = envelope.get("deleted_at") {
+        updated["deleted_at"] = deleted_at.clone();
+    }
+
+    updated
+}
+
+/// Converts a time into milliseconds since the Unix epoch, the format of the envelope timestamps.
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
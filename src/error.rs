@@ -0,0 +1,136 @@
+use crate::options::Quota;
+use std::fmt::{self, Display};
+use std::io;
+
+/// The error type returned by every fallible `JsonDB` operation.
+///
+/// Each variant describes one failure category, so callers can match on
+/// "table not found" vs "duplicate id" instead of parsing error messages.
+#[derive(Debug)]
+pub enum OhMyDbError {
+    /// The requested table does not exist in the database.
+    TableNotFound(String),
+    /// A record with the same id already exists in the table.
+    RecordExists { table: String, id: String },
+    /// No record with the given id exists in the table.
+    RecordNotFound { table: String, id: String },
+    /// A record is missing a field the operation relies on (e.g. `id`).
+    MissingField(String),
+    /// A record could not be converted into a storable JSON record.
+    InvalidRecord(String),
+    /// A field value was rejected by a configured field codec.
+    InvalidField { field: String, reason: String },
+    /// A comparator pattern (e.g. a regular expression) could not be compiled.
+    InvalidPattern { pattern: String, reason: String },
+    /// A write would store two records with equal values for the fields of a unique constraint.
+    UniqueViolation { table: String, fields: Vec<String> },
+    /// A check constraint added with `JsonDB::add_check` could not be parsed.
+    InvalidCheck { expression: String, reason: String },
+    /// A write would store a record that does not satisfy a check constraint of the table.
+    CheckViolation { table: String, check: String },
+    /// A write would make the table exceed one of its quotas.
+    QuotaExceeded { table: String, quota: Quota },
+    /// A deleted record is referenced through a relation declared with `OnDelete::Restrict`.
+    StillReferenced {
+        table: String,
+        referencing_table: String,
+        field: String,
+    },
+    /// A row-level security policy rejected a write to the table.
+    AccessDenied { table: String },
+    /// A query uses a custom comparator that was not registered with `JsonDB::register_comparator`.
+    UnknownComparator(String),
+    /// A query parameter has no bound value, or its value does not suit the comparison it is used in.
+    InvalidParameter { name: String, reason: String },
+    /// A database archive could not be read, e.g. because it is corrupted or has an unsupported format.
+    InvalidArchive(String),
+    /// The operation was cancelled through its `CancellationToken` before changing anything.
+    Cancelled,
+    /// A value could not be serialized to or deserialized from JSON.
+    Serde(serde_json::Error),
+    /// Reading or writing the database file failed.
+    Io(io::Error),
+}
+
+impl Display for OhMyDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OhMyDbError::TableNotFound(table) => write!(f, "Table '{}' not found", table),
+            OhMyDbError::RecordExists { table, id } => {
+                write!(
+                    f,
+                    "Record with id \"{}\" already exists in table {}",
+                    id, table
+                )
+            }
+            OhMyDbError::RecordNotFound { table, id } => {
+                write!(f, "Record with id \"{}\" not found in table {}", id, table)
+            }
+            OhMyDbError::MissingField(field) => write!(f, "Record is missing field '{}'", field),
+            OhMyDbError::InvalidRecord(reason) => write!(f, "Invalid record: {}", reason),
+            OhMyDbError::InvalidField { field, reason } => {
+                write!(f, "Invalid value for field '{}': {}", field, reason)
+            }
+            OhMyDbError::InvalidPattern { pattern, reason } => {
+                write!(f, "Invalid pattern '{}': {}", pattern, reason)
+            }
+            OhMyDbError::UniqueViolation { table, fields } => write!(
+                f,
+                "Unique constraint on ({}) violated in table {}",
+                fields.join(", "),
+                table
+            ),
+            OhMyDbError::InvalidCheck { expression, reason } => {
+                write!(f, "Invalid check '{}': {}", expression, reason)
+            }
+            OhMyDbError::CheckViolation { table, check } => {
+                write!(f, "Check '{}' violated in table {}", check, table)
+            }
+            OhMyDbError::QuotaExceeded { table, quota } => {
+                write!(f, "Quota of {} exceeded in table {}", quota, table)
+            }
+            OhMyDbError::StillReferenced {
+                table,
+                referencing_table,
+                field,
+            } => write!(
+                f,
+                "Record of table {} is still referenced by {}.{}",
+                table, referencing_table, field
+            ),
+            OhMyDbError::AccessDenied { table } => {
+                write!(f, "Access denied to record in table {}", table)
+            }
+            OhMyDbError::UnknownComparator(name) => write!(f, "Unknown comparator '{}'", name),
+            OhMyDbError::InvalidParameter { name, reason } => {
+                write!(f, "Invalid query parameter '{}': {}", name, reason)
+            }
+            OhMyDbError::InvalidArchive(reason) => write!(f, "Invalid archive: {}", reason),
+            OhMyDbError::Cancelled => write!(f, "Operation cancelled"),
+            OhMyDbError::Serde(e) => write!(f, "Serialization error: {}", e),
+            OhMyDbError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OhMyDbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OhMyDbError::Serde(e) => Some(e),
+            OhMyDbError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for OhMyDbError {
+    fn from(e: io::Error) -> Self {
+        OhMyDbError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for OhMyDbError {
+    fn from(e: serde_json::Error) -> Self {
+        OhMyDbError::Serde(e)
+    }
+}
@@ -0,0 +1,38 @@
+use crate::envelope::unix_millis;
+use serde_json::Value;
+use std::time::SystemTime;
+
+/// The top-level key under which a stored record keeps its expiry, in milliseconds since the Unix epoch.
+///
+/// It sits next to the user data in plain tables and next to the other metadata in enveloped ones,
+/// and is removed from the records returned by reads.
+pub(crate) const EXPIRES_AT: &str = "_expires_at";
+
+/// Returns whether a stored record has an expiry that is due at `now`.
+pub(crate) fn is_expired(record: &Value, now: SystemTime) -> bool {
+    record
+        .get(EXPIRES_AT)
+        .and_then(Value::as_u64)
+        .is_some_and(|at| at <= unix_millis(now))
+}
+
+/// Sets the expiry of a stored record.
+pub(crate) fn set(record: &mut Value, at: u64) {
+    if let Value::Object(fields) = record {
+        fields.insert(EXPIRES_AT.to_string(), Value::from(at));
+    }
+}
+
+/// Removes the expiry of a record, e.g. before returning it to the caller.
+pub(crate) fn strip(record: &mut Value) {
+    if let Value::Object(fields) = record {
+        fields.remove(EXPIRES_AT);
+    }
+}
+
+/// Carries the expiry of a stored record over to the record replacing it, so updates don't make it permanent.
+pub(crate) fn keep(existing: &Value, replacement: &mut Value) {
+    if let Some(at) = existing.get(EXPIRES_AT).and_then(Value::as_u64) {
+        set(replacement, at);
+    }
+}
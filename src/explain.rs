@@ -0,0 +1,34 @@
+use crate::types::Order;
+
+/// A description of what running a query would do, returned by `Query::explain`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explain {
+    /// The operation performed: `"create"`, `"read"`, `"update"`, `"merge"` or `"delete"`, or `None` for an empty query.
+    pub operation: Option<&'static str>,
+    /// The table the query targets.
+    pub table: Option<String>,
+    /// The filter conditions, in the order they are evaluated: a record matches if every condition
+    /// of at least one group matches it.
+    pub filters: Vec<Vec<String>>,
+    /// The sort keys, applied in order.
+    pub order_by: Vec<(String, Order)>,
+    /// The number of matching records skipped.
+    pub skip: Option<usize>,
+    /// The maximum number of records returned.
+    pub limit: Option<usize>,
+    /// The projected fields, if any.
+    pub select: Option<Vec<String>>,
+    /// The field results are deduplicated by, if any.
+    pub distinct: Option<String>,
+    /// Whether cached field statistics (see `JsonDB::field_stats`) back the estimate of at least one filter condition.
+    /// It only reflects the estimates: tables are not indexed, so every record is visited to find the matching ones.
+    pub has_statistics: bool,
+    /// The estimated number of records scanned, i.e. the size of the table since every record is visited.
+    pub estimated_scan: usize,
+    /// The estimated number of records matching each filter condition, laid out like `filters`, or `None` for
+    /// conditions on fields without cached statistics or that statistics say nothing about (patterns, predicates, ...).
+    pub estimated_matches: Vec<Vec<Option<usize>>>,
+    /// The estimated number of records matching the filters, before `skip` and `limit`, assuming independent
+    /// conditions and counting the ones without an estimate as matching every record.
+    pub estimated_rows: usize,
+}
@@ -0,0 +1,314 @@
+//! A C ABI over the database, so tools written in other languages (Python with `ctypes` or `cffi`,
+//! Swift, ...) can read and modify the same files as a Rust service.
+//!
+//! Every function takes and returns UTF-8, nul-terminated strings, records being passed as JSON.
+//! Strings returned by the library are owned by the caller and must be released with `ohmydb_string_free`.
+//! Functions signal failures by returning `NULL` (or `-1`), the message of the last error of the calling thread
+//! being available through `ohmydb_last_error`. Panics never cross the boundary: they are reported as failures too.
+//!
+//! ```c
+//! OhMyDb *db = ohmydb_open("app");
+//! char *todo = ohmydb_insert(db, "todos", "{\"id\": \"1\", \"title\": \"Milk\"}");
+//! char *open = ohmydb_query(db, "SELECT * FROM todos WHERE is_completed = false");
+//! ohmydb_string_free(todo);
+//! ohmydb_string_free(open);
+//! ohmydb_close(db);
+//! ```
+
+use crate::error::OhMyDbError;
+use crate::json_db::JsonDB;
+use serde_json::Value;
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use tokio::runtime::{Builder, Runtime};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An open database, with the runtime its operations are driven on.
+pub struct OhMyDb {
+    runtime: Runtime,
+    db: JsonDB,
+}
+
+/// Records the message of `error` as the last error of the calling thread.
+fn set_last_error(error: impl ToString) {
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Borrows a string argument, reporting an `OhMyDbError::InvalidParameter` if it is `NULL` or not UTF-8.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or point to a nul-terminated string valid for the lifetime `'a`.
+unsafe fn arg<'a>(name: &str, s: *const c_char) -> Result<&'a str, OhMyDbError> {
+    if s.is_null() {
+        return Err(OhMyDbError::InvalidParameter {
+            name: name.to_string(),
+            reason: "must not be NULL".to_string(),
+        });
+    }
+
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| OhMyDbError::InvalidParameter {
+            name: name.to_string(),
+            reason: e.to_string(),
+        })
+}
+
+/// Parses a JSON string argument.
+///
+/// # Safety
+///
+/// Same as `arg`.
+unsafe fn json_arg(name: &str, s: *const c_char) -> Result<Value, OhMyDbError> {
+    Ok(serde_json::from_str(arg(name, s)?)?)
+}
+
+/// Borrows the database behind a handle.
+///
+/// # Safety
+///
+/// `handle` must be `NULL` or a handle returned by `ohmydb_open` and not closed yet.
+unsafe fn handle<'a>(handle: *mut OhMyDb) -> Result<&'a mut OhMyDb, OhMyDbError> {
+    handle
+        .as_mut()
+        .ok_or_else(|| OhMyDbError::InvalidParameter {
+            name: "db".to_string(),
+            reason: "must not be NULL".to_string(),
+        })
+}
+
+/// Runs the body of an exported function, as unwinding into the frames of the caller is undefined behavior:
+/// a panic is recorded as the last error and `failed` is returned instead.
+fn guarded<T>(failed: T, body: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        let message = match (
+            payload.downcast_ref::<&str>(),
+            payload.downcast_ref::<String>(),
+        ) {
+            (Some(message), _) => message.to_string(),
+            (None, Some(message)) => message.clone(),
+            (None, None) => "unknown panic".to_string(),
+        };
+        set_last_error(format!("panicked: {}", message));
+        failed
+    })
+}
+
+/// Returns `value` as a JSON string owned by the caller, or `NULL` after recording the error.
+fn to_c_string(value: Result<Value, OhMyDbError>) -> *mut c_char {
+    // Serialized JSON escapes control characters, so it never contains a nul byte.
+    let json = value.and_then(|value| Ok(serde_json::to_string(&value)?));
+
+    match json.map(CString::new) {
+        Ok(Ok(json)) => json.into_raw(),
+        Ok(Err(e)) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+        Err(e) => {
+            set_last_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Returns `Ok(n)` as `n` and errors as `-1` after recording them.
+fn to_c_int(value: Result<usize, OhMyDbError>) -> c_int {
+    match value {
+        Ok(n) => c_int::try_from(n).unwrap_or(c_int::MAX),
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+/// Opens (or creates) the database `<name>.json` in the current directory.
+///
+/// Returns `NULL` on failure.
+///
+/// # Safety
+///
+/// `name` must be a nul-terminated string. The handle must be released with `ohmydb_close`.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_open(name: *const c_char) -> *mut OhMyDb {
+    guarded(ptr::null_mut(), || {
+        let opened = arg("name", name).and_then(|name| {
+            let runtime = Builder::new_current_thread().enable_all().build()?;
+            let db = runtime.block_on(JsonDB::new(name))?;
+            Ok(OhMyDb { runtime, db })
+        });
+
+        match opened {
+            Ok(db) => Box::into_raw(Box::new(db)),
+            Err(e) => {
+                set_last_error(e);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Closes a database opened with `ohmydb_open`. Every write is already saved, so nothing is lost.
+///
+/// # Safety
+///
+/// `db` must be `NULL` or a handle returned by `ohmydb_open`, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_close(db: *mut OhMyDb) {
+    guarded((), || {
+        if !db.is_null() {
+            drop(Box::from_raw(db));
+        }
+    })
+}
+
+/// Creates a table if it doesn't exist yet.
+///
+/// Returns `0`, or `-1` on failure.
+///
+/// # Safety
+///
+/// `db` must be a live handle and `table` a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_add_table(db: *mut OhMyDb, table: *const c_char) -> c_int {
+    guarded(-1, || {
+        let added = handle(db).and_then(|OhMyDb { runtime, db }| {
+            let table = arg("table", table)?;
+            runtime.block_on(db.add_table(table)).map(|()| 0)
+        });
+
+        to_c_int(added)
+    })
+}
+
+/// Returns every record of a table as a JSON array.
+///
+/// # Safety
+///
+/// `db` must be a live handle and `table` a nul-terminated string. The result must be released with `ohmydb_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_find(db: *mut OhMyDb, table: *const c_char) -> *mut c_char {
+    guarded(ptr::null_mut(), || {
+        let found = handle(db).and_then(|OhMyDb { runtime, db }| {
+            let table = arg("table", table)?;
+            let records = runtime.block_on(db.find(table).run(db))?;
+            Ok(Value::Array(records))
+        });
+
+        to_c_string(found)
+    })
+}
+
+/// Runs a query written in the SQL subset of `JsonDB::query`, returning the matching records as a JSON array.
+///
+/// # Safety
+///
+/// `db` must be a live handle and `sql` a nul-terminated string. The result must be released with `ohmydb_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_query(db: *mut OhMyDb, sql: *const c_char) -> *mut c_char {
+    guarded(ptr::null_mut(), || {
+        let found = handle(db).and_then(|OhMyDb { runtime, db }| {
+            let sql = arg("sql", sql)?;
+            let records = runtime.block_on(db.query(sql).run(db))?;
+            Ok(Value::Array(records))
+        });
+
+        to_c_string(found)
+    })
+}
+
+/// Inserts a record, given as a JSON document, returning the stored record (with its generated id, if any).
+///
+/// # Safety
+///
+/// `db` must be a live handle, `table` and `record` nul-terminated strings. The result must be released with `ohmydb_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_insert(
+    db: *mut OhMyDb,
+    table: *const c_char,
+    record: *const c_char,
+) -> *mut c_char {
+    guarded(ptr::null_mut(), || {
+        let inserted = handle(db).and_then(|OhMyDb { runtime, db }| {
+            let (table, record) = (arg("table", table)?, json_arg("record", record)?);
+            let records = runtime.block_on(db.insert(table, &record).run(db))?;
+            Ok(records.into_iter().next().unwrap_or(Value::Null))
+        });
+
+        to_c_string(inserted)
+    })
+}
+
+/// Replaces the record with the same id as `record`, given as a JSON document, returning the stored record.
+///
+/// # Safety
+///
+/// `db` must be a live handle, `table` and `record` nul-terminated strings. The result must be released with `ohmydb_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_update(
+    db: *mut OhMyDb,
+    table: *const c_char,
+    record: *const c_char,
+) -> *mut c_char {
+    guarded(ptr::null_mut(), || {
+        let updated = handle(db).and_then(|OhMyDb { runtime, db }| {
+            let (table, record) = (arg("table", table)?, json_arg("record", record)?);
+            let records = runtime.block_on(db.update(table, &record).run(db))?;
+            Ok(records.into_iter().next().unwrap_or(Value::Null))
+        });
+
+        to_c_string(updated)
+    })
+}
+
+/// Deletes the record of a table with the provided id.
+///
+/// Returns `1` if a record was deleted, `0` if the table has none with this id, or `-1` on failure.
+///
+/// # Safety
+///
+/// `db` must be a live handle, `table` and `id` nul-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_delete(
+    db: *mut OhMyDb,
+    table: *const c_char,
+    id: *const c_char,
+) -> c_int {
+    guarded(-1, || {
+        let deleted = handle(db).and_then(|OhMyDb { runtime, db }| {
+            let (table, id) = (arg("table", table)?, arg("id", id)?);
+            let deleted = runtime.block_on(db.delete_by_id(table, id))?;
+            Ok(usize::from(deleted))
+        });
+
+        to_c_int(deleted)
+    })
+}
+
+/// Returns the message of the last error of the calling thread, or `NULL` if there was none.
+///
+/// The string is owned by the library and stays valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn ohmydb_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Releases a string returned by the library.
+///
+/// # Safety
+///
+/// `s` must be `NULL` or a string returned by this library, and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ohmydb_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
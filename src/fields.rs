@@ -0,0 +1,53 @@
+use crate::error::OhMyDbError;
+
+/// A struct knowing the keys its fields are stored under, implemented by the structs `define_struct_from!` generates.
+///
+/// Queries given the struct with `Query::entity` translate the Rust field names passed to `where_`, `select`, ...
+/// to the stored keys, so `#[serde(rename)]` fields can be addressed by their Rust name.
+pub trait Entity {
+    /// The `(Rust name, stored key)` pairs of the fields of the struct, in declaration order.
+    const FIELDS: &'static [(&'static str, &'static str)];
+}
+
+/// Returns the stored key of a Rust field name in `fields`, or `None` if none of the fields has this name.
+pub(crate) fn stored_key(fields: &[(&str, &'static str)], name: &str) -> Option<&'static str> {
+    fields
+        .iter()
+        .find(|(rust, _)| *rust == name)
+        .map(|(_, stored)| *stored)
+}
+
+/// Translates the first key of a (possibly nested, dot-separated) field chain with `fields`,
+/// keeping it unchanged if no field has this Rust name, e.g. because it is already a stored key.
+pub(crate) fn translate(fields: &[(&str, &'static str)], field: &str) -> String {
+    let (head, rest) = match field.split_once('.') {
+        Some((head, rest)) => (head, Some(rest)),
+        None => (field, None),
+    };
+
+    match (stored_key(fields, head), rest) {
+        (Some(key), Some(rest)) => format!("{}.{}", key, rest),
+        (Some(key), None) => key.to_string(),
+        (None, _) => field.to_string(),
+    }
+}
+
+/// Translates the first key of a (possibly nested, dot-separated) field chain from the name of a field of `T`
+/// to the key it is stored under, see `JsonDB::resolve_field`.
+pub(crate) fn resolve<T>(field: &str) -> Result<String, OhMyDbError>
+where
+    T: Entity,
+{
+    let head = field.split_once('.').map_or(field, |(head, _)| head);
+    let known = T::FIELDS
+        .iter()
+        .any(|(rust, stored)| *rust == head || *stored == head);
+    if !known {
+        return Err(OhMyDbError::InvalidField {
+            field: field.to_string(),
+            reason: "the type has no such field".to_string(),
+        });
+    }
+
+    Ok(translate(T::FIELDS, field))
+}
@@ -0,0 +1,126 @@
+use crate::types::{Comparator, Field};
+use serde_json::{Map, Value};
+
+/// Filter conditions in disjunctive normal form: OR of AND groups of `(field, comparator)` conditions.
+pub(crate) type Dnf = Vec<Vec<(Field, Comparator)>>;
+
+/// Compiles a Mongo-style filter document into filter conditions.
+///
+/// Each key of the document is a (possibly nested, dot-separated) field, all of them having to match.
+/// A plain value requires the field to equal it, while an object of operators (`$eq`, `$ne`, `$gt`, `$gte`,
+/// `$lt`, `$lte`, `$in`, `$nin`, `$exists`, `$regex`, `$not`) applies each of them. The top-level `$and` and
+/// `$or` keys combine arrays of sub-documents.
+pub(crate) fn compile(doc: &Value) -> Result<Dnf, String> {
+    let Value::Object(doc) = doc else {
+        return Err("the filter document must be an object".to_string());
+    };
+
+    doc.iter().try_fold(vec![Vec::new()], |dnf, (key, value)| {
+        let condition = match key.as_str() {
+            "$and" => sub_documents(key, value)?
+                .iter()
+                .try_fold(vec![Vec::new()], |dnf, doc| {
+                    Ok::<_, String>(and(dnf, compile(doc)?))
+                })?,
+            "$or" => sub_documents(key, value)?
+                .iter()
+                .map(compile)
+                .collect::<Result<Vec<Dnf>, String>>()?
+                .concat(),
+            key if key.starts_with('$') => return Err(format!("unknown operator {}", key)),
+            key => vec![field_conditions(key, value)?],
+        };
+
+        Ok(and(dnf, condition))
+    })
+}
+
+/// Combines two sets of filter conditions with AND semantics.
+pub(crate) fn and(left: Dnf, right: Dnf) -> Dnf {
+    left.iter()
+        .flat_map(|l| right.iter().map(move |r| [l.clone(), r.clone()].concat()))
+        .collect()
+}
+
+/// Returns the sub-documents of an `$and` / `$or` operator.
+fn sub_documents<'a>(operator: &str, value: &'a Value) -> Result<&'a Vec<Value>, String> {
+    match value {
+        Value::Array(docs) if !docs.is_empty() => Ok(docs),
+        _ => Err(format!(
+            "{} expects a non-empty array of documents",
+            operator
+        )),
+    }
+}
+
+/// Compiles the conditions a document places on a single field.
+fn field_conditions(field: &str, value: &Value) -> Result<Vec<(Field, Comparator)>, String> {
+    let comparators = match value {
+        Value::Object(ops) if ops.keys().any(|k| k.starts_with('$')) => operators(ops)?,
+        value => vec![Comparator::Equals(value.clone())],
+    };
+
+    Ok(comparators
+        .into_iter()
+        .map(|comparator| (Field::Key(field.to_string()), comparator))
+        .collect())
+}
+
+/// Compiles an object of operators into comparators, all of them having to match.
+fn operators(ops: &Map<String, Value>) -> Result<Vec<Comparator>, String> {
+    ops.iter().map(|(op, arg)| operator(op, arg)).collect()
+}
+
+fn operator(op: &str, arg: &Value) -> Result<Comparator, String> {
+    let number = || {
+        arg.as_number()
+            .cloned()
+            .ok_or_else(|| format!("{} expects a number", op))
+    };
+    let values = || match arg {
+        Value::Array(values) => Ok(values.clone()),
+        _ => Err(format!("{} expects an array", op)),
+    };
+
+    Ok(match op {
+        "$eq" => Comparator::Equals(arg.clone()),
+        "$ne" => Comparator::NotEquals(arg.clone()),
+        "$gt" => Comparator::GreaterThan(number()?),
+        "$gte" => Comparator::GreaterThanOrEqual(number()?),
+        "$lt" => Comparator::LessThan(number()?),
+        "$lte" => Comparator::LessThanOrEqual(number()?),
+        "$in" => Comparator::In(values()?),
+        "$nin" => Comparator::NotIn(values()?),
+        "$exists" => match arg.as_bool() {
+            Some(true) => Comparator::IsSet,
+            Some(false) => Comparator::IsNull,
+            None => return Err("$exists expects a boolean".to_string()),
+        },
+        "$regex" => regex(arg)?,
+        "$not" => match arg {
+            Value::Object(ops) => {
+                let mut comparators = operators(ops)?;
+                match comparators.len() {
+                    1 => Comparator::Not(Box::new(comparators.remove(0))),
+                    _ => return Err("$not expects exactly one operator".to_string()),
+                }
+            }
+            _ => return Err("$not expects an object of operators".to_string()),
+        },
+        op => return Err(format!("unknown operator {}", op)),
+    })
+}
+
+#[cfg(feature = "regex")]
+fn regex(arg: &Value) -> Result<Comparator, String> {
+    let pattern = arg.as_str().ok_or("$regex expects a string")?;
+
+    regex::Regex::new(pattern)
+        .map(|regex| Comparator::Matches(crate::types::Pattern(regex)))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "regex"))]
+fn regex(_: &Value) -> Result<Comparator, String> {
+    Err("$regex requires the regex feature".to_string())
+}
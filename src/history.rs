@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+/// The outcome of an executed operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OperationOutcome {
+    /// The operation succeeded and returned `records` records.
+    Success { records: usize },
+    /// The operation failed with the given error message.
+    Failure(String),
+}
+
+/// A runners pipeline executed by `JsonDB`, as kept by the operation history.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operation {
+    /// The runners pipeline of the operation, as built with the fluent API.
+    pub pipeline: String,
+    /// When the operation finished.
+    pub at: SystemTime,
+    /// Whether the operation succeeded.
+    pub outcome: OperationOutcome,
+}
+
+/// A ring buffer of the last executed operations.
+#[derive(Clone, Debug)]
+pub(crate) struct OperationHistory {
+    capacity: usize,
+    entries: VecDeque<Operation>,
+}
+
+impl OperationHistory {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub(crate) fn record(&mut self, pipeline: &str, at: SystemTime, outcome: OperationOutcome) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(Operation {
+            pipeline: pipeline.to_string(),
+            at,
+            outcome,
+        });
+    }
+
+    pub(crate) fn entries(&self) -> Vec<Operation> {
+        self.entries.iter().cloned().collect()
+    }
+}
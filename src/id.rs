@@ -0,0 +1,148 @@
+use crate::utils::{get_nested_entry, get_nested_ref};
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A strategy for generating the `id` of records inserted without one.
+///
+/// Generators are configured per table with `TableOptions::id_generator`, so each table can keep the
+/// identifier convention the application already uses (ULID, KSUID, snowflake, ...).
+pub trait IdGenerator: Debug + Send + Sync {
+    /// Returns a new, unique id.
+    fn generate(&self) -> String;
+}
+
+/// Returns whether a record needs an id: it is an object whose (possibly nested, dot-separated) `key` field
+/// is missing, `null` or an empty string.
+pub(crate) fn needs_id(item: &Value, key: &str) -> bool {
+    match get_nested_ref(item, key) {
+        None | Some(Value::Null) => item.is_object(),
+        Some(Value::String(id)) => id.is_empty(),
+        Some(_) => false,
+    }
+}
+
+/// Assigns an id produced by `generator` to the `key` field of a record that needs one.
+pub(crate) fn assign_id(mut item: Value, key: &str, generator: &dyn IdGenerator) -> Value {
+    if needs_id(&item, key) {
+        if let Some(id) = get_nested_entry(&mut item, key) {
+            *id = Value::String(generator.generate());
+        }
+    }
+
+    item
+}
+
+/// Prefixes the ids produced by another generator, e.g. `usr_…` for a `users` table.
+#[derive(Debug)]
+pub struct Prefixed<G> {
+    prefix: String,
+    inner: G,
+}
+
+impl<G> Prefixed<G>
+where
+    G: IdGenerator,
+{
+    /// Creates a new generator prefixing every id produced by `inner` with `prefix`.
+    pub fn new(prefix: &str, inner: G) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            inner,
+        }
+    }
+}
+
+impl<G> IdGenerator for Prefixed<G>
+where
+    G: IdGenerator,
+{
+    fn generate(&self) -> String {
+        format!("{}{}", self.prefix, self.inner.generate())
+    }
+}
+
+/// Generates time-ordered, 64-bit snowflake ids.
+///
+/// Each id is made of the milliseconds since the Unix epoch (42 bits), a worker number (10 bits)
+/// and a per-millisecond sequence (12 bits), so ids sort by creation time and distinct workers
+/// never collide.
+#[derive(Debug, Default)]
+pub struct Snowflake {
+    worker: u64,
+    state: Mutex<(u64, u64)>,
+}
+
+impl Snowflake {
+    /// Creates a new generator for the given worker number (only the lower 10 bits are used).
+    pub fn new(worker: u16) -> Self {
+        Self {
+            worker: u64::from(worker) & 0x3ff,
+            state: Mutex::new((0, 0)),
+        }
+    }
+}
+
+impl IdGenerator for Snowflake {
+    fn generate(&self) -> String {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (last, sequence) = *state;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+
+        // Never go back in time, and borrow the next millisecond once the sequence is exhausted.
+        let (millis, sequence) = match now.max(last) {
+            millis if millis == last && sequence >= 0xfff => (millis + 1, 0),
+            millis if millis == last => (millis, sequence + 1),
+            millis => (millis, 0),
+        };
+        *state = (millis, sequence);
+
+        ((millis << 22) | (self.worker << 12) | sequence).to_string()
+    }
+}
+
+/// Generates random (version 4) UUIDs, e.g. `0b7f2b0e-5c1a-4d2e-9f43-7a1c2e6b8d90`.
+///
+/// The random bits come from the randomly keyed hasher of the standard library, mixed with a counter and the time,
+/// so ids are unique without a dependency on a random number generator, but not suited to secrets.
+#[derive(Debug, Default)]
+pub struct Uuid {
+    counter: AtomicU64,
+}
+
+impl Uuid {
+    /// Returns 64 random bits.
+    fn random_u64(&self) -> u64 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(self.counter.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos()),
+        );
+        hasher.finish()
+    }
+}
+
+impl IdGenerator for Uuid {
+    fn generate(&self) -> String {
+        let bits = (u128::from(self.random_u64()) << 64) | u128::from(self.random_u64());
+        // Set the version (4) and the variant (RFC 4122) bits.
+        let bits = (bits & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+
+        format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            bits >> 96,
+            (bits >> 80) & 0xffff,
+            (bits >> 64) & 0xffff,
+            (bits >> 48) & 0xffff,
+            bits & 0xffff_ffff_ffff
+        )
+    }
+}
@@ -0,0 +1,214 @@
+use crate::check::Check;
+use crate::options::{Nulls, Relation};
+use crate::utils::get_nested_ref;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+/// A single problem found by `JsonDB::integrity_report`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IntegrityIssue {
+    /// A record has no usable id, so it can't be updated or referenced.
+    MissingId { table: String, record: Value },
+    /// Several records of the same table share an id.
+    DuplicateId {
+        table: String,
+        id: String,
+        count: usize,
+    },
+    /// A record references, through a relation declared with `TableOptions::reference`, a record that doesn't exist.
+    DanglingReference {
+        table: String,
+        id: Option<String>,
+        field: String,
+        referenced_table: String,
+        value: Value,
+    },
+    /// A record doesn't satisfy a check constraint of its table, e.g. one added after it was written.
+    CheckFailure {
+        table: String,
+        id: Option<String>,
+        check: String,
+    },
+    /// Several records of a table hold equal values for the fields of a unique constraint,
+    /// e.g. one added after they were written.
+    UniqueViolation {
+        table: String,
+        fields: Vec<String>,
+        values: Vec<Value>,
+        count: usize,
+    },
+}
+
+/// A non-mutating diagnostic of the database content, listing every integrity problem found.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IntegrityReport {
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Returns `true` if no issue was found.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Checks the records of a single table, `key` returning the id of a record.
+    pub(crate) fn check_table<F>(&mut self, table: &str, records: &HashSet<Value>, key: F)
+    where
+        F: Fn(&Value) -> Option<String>,
+    {
+        let mut ids: HashMap<String, usize> = HashMap::new();
+
+        for record in records {
+            match key(record) {
+                Some(id) => *ids.entry(id).or_default() += 1,
+                None => self.issues.push(IntegrityIssue::MissingId {
+                    table: table.to_string(),
+                    record: record.clone(),
+                }),
+            }
+        }
+
+        let mut duplicates: Vec<(String, usize)> =
+            ids.into_iter().filter(|(_, count)| *count > 1).collect();
+        duplicates.sort();
+
+        self.issues.extend(
+            duplicates
+                .into_iter()
+                .map(|(id, count)| IntegrityIssue::DuplicateId {
+                    table: table.to_string(),
+                    id,
+                    count,
+                }),
+        );
+    }
+
+    /// Checks the user data of the records of a table against its check constraints, `key` returning the id of a record.
+    pub(crate) fn check_constraints<F>(
+        &mut self,
+        table: &str,
+        data: &[&Value],
+        checks: &[Check],
+        key: F,
+    ) where
+        F: Fn(&Value) -> Option<String>,
+    {
+        let mut failures: Vec<(Option<String>, String)> = data
+            .iter()
+            .flat_map(|data| {
+                checks
+                    .iter()
+                    .filter(|check| !check.holds(data))
+                    .map(|check| (key(data), check.expression().to_string()))
+            })
+            .collect();
+        failures.sort();
+
+        self.issues.extend(
+            failures
+                .into_iter()
+                .map(|(id, check)| IntegrityIssue::CheckFailure {
+                    table: table.to_string(),
+                    id,
+                    check,
+                }),
+        );
+    }
+
+    /// Checks the user data of the records of a table against one of its unique constraints.
+    pub(crate) fn check_unique(
+        &mut self,
+        table: &str,
+        data: &[&Value],
+        fields: &[String],
+        nulls: Nulls,
+    ) {
+        let mut keys: HashMap<Vec<Value>, usize> = HashMap::new();
+
+        for data in data {
+            let key: Vec<Value> = fields
+                .iter()
+                .map(|field| get_nested_ref(data, field).map_or(Value::Null, canonical))
+                .collect();
+            if nulls == Nulls::Distinct && key.iter().any(Value::is_null) {
+                continue;
+            }
+            *keys.entry(key).or_default() += 1;
+        }
+
+        let mut violations: Vec<(Vec<Value>, usize)> =
+            keys.into_iter().filter(|(_, count)| *count > 1).collect();
+        violations.sort_by_key(|(values, _)| Value::from(values.clone()).to_string());
+
+        self.issues
+            .extend(violations.into_iter().map(|(values, count)| {
+                IntegrityIssue::UniqueViolation {
+                    table: table.to_string(),
+                    fields: fields.to_vec(),
+                    values,
+                    count,
+                }
+            }));
+    }
+
+    /// Checks that the values of `field` in the user data of the records of a table, or the items of arrays
+    /// held there, exist in the `referenced` user data of the table the relation points to.
+    pub(crate) fn check_references<F>(
+        &mut self,
+        table: &str,
+        data: &[&Value],
+        field: &str,
+        relation: &Relation,
+        referenced: &[&Value],
+        key: F,
+    ) where
+        F: Fn(&Value) -> Option<String>,
+    {
+        let existing: HashSet<Value> = referenced
+            .iter()
+            .filter_map(|data| get_nested_ref(data, relation.field()))
+            .map(canonical)
+            .collect();
+        let dangling = |value: &&Value| !value.is_null() && !existing.contains(&canonical(value));
+
+        let mut references: Vec<(Option<String>, Value)> = Vec::new();
+        for data in data {
+            let values = match get_nested_ref(data, field) {
+                Some(Value::Array(items)) => items.iter().collect(),
+                Some(value) => vec![value],
+                None => Vec::new(),
+            };
+            references.extend(
+                values
+                    .into_iter()
+                    .filter(dangling)
+                    .map(|value| (key(data), value.clone())),
+            );
+        }
+        references.sort_by_key(|(id, value)| (id.clone(), value.to_string()));
+
+        self.issues
+            .extend(
+                references
+                    .into_iter()
+                    .map(|(id, value)| IntegrityIssue::DanglingReference {
+                        table: table.to_string(),
+                        id,
+                        field: field.to_string(),
+                        referenced_table: relation.table().to_string(),
+                        value,
+                    }),
+            );
+    }
+}
+
+/// Returns a value to compare records by, integral floats being turned into integers, so `1.0` matches `1`
+/// like queries compare them.
+fn canonical(value: &Value) -> Value {
+    match value.as_f64() {
+        Some(n) if value.is_f64() && n.fract() == 0.0 && n.abs() < i64::MAX as f64 => {
+            Value::from(n as i64)
+        }
+        _ => value.clone(),
+    }
+}
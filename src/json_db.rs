@@ -1,14 +1,179 @@
-use crate::get_nested_value;
-use crate::types::{Comparator, MethodName, Runner};
+use crate::archive::{self, Archive, ARCHIVE_FORMAT};
+use crate::backup::{BackupChain, Increment};
+use crate::batch::BatchReport;
+use crate::bench::BenchReport;
+use crate::check::Check;
+use crate::clock::{Clock, SystemClock};
+use crate::config::Config;
+use crate::diff::{self, DatabaseDiff};
+use crate::doctor::{DoctorFinding, DoctorReport};
+use crate::envelope::{self, unix_millis};
+use crate::error::OhMyDbError;
+use crate::expiry;
+use crate::explain::Explain;
+use crate::fields::{self, Entity};
+use crate::filter_doc;
+use crate::history::{Operation, OperationHistory, OperationOutcome};
+use crate::id::{self, IdGenerator, Snowflake, Uuid};
+use crate::integrity::IntegrityReport;
+use crate::merge::{deep_merge, MergeOptions};
+use crate::options::{Nulls, OnDelete, Quota, Relation, TableOptions};
+use crate::outbox::{Outbox, OutboxEvent};
+use crate::outcome::RunOutcome;
+use crate::progress::{Progress, ProgressCallback};
+use crate::query::Query;
+use crate::queue::JobQueue;
+use crate::remote::{RemoteTier, RemoteWrites};
+use crate::scrub::ScrubPolicy;
+use crate::security::Context;
+use crate::seed::{self, OnConflict, SeedReport};
+use crate::sink::{BackupSink, Retention};
+use crate::slow_log::{SlowQuery, SlowQueryLog};
+use crate::sql;
+use crate::stats::{CachedStats, FieldStats, IndexStats};
+use crate::stream::RecordStream;
+use crate::tiering::{self, COLD_TABLE};
+#[cfg(feature = "regex")]
+use crate::types::Pattern;
+use crate::types::{
+    Comparator, CustomComparator, Field, Join, MethodName, Order, Predicate, Runner,
+};
+use crate::update::UpdateOp;
+use crate::utils::{
+    compare_numbers, compare_values, get_nested_entry, get_nested_mut, get_nested_ref, like_match,
+    project, record_id, slugify, soundex, values_equal, wrap_record,
+};
 use colored::*;
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{json, Number, Value};
+use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet, VecDeque};
-use std::io::{self, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::sync::CancellationToken;
+
+/// The number of executed operations kept by the operation history unless configured otherwise.
+const DEFAULT_HISTORY_CAPACITY: usize = 32;
+
+/// The reserved table persisting the idempotency keys of inserts, with the record each one inserted.
+const IDEMPOTENCY_TABLE: &str = "_idempotency";
+
+/// A callback receiving the name and new value of every configuration document that changes.
+type ConfigListener = Arc<dyn Fn(&str, &Value) + Send + Sync>;
+
+/// The reserved table persisting the last value of the auto-increment id sequence of each table.
+const SEQUENCES_TABLE: &str = "_sequences";
+
+/// The reserved table persisting the pending events of the outbox.
+const OUTBOX_TABLE: &str = "_outbox";
+
+/// The operation collected from the runners queue, ready to be executed by `run` or another terminal.
+#[derive(Default)]
+struct Plan {
+    pipeline: String,
+    method: Option<MethodName>,
+    /// The filter conditions in disjunctive normal form: a record matches if every `(field, comparator)`
+    /// condition of at least one group matches it.
+    filters: Vec<Vec<(Field, Comparator)>>,
+    order_by: Vec<(String, Order)>,
+    skip: Option<usize>,
+    limit: Option<usize>,
+    select: Option<Vec<String>>,
+    distinct: Option<String>,
+    /// The changes applied to every selected record by `update_where`.
+    operators: Vec<UpdateOp>,
+    joins: Vec<Join>,
+    /// The referencing fields replaced with the records they reference, with their relation.
+    populate: Vec<(String, Relation)>,
+    error: Option<OhMyDbError>,
+    cancellation: Option<CancellationToken>,
+    context: Context,
+    /// Whether the target table stores its records in envelopes.
+    envelope: bool,
+    /// Whether deletes only mark the records of the target table as deleted.
+    soft_delete: bool,
+    with_deleted: bool,
+    with_meta: bool,
+    idempotency_key: Option<String>,
+    /// When the record inserted by the plan expires, in milliseconds since the Unix epoch.
+    expires_at: Option<u64>,
+    /// Whether the tables the plan reads are reloaded from the database file first.
+    from_disk: bool,
+    events: Vec<Value>,
+}
+
+impl Plan {
+    /// Returns whether the caller cancelled the operation.
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Returns the user data of a stored record.
+    fn data<'a>(&self, record: &'a Value) -> &'a Value {
+        match self.envelope {
+            true => envelope::data(record),
+            false => record,
+        }
+    }
+
+    /// Returns whether the plan selects a stored record as far as soft deletes go: `restore` and `purge` only select
+    /// the soft-deleted records, other operations only the live ones unless `with_deleted` was requested.
+    fn selects_deleted_state(&self, record: &Value) -> bool {
+        match &self.method {
+            Some(MethodName::Restore(_) | MethodName::Purge(_)) => {
+                self.soft_delete && is_soft_deleted(record)
+            }
+            _ => !self.soft_delete || self.with_deleted || !is_soft_deleted(record),
+        }
+    }
+
+    /// Returns the value at the (possibly nested, dot-separated) `field` of the user data of a stored record.
+    fn field<'a>(&self, record: &'a Value, field: &str) -> Option<&'a Value> {
+        get_nested_ref(self.data(record), field)
+    }
+
+    /// Returns the error found while building the plan, if any.
+    fn check(&self) -> Result<(), OhMyDbError> {
+        match &self.error {
+            None => Ok(()),
+            Some(e) => Err(match e {
+                OhMyDbError::InvalidPattern { pattern, reason } => OhMyDbError::InvalidPattern {
+                    pattern: pattern.clone(),
+                    reason: reason.clone(),
+                },
+                OhMyDbError::InvalidParameter { name, reason } => OhMyDbError::InvalidParameter {
+                    name: name.clone(),
+                    reason: reason.clone(),
+                },
+                OhMyDbError::UnknownComparator(name) => {
+                    OhMyDbError::UnknownComparator(name.clone())
+                }
+                OhMyDbError::InvalidField { field, reason } => OhMyDbError::InvalidField {
+                    field: field.clone(),
+                    reason: reason.clone(),
+                },
+                OhMyDbError::InvalidRecord(reason) => OhMyDbError::InvalidRecord(reason.clone()),
+                e => OhMyDbError::InvalidRecord(e.to_string()),
+            }),
+        }
+    }
+}
+
+/// The changes a delete makes to the records referencing the deleted ones, following the declared relations.
+#[derive(Default)]
+struct Cascade {
+    /// The stored records to delete, per table, including the ones the delete selected.
+    deleted: HashMap<String, HashSet<Value>>,
+    /// The stored records whose references are removed, per table, with their new version.
+    detached: HashMap<String, HashMap<Value, Value>>,
+}
 
 #[derive(Clone)]
 pub struct JsonDB {
@@ -16,7 +181,25 @@ pub struct JsonDB {
     path: PathBuf,
     _file: Arc<File>,
     value: Arc<HashMap<String, HashSet<Value>>>,
-    runners: Arc<VecDeque<Runner>>,
+    options: HashMap<String, TableOptions>,
+    stats: HashMap<(String, String), CachedStats>,
+    slow_log: Option<SlowQueryLog>,
+    history: OperationHistory,
+    progress: Option<ProgressCallback>,
+    clock: Arc<dyn Clock>,
+    comparators: HashMap<String, CustomComparator>,
+    checks: HashMap<String, Vec<Check>>,
+    internal_ids: Arc<Snowflake>,
+    config_listener: Option<ConfigListener>,
+    /// When each table was last accessed by a query, to detect the tables `freeze_idle` moves to the cold tier.
+    accessed: HashMap<String, SystemTime>,
+    remotes: HashMap<String, Arc<dyn RemoteTier>>,
+    /// The field identifying the records of each table configured with `set_primary_key`, `"id"` otherwise.
+    primary_keys: HashMap<String, String>,
+    /// The default document of each table configured with `set_template`, merged under new inserts.
+    templates: HashMap<String, Value>,
+    /// Whether operations print what they do, see `set_logging`.
+    logging: bool,
 }
 
 impl JsonDB {
@@ -24,28 +207,31 @@ impl JsonDB {
     ///
     /// This function reads the contents of the `db.json` file in the current directory,
     /// or creates a new file if it doesn't exist. The file contents are deserialized into a `HashMap` and stored in the `JsonDB` struct.
-    /// The `JsonDB` struct also initializes an empty `HashSet` for table names, an `Arc`-wrapped `File` instance, and an empty `VecDeque` for runners.
+    /// The `JsonDB` struct also initializes an empty `HashSet` for table names and an `Arc`-wrapped `File` instance.
     ///
     /// # Returns
     ///
     /// A `Result` containing a new `JsonDB` instance if the operation is successful,
-    /// or an `io::Error` if there is a problem reading or creating the file.
-    pub async fn new(db_name: &str) -> Result<Self, io::Error> {
-        let db_path;
-
-        if db_name.is_empty() {
-            db_path = format!("ohmydb.json")
+    /// or an `OhMyDbError` if there is a problem reading, creating or parsing the file.
+    pub async fn new(db_name: &str) -> Result<Self, OhMyDbError> {
+        let db_path = if db_name.is_empty() {
+            "ohmydb.json".to_string()
         } else {
-            db_path = format!("{}.json", db_name.to_lowercase().trim())
-        }
+            format!("{}.json", db_name.to_lowercase().trim())
+        };
 
         let dir_path = std::env::current_dir()?;
-        let file_path = dir_path.join(db_path);
 
+        Self::open(dir_path.join(db_path)).await
+    }
+
+    /// Opens (or creates) the database file at `file_path`, see `new`.
+    async fn open(file_path: PathBuf) -> Result<Self, OhMyDbError> {
         let file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(&file_path)
             .await?;
 
@@ -57,7 +243,7 @@ impl JsonDB {
         let value = if content.is_empty() {
             HashMap::new()
         } else {
-            serde_json::from_str(&content).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?
+            serde_json::from_str(&content)?
         };
 
         let db = Self {
@@ -65,7 +251,21 @@ impl JsonDB {
             path: file_path,
             _file: Arc::new(file),
             value: Arc::new(value),
-            runners: Arc::new(VecDeque::new()),
+            options: HashMap::new(),
+            stats: HashMap::new(),
+            slow_log: None,
+            history: OperationHistory::new(DEFAULT_HISTORY_CAPACITY),
+            progress: None,
+            clock: Arc::new(SystemClock),
+            comparators: HashMap::new(),
+            checks: HashMap::new(),
+            internal_ids: Arc::new(Snowflake::default()),
+            config_listener: None,
+            accessed: HashMap::new(),
+            remotes: HashMap::new(),
+            primary_keys: HashMap::new(),
+            templates: HashMap::new(),
+            logging: true,
         };
 
         Ok(db)
@@ -80,12 +280,11 @@ impl JsonDB {
 
         let file = OpenOptions::new().read(true).open(&self.path).await.ok();
 
-        let tables = if file.is_some() {
-            file.unwrap().read_to_string(&mut content).await.unwrap();
+        let tables = if let Some(mut file) = file {
+            file.read_to_string(&mut content).await.unwrap_or_default();
 
-            let tables_hash: HashMap<String, HashSet<Value>> = serde_json::from_str(&content)
-                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
-                .unwrap_or_default();
+            let tables_hash: HashMap<String, HashSet<Value>> =
+                serde_json::from_str(&content).unwrap_or_default();
 
             tables_hash.into_keys().collect::<Vec<String>>()
         } else {
@@ -118,24 +317,26 @@ impl JsonDB {
     /// # Returns
     ///
     /// A `Result` containing a mutable reference to the `HashSet<T>` for the specified table if it exists,
-    /// or an `io::Error` if the table is not found.
-    fn get_table_mut(&mut self, table_name: &str) -> Result<&mut HashSet<Value>, io::Error> {
+    /// or an `OhMyDbError::TableNotFound` if the table is not found.
+    fn get_table_mut(&mut self, table_name: &str) -> Result<&mut HashSet<Value>, OhMyDbError> {
+        let logging = self.logging;
+        self.invalidate_stats(table_name);
+
         let table = Arc::make_mut(&mut self.value)
             .get_mut(table_name)
             .ok_or_else(|| {
-                println!(
-                    "{} {} \"{}\" {}\n\t\t{} {}\n",
-                    "(get_table_mut)".bright_cyan().bold(),
-                    "✗ Retrieving".bright_red().bold(),
-                    table_name.to_string().bright_red().bold(),
-                    "table failed!".bright_red().bold(),
-                    "✔".bright_green().bold().blink(),
-                    "Try to add a table first!".bright_green().bold()
-                );
-                io::Error::new(
-                    ErrorKind::NotFound,
-                    format!("Table '{}' not found", table_name),
-                )
+                if logging {
+                    println!(
+                        "{} {} \"{}\" {}\n\t\t{} {}\n",
+                        "(get_table_mut)".bright_cyan().bold(),
+                        "✗ Retrieving".bright_red().bold(),
+                        table_name.to_string().bright_red().bold(),
+                        "table failed!".bright_red().bold(),
+                        "✔".bright_green().bold().blink(),
+                        "Try to add a table first!".bright_green().bold()
+                    );
+                }
+                OhMyDbError::TableNotFound(table_name.to_string())
             })?;
 
         Ok(table)
@@ -149,18 +350,13 @@ impl JsonDB {
     ///
     /// # Returns
     ///
-    /// A `Result` containing a `Vec<T>` if the table is found, or an `io::Error` if the table is not found.
-    pub fn get_table_vec(&mut self, table_name: &str) -> Result<Vec<Value>, io::Error> {
-        let hash_table = (*self.value)
-            .clone()
+    /// A `Result` containing a `Vec<T>` if the table is found, or an `OhMyDbError::TableNotFound` if the table is not found.
+    pub fn get_table_vec(&mut self, table_name: &str) -> Result<Vec<Value>, OhMyDbError> {
+        let hash_table = self
+            .value
             .get(table_name)
-            .map(Clone::clone)
-            .ok_or_else(|| {
-                io::Error::new(
-                    ErrorKind::NotFound,
-                    format!("Table '{}' not found", table_name),
-                )
-            })?;
+            .cloned()
+            .ok_or_else(|| OhMyDbError::TableNotFound(table_name.to_string()))?;
 
         let table = Vec::from_iter(hash_table);
 
@@ -176,389 +372,3607 @@ impl JsonDB {
     /// # Returns
     ///
     /// A `Result` indicating whether the table was successfully added. If the table already exists, this function will return `Ok(())`.
-    pub async fn add_table(&mut self, table_name: &str) -> Result<(), io::Error> {
+    pub async fn add_table(&mut self, table_name: &str) -> Result<(), OhMyDbError> {
+        self.accessed
+            .insert(table_name.to_string(), self.clock.now());
+        let is_cold = self.cold_file(table_name).is_some();
+
         let tables_hash = Arc::make_mut(&mut self.value);
 
-        let table_already_exists = tables_hash.contains_key(table_name);
+        let table_already_exists = is_cold || tables_hash.contains_key(table_name);
 
         if !table_already_exists {
             tables_hash.insert(table_name.to_string(), HashSet::new());
-            self.tables.insert(table_name.to_string());
         }
+        self.tables.insert(table_name.to_string());
 
         self.save().await?;
 
         Ok(())
     }
 
-    /// Saves the current state of the `JsonDb` instance to the file specified by the `path` field.
+    /// Removes a table and all its records from the database, then saves the change,
+    /// e.g. `db.drop_table("todos").await`.
     ///
-    /// # Errors
+    /// Cold tables are dropped too, their file being deleted, and so is the auto-increment sequence of the table.
+    /// The configuration made in code (`set_table_options`, `set_primary_key`, ...) is kept, so a table added back
+    /// with the same name behaves the same. Records of other tables referencing the dropped ones are left as they are.
     ///
-    /// This function will return an error if there is a problem writing the JSON data to the file.
-    pub async fn save(&self) -> Result<(), io::Error> {
-        let json = serde_json::to_string_pretty(&*self.value)
-            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table to drop.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether the table existed, or an `OhMyDbError::TableNotFound` if the name is
+    /// the one of a reserved table (starting with `_`).
+    pub async fn drop_table(&mut self, table_name: &str) -> Result<bool, OhMyDbError> {
+        if table_name.starts_with('_') {
+            return Err(OhMyDbError::TableNotFound(table_name.to_string()));
+        }
 
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&self.path)
-            .await?;
+        let cold_file = self.cold_file(table_name);
+        let existed = self.tables.remove(table_name)
+            | Arc::make_mut(&mut self.value).remove(table_name).is_some()
+            | cold_file.is_some();
+        if !existed {
+            return Ok(false);
+        }
 
-        file.write_all(json.as_bytes()).await?;
-        file.flush().await?;
+        self.stats.retain(|(table, _), _| table != table_name);
+        self.accessed.remove(table_name);
+        if let Some(sequences) = Arc::make_mut(&mut self.value).get_mut(SEQUENCES_TABLE) {
+            sequences.retain(|sequence| record_id(sequence, "id").as_deref() != Some(table_name));
+        }
+        if cold_file.is_some() {
+            self.reserved_table_mut(COLD_TABLE)
+                .retain(|record| record_id(record, "id").as_deref() != Some(table_name));
+        }
+        self.save().await?;
 
-        Ok(())
+        // The table is gone from the database file, so a cold file left behind is only wasted space.
+        if let Some(file_name) = cold_file {
+            tokio::fs::remove_file(tiering::cold_path(&self.path, &file_name))
+                .await
+                .ok();
+        }
+
+        Ok(true)
     }
 
-    /// Inserts a new record into the JSON database table.
+    /// Sets the write options for the specified table, replacing any previously configured options.
     ///
     /// # Arguments
     ///
-    /// * `table` - The name of the table to insert the record into.
-    /// * `item` - The `T` item to insert.
+    /// * `table_name` - The name of the table to configure.
+    /// * `options` - The `TableOptions` applied to every record inserted into or updated in the table.
     ///
     /// # Returns
     ///
     /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
-    pub fn insert<T>(&mut self, table: &str, item: &T) -> &mut Self
-    where
-        T: Serialize,
-    {
-        let value = serde_json::to_value(item).unwrap();
-        Arc::make_mut(&mut self.runners).push_back(Runner::Method(MethodName::Create(
-            table.to_string(),
-            value,
-            false,
-        )));
+    pub fn set_table_options(&mut self, table_name: &str, options: TableOptions) -> &mut Self {
+        self.options.insert(table_name.to_string(), options);
         self
     }
 
-    /// Inserts a new record into the JSON database table,
-    /// or creates a table first if it does not already exists.
+    /// Sets the field identifying the records of a table, e.g. `db.set_primary_key("users", "email")` for records
+    /// without an `id` field. Tables use `"id"` unless configured otherwise.
+    ///
+    /// The key is used everywhere records are matched by id: duplicate detection on insert, `update`, `merge`
+    /// and `upsert` matching, the `*_by_id` methods, unique constraints, the integrity report and generated ids.
     ///
     /// # Arguments
     ///
-    /// * `table` - The name of the table to insert the record into.
-    /// * `item` - The `T` item to insert or update.
+    /// * `table_name` - The name of the table to configure.
+    /// * `field` - The (possibly nested, dot-separated) field holding the key of the records.
     ///
     /// # Returns
     ///
     /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
-    pub fn insert_or<T>(&mut self, table: &str, item: &T) -> &mut Self
-    where
-        T: Serialize,
-    {
-        let value = serde_json::to_value(item).unwrap();
-        Arc::make_mut(&mut self.runners).push_back(Runner::Method(MethodName::Create(
-            table.to_string(),
-            value,
-            true,
-        )));
+    pub fn set_primary_key(&mut self, table_name: &str, field: &str) -> &mut Self {
+        self.primary_keys
+            .insert(table_name.to_string(), field.to_string());
         self
     }
 
-    /// Adds a `Runner::Method(MethodName::Read)` to the end of the runners queue, indicating that the current operation is a read operation.
-    /// The returned `Self` instance contains the updated runners queue.
+    /// Registers the template document of a table, e.g. `db.set_template("todos", json!({"is_completed": false, "tags": []}))`.
+    ///
+    /// The template is deep-merged under every record inserted into the table afterwards (with `insert`, `insert_or`,
+    /// `insert_many` or an `upsert` turning into an insert), so ad-hoc inserts get consistent default fields without
+    /// a schema: fields present in the record win, nested objects are merged and every other value replaces the default.
+    /// Updates are left unchanged. Registering a template for a table replaces the previous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table to configure.
+    /// * `template` - The default document, which must be an object to have any effect.
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn find(&mut self, table: &str) -> &mut Self {
-        Arc::make_mut(&mut self.runners)
-            .push_back(Runner::Method(MethodName::Read(table.to_string())));
-
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn set_template(&mut self, table_name: &str, template: Value) -> &mut Self {
+        self.templates.insert(table_name.to_string(), template);
         self
     }
 
-    /// Adds a `Runner::Method(MethodName::Update)` to the end of the runners queue, indicating that the current operation is an update operation.
-    /// The returned `Self` instance contains the updated runners queue.
-    ///
-    /// # Returns
+    /// Returns the field identifying the records of the specified table.
+    fn primary_key(&self, table_name: &str) -> &str {
+        self.primary_keys
+            .get(table_name)
+            .map_or("id", String::as_str)
+    }
+
+    /// Returns the key of a record stored in the specified table, read from its user data.
+    fn record_key(&self, table_name: &str, record: &Value) -> Option<String> {
+        match self.is_enveloped(table_name) {
+            true => record_id(envelope::data(record), self.primary_key(table_name)),
+            false => record_id(record, self.primary_key(table_name)),
+        }
+    }
+
+    /// Applies the write options configured for the specified table to a record about to be stored.
+    fn prepare_write(&self, table_name: &str, item: Value) -> Result<Value, OhMyDbError> {
+        match self.options.get(table_name) {
+            Some(options) => options.apply(item),
+            None => Ok(item),
+        }
+    }
+
+    /// Prepares a record about to be inserted into the specified table, generating its key if the
+    /// table has an `IdGenerator` and the record has none, then applying the table's write options.
+    fn prepare_insert(&self, table_name: &str, item: Value) -> Result<Value, OhMyDbError> {
+        let Some(options) = self.options.get(table_name) else {
+            return Ok(item);
+        };
+        let mut item = options.apply(options.assign_id(item, self.primary_key(table_name)))?;
+
+        if let Some((source, field)) = options.slug_fields() {
+            let base = match get_nested_ref(&item, source) {
+                Some(Value::String(s)) if get_nested_ref(&item, field).is_none() => slugify(s),
+                _ => return Ok(item),
+            };
+            let taken: HashSet<&str> = self
+                .value
+                .get(table_name)
+                .into_iter()
+                .flatten()
+                .filter_map(|record| self.record_field(table_name, record, field)?.as_str())
+                .collect();
+            let slug = (1..)
+                .map(|n| match n {
+                    1 => base.clone(),
+                    n => format!("{}-{}", base, n),
+                })
+                .find(|slug| !taken.contains(slug.as_str()))
+                .unwrap_or(base);
+
+            if let Value::Object(obj) = &mut item {
+                obj.insert(field.to_string(), Value::String(slug));
+            }
+        }
+
+        Ok(item)
+    }
+
+    /// Applies the read side of the options configured for the specified table to a queried record.
+    fn prepare_read(&self, table_name: &str, mut item: Value) -> Value {
+        match self.options.get(table_name) {
+            Some(options) if options.is_enveloped() => {
+                options.decode(envelope::data(&item).clone())
+            }
+            options => {
+                expiry::strip(&mut item);
+                match options {
+                    Some(options) => options.decode(item),
+                    None => item,
+                }
+            }
+        }
+    }
+
+    /// Returns the value at the (possibly nested, dot-separated) `field` of the user data of a record
+    /// stored in the specified table.
+    fn record_field<'a>(
+        &self,
+        table_name: &str,
+        record: &'a Value,
+        field: &str,
+    ) -> Option<&'a Value> {
+        get_nested_ref(self.stored_data(table_name, record), field)
+    }
+
+    /// Returns the user data of a record stored in the specified table: the `data` of an envelope, or the record itself.
+    fn stored_data<'a>(&self, table_name: &str, record: &'a Value) -> &'a Value {
+        match self.is_enveloped(table_name) {
+            true => envelope::data(record),
+            false => record,
+        }
+    }
+
+    /// Returns whether a record stored in the specified table is visible to the provided security context.
+    fn is_visible(&self, table_name: &str, record: &Value, context: &Context) -> bool {
+        let Some(options) = self.options.get(table_name) else {
+            return true;
+        };
+        let data = match options.is_enveloped() {
+            true => envelope::data(record),
+            false => record,
+        };
+
+        options.policy().is_none_or(|p| p.allows(context, data))
+    }
+
+    /// Returns whether deletes only mark the records of the specified table as deleted.
+    fn is_soft_delete(&self, table_name: &str) -> bool {
+        self.options
+            .get(table_name)
+            .is_some_and(TableOptions::is_soft_delete)
+    }
+
+    /// Returns whether the specified table stores its records in envelopes.
+    fn is_enveloped(&self, table_name: &str) -> bool {
+        self.options
+            .get(table_name)
+            .is_some_and(TableOptions::is_enveloped)
+    }
+
+    /// Prepares a queried record to be returned to the caller, decoding and projecting it as the plan requests.
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn update<T>(&mut self, table: &str, item: &T) -> &mut Self
-    where
-        T: Serialize,
-    {
-        let value = serde_json::to_value(item).unwrap();
-        Arc::make_mut(&mut self.runners)
-            .push_back(Runner::Method(MethodName::Update(table.to_string(), value)));
+    /// With `with_meta`, records of enveloped tables are returned whole, with only their `data` decoded and projected.
+    fn finalize_read(&self, plan: &Plan, table_name: &str, item: Value) -> Value {
+        match item {
+            Value::Object(mut fields) if plan.envelope && plan.with_meta => {
+                let data = fields.remove(envelope::DATA).unwrap_or_default();
+                let data = match self.options.get(table_name) {
+                    Some(options) => options.decode(data),
+                    None => data,
+                };
+                fields.insert(envelope::DATA.to_string(), self.shape_read(plan, data));
+                Value::Object(fields)
+            }
+            item => {
+                let item = self.prepare_read(table_name, item);
+                self.shape_read(plan, item)
+            }
+        }
+    }
 
-        self
+    /// Projects the decoded user data of a queried record as the plan requests, then attaches its joined records.
+    fn shape_read(&self, plan: &Plan, mut data: Value) -> Value {
+        let joined: Vec<(String, Value)> = plan
+            .joins
+            .iter()
+            .map(|join| (join.key.clone(), self.joined_records(plan, join, &data)))
+            .collect();
+
+        for (field, relation) in &plan.populate {
+            if let Some(value) = get_nested_mut(&mut data, field) {
+                *value = match value.take() {
+                    Value::Null => Value::Null,
+                    Value::Array(ids) => ids
+                        .iter()
+                        .filter_map(|id| {
+                            self.referenced_records(plan, relation.table(), relation.field(), id)
+                                .next()
+                        })
+                        .collect(),
+                    id => self
+                        .referenced_records(plan, relation.table(), relation.field(), &id)
+                        .next()
+                        .unwrap_or(Value::Null),
+                };
+            }
+        }
+
+        let mut data = match &plan.select {
+            Some(fields) => project(&data, fields),
+            None => data,
+        };
+
+        if let Value::Object(object) = &mut data {
+            object.extend(joined);
+        }
+
+        data
+    }
+
+    /// Returns the decoded records of the joined table whose foreign field equals the local field of `data`,
+    /// as an array that is empty if the local field is missing or `null`.
+    fn joined_records(&self, plan: &Plan, join: &Join, data: &Value) -> Value {
+        let Some(local) = get_nested_ref(data, &join.local).filter(|value| !value.is_null()) else {
+            return Value::Array(Vec::new());
+        };
+
+        self.referenced_records(plan, &join.table, &join.foreign, local)
+            .collect()
     }
 
-    /// Adds a `Runner::Method(MethodName::Delete(c))` to the end of the runners queue,
-    /// indicating that the current operation is a delete operation.
-    /// The returned `Self` instance contains the updated runners queue.
+    /// Returns the decoded records of `table` visible to the context of the plan whose `field` equals `value`.
+    fn referenced_records<'a>(
+        &'a self,
+        plan: &'a Plan,
+        table: &'a str,
+        field: &'a str,
+        value: &'a Value,
+    ) -> impl Iterator<Item = Value> + 'a {
+        let now = self.clock.now();
+        let soft_delete = self.is_soft_delete(table);
+
+        self.value
+            .get(table)
+            .into_iter()
+            .flatten()
+            .filter(move |record| !expiry::is_expired(record, now))
+            .filter(move |record| !soft_delete || !is_soft_deleted(record))
+            .filter(move |record| {
+                self.record_field(table, record, field)
+                    .is_some_and(|other| values_equal(other, value))
+            })
+            .filter(move |record| self.is_visible(table, record, &plan.context))
+            .map(move |record| self.prepare_read(table, record.clone()))
+    }
+
+    /// Returns statistics (distinct count, min/max, null fraction) about a field of the specified table.
+    ///
+    /// Statistics are computed on first use and cached until the table is modified, then recomputed on next use.
     ///
     /// # Arguments
     ///
-    /// * `key` - The character to use for the delete operation.
+    /// * `table_name` - The name of the table to inspect.
+    /// * `field` - The (possibly nested, dot-separated) field to compute statistics for.
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn delete(&mut self, table: &str) -> &mut Self {
-        Arc::make_mut(&mut self.runners)
-            .push_back(Runner::Method(MethodName::Delete(table.to_string())));
+    /// A `Result` containing the `FieldStats` of the field, or an `OhMyDbError::TableNotFound` if the table is not found.
+    pub fn field_stats(
+        &mut self,
+        table_name: &str,
+        field: &str,
+    ) -> Result<FieldStats, OhMyDbError> {
+        let key = (table_name.to_string(), field.to_string());
 
-        self
+        if let Some(cached) = self.stats.get(&key).filter(|cached| !cached.stale) {
+            return Ok(cached.stats.clone());
+        }
+
+        let table = self
+            .value
+            .get(table_name)
+            .ok_or_else(|| OhMyDbError::TableNotFound(table_name.to_string()))?;
+        let stats = FieldStats::compute(table, field);
+
+        self.stats.insert(
+            key,
+            CachedStats {
+                stats: stats.clone(),
+                stale: false,
+            },
+        );
+
+        Ok(stats)
     }
 
-    /// Adds a `Runner::Where(field.to_string())` to the end of the runners queue, filtering the data based on the provided field.
-    /// The returned `Self` instance contains the updated runners queue.
+    /// Recomputes the cached field statistics of the specified table, e.g. after a bulk import,
+    /// so the next queries don't pay for it.
+    ///
+    /// Every field whose statistics were computed by `field_stats` is rebuilt, whether the cache entry is stale or not.
+    /// Progress is reported per field under the `"reindex"` stage.
     ///
     /// # Arguments
     ///
-    /// * `field` - The field to filter the data by.
+    /// * `table_name` - The name of the table to rebuild the statistics of.
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn where_(&mut self, field: &str) -> &mut Self {
-        Arc::make_mut(&mut self.runners).push_back(Runner::Where(field.to_string()));
+    /// A `Result` containing the number of fields rebuilt, or an `OhMyDbError::TableNotFound` if the table is not found.
+    pub async fn reindex(&mut self, table_name: &str) -> Result<usize, OhMyDbError> {
+        let value = Arc::clone(&self.value);
+        let table = value
+            .get(table_name)
+            .ok_or_else(|| OhMyDbError::TableNotFound(table_name.to_string()))?;
 
-        self
+        let mut fields: Vec<String> = self
+            .stats
+            .keys()
+            .filter(|(table, _)| table == table_name)
+            .map(|(_, field)| field.clone())
+            .collect();
+        fields.sort();
+
+        let total = fields.len();
+
+        for (processed, field) in fields.into_iter().enumerate() {
+            let stats = FieldStats::compute(table, &field);
+            self.stats.insert(
+                (table_name.to_string(), field),
+                CachedStats {
+                    stats,
+                    stale: false,
+                },
+            );
+            self.report_progress("reindex", processed + 1, total);
+            tokio::task::yield_now().await;
+        }
+
+        Ok(total)
+    }
+
+    /// Returns the health of every cached field statistics entry (record and distinct value counts,
+    /// approximate memory and staleness), ordered by table and field.
+    pub fn index_stats(&self) -> Vec<IndexStats> {
+        let mut stats: Vec<IndexStats> = self
+            .stats
+            .iter()
+            .map(|((table, field), cached)| IndexStats::new(table, field, cached))
+            .collect();
+        stats.sort_by(|a, b| (&a.table, &a.field).cmp(&(&b.table, &b.field)));
+
+        stats
     }
 
-    /// Adds a `Runner::Compare(Comparator::Equals(value.to_string()))` to the end of the runners queue, filtering the data based on the provided value.
-    /// The returned `Self` instance contains the updated runners queue.
+    /// Looks up a record of the specified table by its slug.
+    ///
+    /// The slug field is the one configured with `TableOptions::slug`, or `slug` if none is configured.
+    /// Records hidden from the anonymous `Context` by a row-level security policy are not returned.
     ///
     /// # Arguments
     ///
-    /// * `value` - The value to filter the data by.
+    /// * `table_name` - The name of the table to search.
+    /// * `slug` - The slug of the record to find.
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn equals(&mut self, value: &str) -> &mut Self {
-        Arc::make_mut(&mut self.runners)
-            .push_back(Runner::Compare(Comparator::Equals(value.to_string())));
+    /// A `Result` containing the matching record, if any, or an `OhMyDbError::TableNotFound` if the table is not found.
+    pub fn find_by_slug(&self, table_name: &str, slug: &str) -> Result<Option<Value>, OhMyDbError> {
+        let field = self
+            .options
+            .get(table_name)
+            .and_then(|options| options.slug_fields())
+            .map_or("slug", |(_, field)| field);
+        let table = self
+            .value
+            .get(table_name)
+            .ok_or_else(|| OhMyDbError::TableNotFound(table_name.to_string()))?;
 
-        self
+        Ok(table
+            .iter()
+            .find(|record| {
+                self.record_field(table_name, record, field)
+                    .and_then(Value::as_str)
+                    == Some(slug)
+            })
+            .filter(|record| self.is_visible(table_name, record, &Context::default()))
+            .map(|record| self.prepare_read(table_name, record.clone())))
     }
 
-    /// Adds a `Runner::Compare(Comparator::NotEquals(value.to_string()))` to the end of the runners queue, filtering the data based on the provided value.
-    /// The returned `Self` instance contains the updated runners queue.
+    /// Marks the cached field statistics of the specified table as stale, so they are recomputed on next use.
+    fn invalidate_stats(&mut self, table_name: &str) {
+        for ((table, _), cached) in self.stats.iter_mut() {
+            if table == table_name {
+                cached.stale = true;
+            }
+        }
+    }
+
+    /// Enables the slow-query log, replacing any previously configured one.
     ///
     /// # Arguments
     ///
-    /// * `value` - The value to filter the data by.
+    /// * `log` - The `SlowQueryLog` configuration (threshold, capacity and optional file).
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn not_equals(&mut self, value: &str) -> &mut Self {
-        Arc::make_mut(&mut self.runners)
-            .push_back(Runner::Compare(Comparator::NotEquals(value.to_string())));
-
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn set_slow_query_log(&mut self, log: SlowQueryLog) -> &mut Self {
+        self.slow_log = Some(log);
         self
     }
 
-    /// Adds a `Runner::Compare(Comparator::In(value.to_vec()))` to the end of the runners queue, filtering the data based on the provided values.
-    /// The returned `Self` instance contains the updated runners queue.
+    /// Returns the slow queries recorded so far, oldest first.
+    /// The list is empty if the slow-query log was not enabled with `set_slow_query_log`.
+    pub fn slow_queries(&self) -> Vec<SlowQuery> {
+        self.slow_log
+            .as_ref()
+            .map(SlowQueryLog::entries)
+            .unwrap_or_default()
+    }
+
+    /// Backs a table with an external system, the table acting as a local cache of it: `find_by_id` reads through
+    /// to the remote when the table has no record with the id, and every write to the table is written through to it.
+    ///
+    /// Writes are sent to the remote once every check passed and before the database file is saved. If the remote
+    /// fails, the write is rolled back and its error returned, so the cache never holds changes the remote rejected.
+    /// Registering a remote for a table replaces the previous one.
     ///
     /// # Arguments
     ///
-    /// * `value` - The values to filter the data by.
+    /// * `table_name` - The name of the table to back.
+    /// * `remote` - The `RemoteTier` implementation to read from and write to.
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn in_(&mut self, values: Vec<String>) -> &mut Self {
-        Arc::make_mut(&mut self.runners).push_back(Runner::Compare(Comparator::In(values)));
-
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn set_remote_tier<R>(&mut self, table_name: &str, remote: R) -> &mut Self
+    where
+        R: RemoteTier + 'static,
+    {
+        self.remotes
+            .insert(table_name.to_string(), Arc::new(remote));
         self
     }
 
-    /// Adds a `Runner::Compare(Comparator::LessThan(value))` to the end of the runners queue, filtering the data based on the provided value.
-    /// The returned `Self` instance contains the updated runners queue.
-    ///
-    /// # Arguments
-    ///
-    /// * `value` - The value to filter the data by.
+    /// Adds a record stored in a table to the writes to write through, if the table is backed by a `RemoteTier`.
+    fn track_stored(&self, writes: &mut RemoteWrites, table_name: &str, stored: &Value) {
+        if self.remotes.contains_key(table_name) {
+            writes.stored.push((
+                table_name.to_string(),
+                self.prepare_read(table_name, stored.clone()),
+            ));
+        }
+    }
+
+    /// Adds a record removed from a table to the writes to write through, if the table is backed by a `RemoteTier`.
+    fn track_removed(&self, writes: &mut RemoteWrites, table_name: &str, stored: &Value) {
+        if let (true, Some(id)) = (
+            self.remotes.contains_key(table_name),
+            self.record_key(table_name, stored),
+        ) {
+            writes.removed.push((table_name.to_string(), id));
+        }
+    }
+
+    /// Sends the writes of an operation to the `RemoteTier` of their tables, stopping at the first failure.
+    async fn write_through(&self, writes: &RemoteWrites) -> Result<(), OhMyDbError> {
+        for (table, record) in &writes.stored {
+            if let Some(remote) = self.remotes.get(table) {
+                remote.store(table, record).await?;
+            }
+        }
+        for (table, id) in &writes.removed {
+            if let Some(remote) = self.remotes.get(table) {
+                remote.remove(table, id).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks a record missing from a table up in the `RemoteTier` backing it, caching it in the table if found.
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn less_than(&mut self, value: u64) -> &mut Self {
-        Arc::make_mut(&mut self.runners).push_back(Runner::Compare(Comparator::LessThan(value)));
+    /// A `Result` containing whether the remote had the record.
+    async fn read_through(&mut self, table_name: &str, id: &str) -> Result<bool, OhMyDbError> {
+        let Some(remote) = self.remotes.get(table_name).cloned() else {
+            return Ok(false);
+        };
+        if !self.value.contains_key(table_name) {
+            return Err(OhMyDbError::TableNotFound(table_name.to_string()));
+        }
+        let Some(record) = remote.fetch(table_name, id).await? else {
+            return Ok(false);
+        };
 
-        self
+        let mut stored = self.prepare_insert(table_name, wrap_record(record))?;
+        if self.is_enveloped(table_name) {
+            stored = envelope::wrap(stored, self.clock.now());
+        }
+        self.insert_into_table(table_name, &stored, false)?;
+        self.save().await?;
+
+        Ok(true)
     }
 
-    /// Adds a `Runner::Compare(Comparator::GreaterThan(value))` to the end of the runners queue, filtering the data based on the provided value.
-    /// The returned `Self` instance contains the updated runners queue.
+    /// Registers a custom comparator usable by every query of the database, e.g. a phonetic or locale-aware equality.
+    ///
+    /// Queries use it with `.custom(name, argument)` in the builder API, or as `field name literal` with `query`.
+    /// Registering a comparator under an existing name replaces it.
     ///
     /// # Arguments
     ///
-    /// * `value` - The value to filter the data by.
+    /// * `name` - The name the comparator is used by.
+    /// * `compare` - The function called with the field value and the query argument, returning whether the record matches.
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn greater_than(&mut self, value: u64) -> &mut Self {
-        Arc::make_mut(&mut self.runners).push_back(Runner::Compare(Comparator::GreaterThan(value)));
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn register_comparator<F>(&mut self, name: &str, compare: F) -> &mut Self
+    where
+        F: Fn(&Value, &Value) -> bool + Send + Sync + 'static,
+    {
+        self.comparators.insert(name.to_string(), Arc::new(compare));
 
         self
     }
 
-    /// Adds a `Runner::Compare(Comparator::Between((start, end)))` to the end of the runners queue, filtering the data based on the provided start and end values.
-    /// The returned `Self` instance contains the updated runners queue.
+    /// Adds a check constraint to a table: a boolean expression every record inserted or updated must satisfy,
+    /// e.g. `"qty > 0 && total >= 0"`.
+    ///
+    /// Expressions compare dot-separated fields of the record and literals with `==`, `!=`, `<`, `<=`, `>` and `>=`,
+    /// possibly after arithmetic (`+`, `-`, `*`, `/`, `%`), and combine the comparisons with `&&`, `||`, `!` and parentheses.
+    /// A write is rejected unless the expression evaluates to `true`; missing fields evaluate to `null`, so optional
+    /// fields are checked with e.g. `"discount == null || discount >= 0"`. Existing records are not checked.
     ///
     /// # Arguments
     ///
-    /// * `start` - The start value to filter the data by.
-    /// * `end` - The end value to filter the data by.
+    /// * `table_name` - The name of the table the check applies to.
+    /// * `expression` - The expression records must satisfy, also naming the check in errors.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::InvalidCheck` if the expression cannot be parsed.
+    /// Writes violating the check fail with an `OhMyDbError::CheckViolation` naming it.
     ///
     /// # Returns
     ///
-    /// A new `Self` instance with the updated runners queue.
-    pub fn between(&mut self, start: u64, end: u64) -> &mut Self {
-        Arc::make_mut(&mut self.runners)
-            .push_back(Runner::Compare(Comparator::Between((start, end))));
+    /// A `Result` containing a mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn add_check(
+        &mut self,
+        table_name: &str,
+        expression: &str,
+    ) -> Result<&mut Self, OhMyDbError> {
+        let check = Check::parse(expression).map_err(|reason| OhMyDbError::InvalidCheck {
+            expression: expression.to_string(),
+            reason,
+        })?;
+        self.checks
+            .entry(table_name.to_string())
+            .or_default()
+            .push(check);
 
-        self
+        Ok(self)
     }
 
-    /// Runs the database operations specified in the runners queue.
+    /// Replaces the `Clock` used for every timestamp the database records (the system clock by default).
+    ///
+    /// # Arguments
     ///
-    /// This method processes the runners queue, performing various database operations such as creating, reading, updating, and deleting records.
-    /// The method returns the resulting list of `T` items after applying the specified operations.
+    /// * `clock` - The clock to use, e.g. a `ManualClock` to freeze and advance time in tests.
     ///
-    /// # Errors
+    /// # Returns
     ///
-    /// This method may return an `std::io::Error` if there is an error saving the database state after the operations are completed.
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn set_clock<C>(&mut self, clock: C) -> &mut Self
+    where
+        C: Clock + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets how many executed operations are kept by the operation history (32 by default).
+    /// A capacity of zero disables the history.
     ///
     /// # Returns
     ///
-    /// A `Result` containing a `Vec` of `T` items representing the final state of the database after the operations have been performed.
-    pub async fn run(&mut self) -> Result<Vec<Value>, std::io::Error> {
-        let mut result = Vec::new();
-        let mut key_chain = String::new();
-        let mut method: Option<MethodName> = None;
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn set_history_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.history.set_capacity(capacity);
+        self
+    }
 
-        Arc::make_mut(&mut self.runners).push_back(Runner::Done);
+    /// Returns the last executed operations with their timestamps and outcomes, oldest first.
+    pub fn recent_operations(&self) -> Vec<Operation> {
+        self.history.entries()
+    }
 
-        while let Some(runner) = Arc::make_mut(&mut self.runners).pop_front() {
-            match runner {
-                Runner::Method(name) => match name {
-                    MethodName::Create(table, new_item, or) => {
-                        result = self.get_table_vec(&table).unwrap_or_default();
-                        method = Some(MethodName::Create(table, new_item.clone(), or));
-                    }
-                    MethodName::Read(table) => {
-                        result = self.get_table_vec(&table).unwrap_or_default();
-                        method = Some(MethodName::Read(table));
-                    }
-                    MethodName::Delete(table) => {
-                        result = self.get_table_vec(&table).unwrap_or_default();
-                        method = Some(MethodName::Delete(table));
-                    }
-                    MethodName::Update(table, new_item) => {
-                        result = self.get_table_vec(&table).unwrap_or_default();
-                        method = Some(MethodName::Update(table, new_item));
-                    }
-                },
-                Runner::Where(f) => {
-                    key_chain = f;
-                }
-                Runner::Compare(ref comparator) => {
-                    result = result
-                        .into_iter()
-                        .filter(|t| {
-                            let value = get_nested_value(t, &key_chain).unwrap();
-                            self.filter_with_conmpare(value, comparator)
-                        })
+    /// Records a finished operation in the operation history and, when it succeeded, in the slow-query log.
+    async fn finish_operation<T>(
+        &mut self,
+        plan: &Plan,
+        started: Instant,
+        matched: usize,
+        outcome: &Result<T, OhMyDbError>,
+        records: impl Fn(&T) -> usize,
+    ) {
+        match outcome {
+            Ok(value) => {
+                self.history.record(
+                    &plan.pipeline,
+                    self.clock.now(),
+                    OperationOutcome::Success {
+                        records: records(value),
+                    },
+                );
+                self.log_query(plan, started, matched).await;
+            }
+            Err(e) => self.history.record(
+                &plan.pipeline,
+                self.clock.now(),
+                OperationOutcome::Failure(e.to_string()),
+            ),
+        }
+    }
+
+    /// Records a finished query in the slow-query log, if it is enabled.
+    async fn log_query(&mut self, plan: &Plan, started: Instant, matched: usize) {
+        let Some(method) = &plan.method else {
+            return;
+        };
+        let scanned = self.value.get(method.table()).map_or(0, HashSet::len);
+
+        if let Some(log) = self.slow_log.as_mut() {
+            log.record(SlowQuery {
+                pipeline: plan.pipeline.clone(),
+                table: method.table().to_string(),
+                duration: started.elapsed(),
+                scanned,
+                matched,
+                at: self.clock.now(),
+            })
+            .await;
+        }
+    }
+
+    /// Registers a callback receiving `Progress` updates (stage name, items processed and total)
+    /// from long-running operations, e.g. to render progress bars.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn on_progress<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        self.progress = Some(ProgressCallback::new(f));
+        self
+    }
+
+    /// Returns the configuration document of type `T`, to read it and update it durably in the database file.
+    pub fn config<T>(&mut self) -> Config<'_, T>
+    where
+        T: Serialize + DeserializeOwned + Default,
+    {
+        Config::new(self)
+    }
+
+    /// Registers a callback receiving the name (e.g. `"AppConfig"`) and the new value of every configuration
+    /// document changed through `config`, once the change is saved. Registering a callback replaces the previous one.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn on_config_change<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&str, &Value) + Send + Sync + 'static,
+    {
+        self.config_listener = Some(Arc::new(f));
+        self
+    }
+
+    /// Reports a saved configuration change to the registered callback, if any.
+    pub(crate) fn notify_config_change(&self, name: &str, config: &Value) {
+        if let Some(listener) = &self.config_listener {
+            listener(name, config);
+        }
+    }
+
+    /// Reports progress to the registered progress callback, if any.
+    fn report_progress(&self, stage: &str, processed: usize, total: usize) {
+        if let Some(progress) = &self.progress {
+            progress.report(stage, processed, total);
+        }
+    }
+
+    /// Checks the whole database for integrity problems without modifying anything.
+    ///
+    /// The report lists records without a usable id, ids shared by several records of the same table, references
+    /// to records that don't exist (see `TableOptions::reference`), records failing a check constraint (see `add_check`)
+    /// and records breaking a unique constraint (see `TableOptions::unique`), e.g. because they were written before
+    /// the constraint was declared or by another tool. Cold tables are not checked. Problems can be reviewed
+    /// before running repairs.
+    /// Progress is reported per table under the `"integrity"` stage.
+    ///
+    /// # Returns
+    ///
+    /// An `IntegrityReport` listing every issue found, tables being checked in alphabetical order.
+    pub fn integrity_report(&self) -> IntegrityReport {
+        let mut report = IntegrityReport::default();
+        let mut tables: Vec<(&String, &HashSet<Value>)> = self.value.iter().collect();
+        tables.sort_by_key(|(name, _)| *name);
+
+        let total = tables.len();
+
+        for (processed, (name, records)) in tables.into_iter().enumerate() {
+            report.check_table(name, records, |record| self.record_key(name, record));
+
+            let data: Vec<&Value> = records
+                .iter()
+                .map(|record| self.stored_data(name, record))
+                .collect();
+            let key = |data: &Value| record_id(data, self.primary_key(name));
+            if let Some(checks) = self.checks.get(name) {
+                report.check_constraints(name, &data, checks, key);
+            }
+            if let Some(options) = self.options.get(name) {
+                for (fields, nulls) in options.unique_constraints() {
+                    report.check_unique(name, &data, fields, *nulls);
+                }
+                // Cold tables are not loaded, so references to them can't be checked here.
+                let relations = options
+                    .relations()
+                    .iter()
+                    .filter(|(_, relation)| self.cold_file(relation.table()).is_none());
+                for (field, relation) in relations {
+                    let referenced: Vec<&Value> = self
+                        .value
+                        .get(relation.table())
+                        .into_iter()
+                        .flatten()
+                        .map(|record| self.stored_data(relation.table(), record))
                         .collect();
+                    report.check_references(name, &data, field, relation, &referenced, key);
                 }
-                Runner::Done => {
-                    match method {
-                        Some(MethodName::Read(table)) => {
-                            MethodName::Read(table).notify();
+            }
+
+            self.report_progress("integrity", processed + 1, total);
+        }
+
+        report
+    }
+
+    /// Returns a stream of every record of the user tables with the name of its table, e.g. to export,
+    /// index or validate the whole database without listing its tables.
+    ///
+    /// Tables are visited in alphabetical order and their records are decoded like reads, so codecs and envelopes
+    /// are handled, but row-level security does not apply. Reserved tables (outbox, queues, configuration) are skipped.
+    ///
+    /// # Returns
+    ///
+    /// A `RecordStream` yielding `(table, record)` pairs.
+    pub fn iter_all(&self) -> RecordStream<'_> {
+        let mut tables: Vec<&String> = self
+            .value
+            .keys()
+            .filter(|table| is_user_table(table))
+            .collect();
+        tables.sort();
+        let now = self.clock.now();
+
+        RecordStream::new(tables.into_iter().flat_map(move |table| {
+            self.value
+                .get(table)
+                .into_iter()
+                .flatten()
+                .filter(move |record| !expiry::is_expired(record, now))
+                .filter(move |record| !self.is_soft_delete(table) || !is_soft_deleted(record))
+                .map(move |record| (table.clone(), self.prepare_read(table, record.clone())))
+        }))
+    }
+
+    /// Runs `query` `iterations` times against the current data and reports its latency percentiles,
+    /// e.g. to compare table options or data layouts on real data.
+    ///
+    /// Each iteration plans the query, selects the matching records and materializes them as `run` would for a read
+    /// (cloning, envelope unwrapping and projection). Nothing is written, notified or recorded in the operation history,
+    /// so write queries only measure the selection of the records they would change.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to measure.
+    /// * `iterations` - How many times to run it.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the error the query itself fails with, e.g. `OhMyDbError::InvalidParameter`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BenchReport` of the runs.
+    pub fn bench(&self, query: &Query, iterations: usize) -> Result<BenchReport, OhMyDbError> {
+        let mut samples = Vec::with_capacity(iterations);
+        let mut rows = 0;
+
+        for _ in 0..iterations {
+            let started = Instant::now();
+            let plan = self.plan(query);
+            let records = self.select_records(&plan)?;
+            let result: Vec<Value> = match &plan.method {
+                Some(method) => records
+                    .into_iter()
+                    .map(|item| self.finalize_read(&plan, method.table(), item.clone()))
+                    .collect(),
+                None => records.into_iter().cloned().collect(),
+            };
+            samples.push(started.elapsed());
+            rows = result.len();
+        }
+
+        Ok(BenchReport::from_samples(samples, rows))
+    }
+
+    /// Returns the outbox of the database, to enqueue events, poll the pending ones and acknowledge them once published.
+    pub fn outbox(&mut self) -> Outbox<'_> {
+        Outbox::new(self)
+    }
+
+    /// Returns the job queue with the provided name, to push, claim, complete and retry its jobs.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the queue, e.g. `"emails"`. Queues are created when their first job is pushed.
+    pub fn queue(&mut self, name: &str) -> JobQueue<'_> {
+        JobQueue::new(self, name)
+    }
+
+    /// Returns the current time of the clock of the database.
+    pub(crate) fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Returns a new id for the records of the reserved tables, increasing with time.
+    pub(crate) fn next_internal_id(&self) -> String {
+        self.internal_ids.generate()
+    }
+
+    /// Returns the records of a reserved table, which is not listed among the user tables.
+    pub(crate) fn reserved_table(&self, name: &str) -> Option<&HashSet<Value>> {
+        self.value.get(name)
+    }
+
+    /// Returns the records of a reserved table for changing them, creating the table if needed.
+    pub(crate) fn reserved_table_mut(&mut self, name: &str) -> &mut HashSet<Value> {
+        Arc::make_mut(&mut self.value)
+            .entry(name.to_string())
+            .or_default()
+    }
+
+    /// Adds an event to the outbox, returning its id. Nothing is saved.
+    pub(crate) fn push_event(&mut self, event: Value) -> String {
+        let id = self.next_internal_id();
+        let record = json!({
+            "id": id,
+            "event": event,
+            "enqueued_at": unix_millis(self.now()),
+        });
+        self.reserved_table_mut(OUTBOX_TABLE).insert(record);
+
+        id
+    }
+
+    /// Returns up to `limit` pending events of the outbox, oldest first.
+    pub(crate) fn pending_events(&self, limit: usize) -> Vec<OutboxEvent> {
+        let mut events: Vec<OutboxEvent> = self
+            .reserved_table(OUTBOX_TABLE)
+            .into_iter()
+            .flatten()
+            .map(|record| OutboxEvent {
+                id: record_id(record, "id").unwrap_or_default(),
+                event: record["event"].clone(),
+                enqueued_at: UNIX_EPOCH
+                    + Duration::from_millis(record["enqueued_at"].as_u64().unwrap_or(0)),
+            })
+            .collect();
+        events.sort_by_key(|event| (event.enqueued_at, event.id.parse::<u64>().unwrap_or(0)));
+        events.truncate(limit);
+
+        events
+    }
+
+    /// Removes an event from the outbox, returning whether it was pending. Nothing is saved.
+    pub(crate) fn remove_event(&mut self, id: &str) -> bool {
+        let events = self.reserved_table_mut(OUTBOX_TABLE);
+        let before = events.len();
+        events.retain(|record| record_id(record, "id").as_deref() != Some(id));
+
+        events.len() < before
+    }
+
+    /// Saves the current state of the `JsonDb` instance to the file specified by the `path` field.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is a problem writing the JSON data to the file.
+    pub async fn save(&self) -> Result<(), OhMyDbError> {
+        let now = self.clock.now();
+        let json = match self
+            .value
+            .values()
+            .flatten()
+            .any(|r| expiry::is_expired(r, now))
+        {
+            // Expired records are purged from the file, the next `purge_expired` dropping them from memory.
+            true => {
+                let live: HashMap<&String, Vec<&Value>> = self
+                    .value
+                    .iter()
+                    .map(|(table, records)| {
+                        let live = records.iter().filter(|r| !expiry::is_expired(r, now));
+                        (table, live.collect())
+                    })
+                    .collect();
+                serde_json::to_string_pretty(&live)?
+            }
+            false => serde_json::to_string_pretty(&*self.value)?,
+        };
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&self.path)
+            .await?;
+
+        file.write_all(json.as_bytes()).await?;
+        file.flush().await?;
+
+        Ok(())
+    }
+
+    /// Turns on or off the messages printed to the standard output for every operation and failed lookup,
+    /// e.g. to keep the output of a program clean. Messages are printed by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether the messages are printed.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn set_logging(&mut self, enabled: bool) -> &mut Self {
+        self.logging = enabled;
+        self
+    }
+
+    /// Prints what `method` does, unless logging is turned off.
+    fn notify(&self, method: &MethodName) {
+        if self.logging {
+            method.notify();
+        }
+    }
+
+    /// Removes the expired records (see `Query::expires_in`) from memory and saves the database file.
+    ///
+    /// Expired records are already hidden from reads and left out of every save, so this only reclaims their memory,
+    /// e.g. by calling it periodically from a background task for session or cache tables.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of records removed.
+    pub async fn purge_expired(&mut self) -> Result<usize, OhMyDbError> {
+        let now = self.clock.now();
+        let expired: Vec<String> = self
+            .value
+            .iter()
+            .filter(|(_, records)| records.iter().any(|r| expiry::is_expired(r, now)))
+            .map(|(table, _)| table.clone())
+            .collect();
+
+        let mut purged = 0;
+        for table in expired {
+            self.invalidate_stats(&table);
+            let records = Arc::make_mut(&mut self.value).entry(table).or_default();
+            let before = records.len();
+            records.retain(|r| !expiry::is_expired(r, now));
+            purged += before - records.len();
+        }
+
+        if purged > 0 {
+            self.save().await?;
+        }
+
+        Ok(purged)
+    }
+
+    /// Forgets the idempotency keys (see `Query::with_idempotency_key`) of the inserts made more than `older_than` ago
+    /// and saves the database file.
+    ///
+    /// Keys are otherwise kept forever, so the reserved table holding them grows with every keyed insert. Prune them
+    /// periodically with a duration longer than the delay after which a retry can still happen, e.g. the message
+    /// retention of the queue redelivering them: retrying an insert whose key was pruned inserts the record again.
+    /// Keys persisted without their insertion time, by versions of the library older than this method, are pruned too.
+    ///
+    /// # Arguments
+    ///
+    /// * `older_than` - The age past which a key is forgotten.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of keys removed.
+    pub async fn prune_idempotency(&mut self, older_than: Duration) -> Result<usize, OhMyDbError> {
+        let cutoff = unix_millis(self.now()).saturating_sub(older_than.as_millis() as u64);
+        let is_recent = |entry: &Value| entry["inserted_at"].as_u64().is_some_and(|at| at > cutoff);
+        let Some(keys) = self.reserved_table(IDEMPOTENCY_TABLE) else {
+            return Ok(0);
+        };
+        if keys.iter().all(is_recent) {
+            return Ok(0);
+        }
+
+        let keys = self.reserved_table_mut(IDEMPOTENCY_TABLE);
+        let before = keys.len();
+        keys.retain(is_recent);
+        let pruned = before - keys.len();
+        self.save().await?;
+
+        Ok(pruned)
+    }
+
+    /// Moves a table to the cold tier: its records are written gzip-compressed to a file next to the database file,
+    /// then dropped from memory and from the database file, reducing the resident memory of rarely used tables.
+    ///
+    /// The table is loaded back transparently by the next query targeting it, joining or populating from it, or cascading
+    /// a delete into it. Reads that don't go through a query (`iter_all`, `integrity_report`, `field_stats`, ...)
+    /// only see hot tables, while exports and backups include the cold ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table to freeze.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether the table was frozen, `false` if it was already cold,
+    /// or an `OhMyDbError::TableNotFound` if it is not a user table.
+    pub async fn freeze(&mut self, table_name: &str) -> Result<bool, OhMyDbError> {
+        if self.cold_file(table_name).is_some() {
+            return Ok(false);
+        }
+        let records = match self.value.get(table_name) {
+            Some(records) if is_user_table(table_name) => records,
+            _ => return Err(OhMyDbError::TableNotFound(table_name.to_string())),
+        };
+
+        let file_name = tiering::cold_file_name(&self.path, &self.next_internal_id());
+        tiering::write_cold(&tiering::cold_path(&self.path, &file_name), records).await?;
+
+        let count = records.len();
+        Arc::make_mut(&mut self.value).remove(table_name);
+        self.invalidate_stats(table_name);
+        let record = json!({
+            "id": table_name,
+            "file": file_name,
+            "records": count,
+            "frozen_at": unix_millis(self.now()),
+        });
+        self.reserved_table_mut(COLD_TABLE).insert(record);
+        self.save().await?;
+
+        Ok(true)
+    }
+
+    /// Moves every user table that no query accessed for at least `idle` to the cold tier, like `freeze`.
+    ///
+    /// Accesses are tracked in memory with the clock of the database, so tables not accessed since
+    /// the database was opened (nor added with `add_table`) count as idle.
+    ///
+    /// # Arguments
+    ///
+    /// * `idle` - How long a table must go unaccessed to be frozen.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the names of the frozen tables, in alphabetical order.
+    pub async fn freeze_idle(&mut self, idle: Duration) -> Result<Vec<String>, OhMyDbError> {
+        let now = self.clock.now();
+        let mut idle_tables: Vec<String> = self
+            .value
+            .keys()
+            .filter(|table| is_user_table(table))
+            .filter(|table| {
+                self.accessed.get(*table).is_none_or(|accessed| {
+                    now.duration_since(*accessed).unwrap_or_default() >= idle
+                })
+            })
+            .cloned()
+            .collect();
+        idle_tables.sort();
+
+        for table in &idle_tables {
+            self.freeze(table).await?;
+        }
+
+        Ok(idle_tables)
+    }
+
+    /// Loads a cold table back into memory and into the database file, removing its cold table file.
+    ///
+    /// Queries do this on their own, so calling it is only needed before reads that don't go through a query.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - The name of the table to thaw.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether the table was thawed, `false` if it was not cold.
+    pub async fn thaw(&mut self, table_name: &str) -> Result<bool, OhMyDbError> {
+        let Some(file_name) = self.cold_file(table_name) else {
+            return Ok(false);
+        };
+        let path = tiering::cold_path(&self.path, &file_name);
+        let records = tiering::read_cold(&path).await?;
+
+        Arc::make_mut(&mut self.value)
+            .entry(table_name.to_string())
+            .or_default()
+            .extend(records);
+        self.invalidate_stats(table_name);
+        self.reserved_table_mut(COLD_TABLE)
+            .retain(|record| record_id(record, "id").as_deref() != Some(table_name));
+        self.save().await?;
+
+        // The records are saved in the database file again, so a file left behind is only wasted space.
+        tokio::fs::remove_file(&path).await.ok();
+
+        Ok(true)
+    }
+
+    /// Returns the names of the cold tables, in alphabetical order.
+    pub fn cold_tables(&self) -> Vec<String> {
+        let mut tables: Vec<String> = self
+            .reserved_table(COLD_TABLE)
+            .into_iter()
+            .flatten()
+            .filter_map(|record| record_id(record, "id"))
+            .collect();
+        tables.sort();
+
+        tables
+    }
+
+    /// Returns the name of the file holding the records of a cold table, or `None` if the table is not cold.
+    fn cold_file(&self, table_name: &str) -> Option<String> {
+        self.reserved_table(COLD_TABLE)?
+            .iter()
+            .find(|record| record_id(record, "id").as_deref() == Some(table_name))
+            .and_then(|record| record["file"].as_str())
+            .map(str::to_string)
+    }
+
+    /// Returns every table as stored, cold tables included, e.g. to export or back up the whole database.
+    async fn stored_tables(&self) -> Result<Cow<'_, HashMap<String, HashSet<Value>>>, OhMyDbError> {
+        let Some(cold) = self.reserved_table(COLD_TABLE) else {
+            return Ok(Cow::Borrowed(&*self.value));
+        };
+
+        let mut tables = (*self.value).clone();
+        tables.remove(COLD_TABLE);
+        for record in cold {
+            let (Some(table), Some(file_name)) = (record_id(record, "id"), record["file"].as_str())
+            else {
+                continue;
+            };
+            let records = tiering::read_cold(&tiering::cold_path(&self.path, file_name)).await?;
+            tables.insert(table, records);
+        }
+
+        Ok(Cow::Owned(tables))
+    }
+
+    /// Records the access to the tables `plan` reads or writes, loading the cold ones back first.
+    ///
+    /// Besides the target table, joined and populated tables are read, and a delete may cascade
+    /// into every table declaring a relation.
+    async fn warm_up(&mut self, plan: &Plan) -> Result<(), OhMyDbError> {
+        let Some(method) = &plan.method else {
+            return Ok(());
+        };
+
+        let mut tables = vec![method.table().to_string()];
+        tables.extend(plan.joins.iter().map(|join| join.table.clone()));
+        tables.extend(
+            plan.populate
+                .iter()
+                .map(|(_, relation)| relation.table().to_string()),
+        );
+        if let MethodName::Delete(_) | MethodName::Purge(_) = method {
+            tables.extend(
+                self.options
+                    .iter()
+                    .filter(|(_, options)| !options.relations().is_empty())
+                    .map(|(table, _)| table.clone()),
+            );
+        }
+
+        if plan.from_disk {
+            self.reload(&tables).await?;
+        }
+
+        let now = self.clock.now();
+        for table in tables {
+            self.thaw(&table).await?;
+            self.accessed.insert(table, now);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the in-memory records of the provided tables with the ones currently in the database file,
+    /// picking up the writes of other processes. Tables missing from the file are left as they are.
+    async fn reload(&mut self, tables: &[String]) -> Result<(), OhMyDbError> {
+        let content = tokio::fs::read_to_string(&self.path).await?;
+        if content.is_empty() {
+            return Ok(());
+        }
+        let mut stored: HashMap<String, HashSet<Value>> = serde_json::from_str(&content)?;
+
+        for table in tables {
+            if let Some(records) = stored.remove(table) {
+                self.invalidate_stats(table);
+                Arc::make_mut(&mut self.value).insert(table.clone(), records);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Exports the whole database into a single compressed archive file.
+    ///
+    /// The archive holds every table with its records exactly as stored (envelopes included), along with the
+    /// time of the export and the path of the source database. Table options are not part of the archive,
+    /// as they are configured in code. Progress is reported under the `"export"` stage in three steps:
+    /// capturing the tables (cold ones included), compressing them and writing the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the archive file to write.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating whether the archive was written successfully.
+    pub async fn export_archive<P>(&self, path: P) -> Result<(), OhMyDbError>
+    where
+        P: AsRef<Path>,
+    {
+        let archive = self.to_archive().await?;
+        self.report_progress("export", 1, 3);
+        let bytes = archive.encode()?;
+        self.report_progress("export", 2, 3);
+        tokio::fs::write(path, bytes).await?;
+        self.report_progress("export", 3, 3);
+
+        Ok(())
+    }
+
+    /// Captures the whole database as an `Archive`.
+    async fn to_archive(&self) -> Result<Archive, OhMyDbError> {
+        Ok(Archive {
+            format: ARCHIVE_FORMAT,
+            exported_at: envelope::unix_millis(self.clock.now()),
+            source: self.get_db_path().to_string(),
+            tables: self.stored_tables().await?.into_owned(),
+        })
+    }
+
+    /// Replaces the fields configured in `policy` (names, emails, tokens, ...) with fake but shape-preserving data
+    /// in every user table, cold tables included, then saves, e.g. to share a copy of production data with developers.
+    ///
+    /// The database is changed in place, so scrub a copy (see `export_archive` and `import_archive`). Equal values
+    /// get equal replacements, so references between tables keep matching. Records are rewritten as stored,
+    /// bypassing constraints, policies and the remote tier. Progress is reported per table under the `"scrub"` stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The `ScrubPolicy` listing the fields to replace.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of records changed.
+    pub async fn scrub(&mut self, policy: &ScrubPolicy) -> Result<usize, OhMyDbError> {
+        for table in self.cold_tables() {
+            self.thaw(&table).await?;
+        }
+
+        let mut tables: Vec<String> = self
+            .value
+            .keys()
+            .filter(|table| is_user_table(table))
+            .cloned()
+            .collect();
+        tables.sort();
+
+        let total = tables.len();
+        let mut changed = 0;
+        for (processed, table) in tables.into_iter().enumerate() {
+            let Some(records) = self.value.get(&table) else {
+                continue;
+            };
+
+            let mut scrubbed = HashSet::with_capacity(records.len());
+            for record in records {
+                let mut data = self.prepare_read(&table, record.clone());
+                if !policy.apply(&table, &mut data) {
+                    scrubbed.insert(record.clone());
+                    continue;
+                }
+
+                let data = self.prepare_write(&table, data)?;
+                let mut stored = match self.is_enveloped(&table) {
+                    true => envelope::rewrap(record, data, self.clock.now()),
+                    false => data,
+                };
+                expiry::keep(record, &mut stored);
+                scrubbed.insert(stored);
+                changed += 1;
+            }
+
+            *self.get_table_mut(&table)? = scrubbed;
+            self.report_progress("scrub", processed + 1, total);
+        }
+
+        self.save().await?;
+
+        Ok(changed)
+    }
+
+    /// Compares two database files, e.g. two backups, reporting the records added, removed and changed in each table.
+    ///
+    /// Records are matched by id, so an updated record is reported as changed with both versions. Each file can
+    /// be a database file or an archive written by `export_archive` or `backup_to`. The report prints as a summary.
+    ///
+    /// # Arguments
+    ///
+    /// * `a` - The path of the first (older) database.
+    /// * `b` - The path of the second (newer) database.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `DatabaseDiff` turning `a` into `b`, or an error if a file can't be read or parsed.
+    pub async fn diff_files<P, Q>(a: P, b: Q) -> Result<DatabaseDiff, OhMyDbError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let before = diff::read_tables(a.as_ref()).await?;
+        let after = diff::read_tables(b.as_ref()).await?;
+
+        Ok(DatabaseDiff::between(&before, &after))
+    }
+
+    /// Loads the fixture files of a directory into the tables they are named after, creating missing tables,
+    /// e.g. `db.seed("fixtures", OnConflict::Skip)` to bootstrap a development database from `fixtures/todos.json`.
+    ///
+    /// `.json` files hold an array of records (or a single one), `.ndjson` and `.jsonl` files one record per line,
+    /// and `.csv` files a header row naming the fields followed by one record per row, numbers, booleans and `null`
+    /// being read as such. Other files are ignored. Each file is inserted like `insert_many`, so the records go
+    /// through the options and constraints of the table; `on_conflict` decides what happens to the records whose
+    /// id is already stored. Progress is reported per file under the `"seed"` stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the fixture files.
+    /// * `on_conflict` - What to do with the fixture records whose id is already stored.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `SeedReport` of the seeded records, or an error if a fixture file can't be read
+    /// or parsed, names a reserved table, or conflicts with a stored record under `OnConflict::Fail`.
+    pub async fn seed<P>(
+        &mut self,
+        dir: P,
+        on_conflict: OnConflict,
+    ) -> Result<SeedReport, OhMyDbError>
+    where
+        P: AsRef<Path>,
+    {
+        let fixtures = seed::read_fixtures(dir.as_ref()).await?;
+        if let Some((table, _)) = fixtures.iter().find(|(table, _)| !is_user_table(table)) {
+            return Err(OhMyDbError::TableNotFound(table.clone()));
+        }
+
+        let total = fixtures.len();
+        let mut report = SeedReport::default();
+        for (processed, (table, records)) in fixtures.into_iter().enumerate() {
+            self.add_table(&table).await?;
+            let batch = self.insert_many(&table, &records).await?;
+            report.inserted += batch.inserted.len();
+
+            for (index, e) in batch.failures {
+                match (e, on_conflict) {
+                    (OhMyDbError::RecordExists { .. }, OnConflict::Skip) => report.skipped += 1,
+                    (OhMyDbError::RecordExists { .. }, OnConflict::Replace) => {
+                        match self.update(&table, &records[index]).run(self).await {
+                            Ok(_) => report.replaced += 1,
+                            Err(e) => report.failures.push((table.clone(), index, e)),
+                        }
+                    }
+                    (e @ OhMyDbError::RecordExists { .. }, OnConflict::Fail) => return Err(e),
+                    (e, _) => report.failures.push((table.clone(), index, e)),
+                }
+            }
+            self.report_progress("seed", processed + 1, total);
+        }
+
+        Ok(report)
+    }
+
+    /// Diagnoses the database, listing every problem found with a suggested fix, without modifying anything.
+    ///
+    /// Besides the problems with the records found by `integrity_report`, which use the constraints configured
+    /// on this instance, it reports cold tables whose file is missing or unreadable, cold table files no table
+    /// refers to, auto-increment sequences behind the largest id stored and stale field statistics. The database
+    /// has no lock file nor write-ahead log, every save rewriting the whole file, so there are none to check.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `DoctorReport`, or an error if the directory of the database file can't be listed.
+    pub async fn doctor(&self) -> Result<DoctorReport, OhMyDbError> {
+        let mut report = DoctorReport::default();
+        report.findings.extend(
+            self.integrity_report()
+                .issues
+                .into_iter()
+                .map(DoctorFinding::Integrity),
+        );
+
+        let mut cold_files = HashSet::new();
+        for table in self.cold_tables() {
+            let Some(file) = self.cold_file(&table) else {
+                continue;
+            };
+            if let Err(e) = tiering::read_cold(&tiering::cold_path(&self.path, &file)).await {
+                report.findings.push(DoctorFinding::MissingColdFile {
+                    table,
+                    file: file.clone(),
+                    reason: e.to_string(),
+                });
+            }
+            cold_files.insert(file);
+        }
+        let prefix = tiering::cold_file_name(&self.path, "");
+        let prefix = prefix.trim_end_matches(".json.gz");
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut orphans = Vec::new();
+        let mut entries = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let file = entry.file_name().to_string_lossy().into_owned();
+            if file.starts_with(prefix) && file.ends_with(".json.gz") && !cold_files.contains(&file)
+            {
+                orphans.push(file);
+            }
+        }
+        orphans.sort();
+        report.findings.extend(
+            orphans
+                .into_iter()
+                .map(|file| DoctorFinding::OrphanedColdFile { file }),
+        );
+
+        let mut sequences: Vec<(String, u64)> = self
+            .reserved_table(SEQUENCES_TABLE)
+            .into_iter()
+            .flatten()
+            .filter_map(|sequence| Some((record_id(sequence, "id")?, sequence["last"].as_u64()?)))
+            .collect();
+        sequences.sort();
+        for (table, last) in sequences {
+            let max_id = self
+                .value
+                .get(&table)
+                .into_iter()
+                .flatten()
+                .filter_map(|record| {
+                    self.record_field(&table, record, self.primary_key(&table))?
+                        .as_u64()
+                })
+                .max();
+            if let Some(max_id) = max_id.filter(|max_id| *max_id > last) {
+                report.findings.push(DoctorFinding::SequenceBehind {
+                    table,
+                    last,
+                    max_id,
+                });
+            }
+        }
+
+        report.findings.extend(
+            self.index_stats()
+                .into_iter()
+                .filter(|stats| stats.stale)
+                .map(|stats| DoctorFinding::StaleStatistics {
+                    table: stats.table,
+                    field: stats.field,
+                }),
+        );
+
+        Ok(report)
+    }
+
+    /// Diagnoses a database file or an archive written by `export_archive` or `backup_to`, like `doctor`,
+    /// e.g. before opening a file copied from another machine.
+    ///
+    /// A file that can't be read as either is reported as such, as is an archive with an unsupported format version.
+    /// Archives are only checked for records without a usable id and ids shared by several records. As the file is
+    /// opened without the configuration of the application, the constraints declared in code are not checked
+    /// and `id` is taken as the primary key of every table.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the database file or archive to diagnose.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `DoctorReport`, or an error if the file doesn't exist or can't be read.
+    pub async fn doctor_file<P>(path: P) -> Result<DoctorReport, OhMyDbError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let bytes = tokio::fs::read(path).await?;
+
+        let unreadable = |reason: String| DoctorReport {
+            archive_format: None,
+            findings: vec![DoctorFinding::UnreadableFile { reason }],
+        };
+
+        if !bytes.starts_with(&[0x1f, 0x8b]) {
+            return match serde_json::from_slice::<HashMap<String, HashSet<Value>>>(&bytes) {
+                Err(e) if !bytes.is_empty() => Ok(unreadable(e.to_string())),
+                _ => Self::open(path.to_path_buf()).await?.doctor().await,
+            };
+        }
+
+        let archive: Archive = match archive::decode(&bytes) {
+            Ok(archive) => archive,
+            Err(e) => return Ok(unreadable(e.to_string())),
+        };
+        let mut report = DoctorReport {
+            archive_format: Some(archive.format),
+            findings: Vec::new(),
+        };
+        if archive::check_format(archive.format).is_err() {
+            report.findings.push(DoctorFinding::UnsupportedFormat {
+                format: archive.format,
+            });
+            return Ok(report);
+        }
+
+        let mut tables: Vec<(&String, &HashSet<Value>)> = archive
+            .tables
+            .iter()
+            .filter(|(table, _)| is_user_table(table))
+            .collect();
+        tables.sort_by_key(|(table, _)| *table);
+        let mut integrity = IntegrityReport::default();
+        for (table, records) in tables {
+            integrity.check_table(table, records, |record| record_id(record, "id"));
+        }
+        report
+            .findings
+            .extend(integrity.issues.into_iter().map(DoctorFinding::Integrity));
+
+        Ok(report)
+    }
+
+    /// Creates (or overwrites) the database `db_name` from an archive written by `export_archive`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the archive file to read.
+    /// * `db_name` - The name of the database to restore the archive into, as passed to `JsonDB::new`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the restored `JsonDB`, or an `OhMyDbError::InvalidArchive` if the archive
+    /// is corrupted or has an unsupported format.
+    pub async fn import_archive<P>(path: P, db_name: &str) -> Result<Self, OhMyDbError>
+    where
+        P: AsRef<Path>,
+    {
+        let archive = Archive::decode(&tokio::fs::read(path).await?)?;
+        let mut db = Self::new(db_name).await?;
+
+        db.tables = archive
+            .tables
+            .keys()
+            .filter(|table| is_user_table(table))
+            .cloned()
+            .collect();
+        db.value = Arc::new(archive.tables);
+        db.save().await?;
+
+        Ok(db)
+    }
+
+    /// Backs the database up into a chain of backups stored in `dir`.
+    ///
+    /// The first backup of a chain is a full archive (as written by `export_archive`). Every following
+    /// backup only stores the records added and removed since the previous one, keeping the chain small
+    /// for large databases. Use `restore_chain` to rebuild the database from the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the backup chain. It is created if it does not exist.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the path of the backup file that was written.
+    pub async fn backup_incremental<P>(&self, dir: P) -> Result<PathBuf, OhMyDbError>
+    where
+        P: AsRef<Path>,
+    {
+        let chain = BackupChain::new(dir.as_ref());
+        tokio::fs::create_dir_all(dir.as_ref()).await?;
+
+        if !chain.exists().await {
+            let path = chain.full_path();
+            self.export_archive(&path).await?;
+            return Ok(path);
+        }
+
+        let (previous, sequence) = chain.restore().await?;
+        let increment = Increment::between(
+            &previous,
+            &*self.stored_tables().await?,
+            envelope::unix_millis(self.clock.now()),
+        );
+        let path = chain.increment_path(sequence + 1);
+
+        tokio::fs::write(&path, archive::encode(&increment)?).await?;
+
+        Ok(path)
+    }
+
+    /// Uploads a full archive of the database (as written by `export_archive`) to a `BackupSink`,
+    /// then removes the backups the retention rules no longer keep.
+    ///
+    /// Backups are named `backup-<milliseconds since the Unix epoch>.ohmy.gz`, so they sort by age.
+    /// Other objects stored in the sink are left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - The destination of the backup, e.g. a `DirectorySink` or an object storage sink.
+    /// * `retention` - How many backups to keep in the sink.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the name of the uploaded backup.
+    pub async fn backup_to<S>(&self, sink: &S, retention: Retention) -> Result<String, OhMyDbError>
+    where
+        S: BackupSink + ?Sized,
+    {
+        let archive = self.to_archive().await?;
+        let name = format!("backup-{:013}.ohmy.gz", archive.exported_at);
+
+        sink.put(&name, archive.encode()?).await?;
+
+        let backups = sink
+            .list()
+            .await?
+            .into_iter()
+            .filter(|n| n.starts_with("backup-") && n.ends_with(".ohmy.gz"))
+            .collect();
+        for expired in retention.expired(backups) {
+            sink.remove(&expired).await?;
+        }
+
+        Ok(name)
+    }
+
+    /// Creates (or overwrites) the database `db_name` from a backup chain written by `backup_incremental`,
+    /// applying every increment of the chain to its full backup in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the backup chain.
+    /// * `db_name` - The name of the database to restore the chain into, as passed to `JsonDB::new`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the restored `JsonDB`, or an `OhMyDbError::InvalidArchive` if a backup of the
+    /// chain is corrupted.
+    pub async fn restore_chain<P>(dir: P, db_name: &str) -> Result<Self, OhMyDbError>
+    where
+        P: AsRef<Path>,
+    {
+        let (tables, _) = BackupChain::new(dir.as_ref()).restore().await?;
+        let mut db = Self::new(db_name).await?;
+
+        db.tables = tables
+            .keys()
+            .filter(|table| is_user_table(table))
+            .cloned()
+            .collect();
+        db.value = Arc::new(tables);
+        db.save().await?;
+
+        Ok(db)
+    }
+
+    /// Inserts a new record into the JSON database table.
+    ///
+    /// Records that are not JSON objects (strings, numbers, arrays, ...) are stored wrapped as
+    /// `{"id": <hash>, "value": <record>}`, the id being a hash of the record content.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to insert the record into.
+    /// * `item` - The `T` item to insert.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn insert<T>(&self, table: &str, item: &T) -> Query
+    where
+        T: Serialize,
+    {
+        let runner = match serde_json::to_value(item) {
+            Ok(value) => Runner::Method(MethodName::Create(table.to_string(), value, false)),
+            Err(e) => Runner::Invalid(e.to_string()),
+        };
+        Query::new(runner)
+    }
+
+    /// Adds a `Runner::Method(MethodName::Create)` like `insert`, first assigning a random UUID (see `Uuid`)
+    /// to the `id` of the item if it is missing, `null` or empty, e.g. `db.insert_auto_id("todos", &todo)`.
+    ///
+    /// To generate the ids of every insert into a table, configure it with `TableOptions::id_generator(Uuid::default())`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to insert the record into.
+    /// * `item` - The `T` item to insert.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` performing the insert, `run` returning the inserted record with its id.
+    pub fn insert_auto_id<T>(&self, table: &str, item: &T) -> Query
+    where
+        T: Serialize,
+    {
+        let runner = match serde_json::to_value(item) {
+            Ok(value) => Runner::Method(MethodName::Create(
+                table.to_string(),
+                id::assign_id(value, self.primary_key(table), &Uuid::default()),
+                false,
+            )),
+            Err(e) => Runner::Invalid(e.to_string()),
+        };
+        Query::new(runner)
+    }
+
+    /// Inserts a new record into the JSON database table,
+    /// or creates a table first if it does not already exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to insert the record into.
+    /// * `item` - The `T` item to insert or update.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `JsonDb` instance, allowing for method chaining.
+    pub fn insert_or<T>(&self, table: &str, item: &T) -> Query
+    where
+        T: Serialize,
+    {
+        let runner = match serde_json::to_value(item) {
+            Ok(value) => Runner::Method(MethodName::Create(table.to_string(), value, true)),
+            Err(e) => Runner::Invalid(e.to_string()),
+        };
+        Query::new(runner)
+    }
+
+    /// Inserts several records into a table and saves the database file once, instead of once per record.
+    ///
+    /// Each item goes through the same checks as `insert` (table options, constraints, row-level security
+    /// and quotas), including against the items inserted before it. Items failing them are skipped and reported,
+    /// the other ones are inserted. The batch is recorded in the operation history as a single operation.
+    /// Progress is reported per item under the `"insert"` stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to insert the records into.
+    /// * `items` - The `T` items to insert.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::TableNotFound` if the table doesn't exist, inserting nothing,
+    /// or the error saving the database file failed with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BatchReport` of the inserted records and the failed items.
+    pub async fn insert_many<T>(
+        &mut self,
+        table: &str,
+        items: &[T],
+    ) -> Result<BatchReport, OhMyDbError>
+    where
+        T: Serialize,
+    {
+        let started = Instant::now();
+        let mut plan = self.plan(&Query::new(Runner::Method(MethodName::Create(
+            table.to_string(),
+            Value::Null,
+            false,
+        ))));
+        plan.pipeline = format!("InsertMany({:?}, {} items)", table, items.len());
+
+        let outcome = match self.warm_up(&plan).await {
+            Ok(()) => self.insert_batch(&plan, table, items).await,
+            Err(e) => Err(e),
+        };
+        self.finish_operation(&plan, started, 0, &outcome, |report| report.inserted.len())
+            .await;
+
+        outcome
+    }
+
+    /// Inserts the items of `insert_many` one by one, then saves the database file if any was inserted.
+    async fn insert_batch<T>(
+        &mut self,
+        plan: &Plan,
+        table: &str,
+        items: &[T],
+    ) -> Result<BatchReport, OhMyDbError>
+    where
+        T: Serialize,
+    {
+        if !self.value.contains_key(table) {
+            return Err(OhMyDbError::TableNotFound(table.to_string()));
+        }
+
+        let snapshot = Arc::clone(&self.value);
+        let mut writes = RemoteWrites::default();
+        let mut report = BatchReport::default();
+        for (index, item) in items.iter().enumerate() {
+            let inserted = serde_json::to_value(item)
+                .map_err(OhMyDbError::from)
+                .and_then(|item| self.insert_record(plan, table, item, false));
+            match inserted {
+                Ok(stored) => {
+                    self.track_stored(&mut writes, table, &stored);
+                    report
+                        .inserted
+                        .push(self.finalize_read(plan, table, stored));
+                }
+                Err(e) => report.failures.push((index, e)),
+            }
+            self.report_progress("insert", index + 1, items.len());
+        }
+
+        if let Err(e) = self.write_through(&writes).await {
+            self.value = snapshot;
+            return Err(e);
+        }
+
+        if !report.inserted.is_empty() {
+            self.save().await?;
+        }
+
+        Ok(report)
+    }
+
+    /// Starts a read of a table, returning the records matching the filters added to the query,
+    /// e.g. `db.find("todos").where_("is_completed").equals(false)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to read.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` reading the table when run.
+    pub fn find(&self, table: &str) -> Query {
+        Query::new(Runner::Method(MethodName::Read(table.to_string())))
+    }
+
+    /// Returns the record of a table with the provided id, without building a query.
+    ///
+    /// Ids are compared as strings, so `"1"` finds a record whose id is the number `1`. The record is read
+    /// like `db.find(table).first(&mut db)` would read it, so table options and row-level security apply.
+    /// If the table is backed by a `RemoteTier` (see `set_remote_tier`) and has no record with the id,
+    /// the record is looked up in the remote.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to search.
+    /// * `id` - The id of the record.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the record, or `None` if the table has no record with this id.
+    /// Looking a record up in the remote of a table that doesn't exist returns an `OhMyDbError::TableNotFound`
+    /// without reaching the remote.
+    pub async fn find_by_id(
+        &mut self,
+        table: &str,
+        id: &str,
+    ) -> Result<Option<Value>, OhMyDbError> {
+        let key = self.primary_key(table).to_string();
+        let found = self
+            .find(table)
+            .filter_with(has_id(&key, id))
+            .first(self)
+            .await?;
+        if found.is_some() || !self.read_through(table, id).await? {
+            return Ok(found);
+        }
+
+        self.find(table)
+            .filter_with(has_id(&key, id))
+            .first(self)
+            .await
+    }
+
+    /// Returns the record of a table with the provided id like `find_by_id`, deserialized into `T`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to search.
+    /// * `id` - The id of the record.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::Serde` error if the record cannot be deserialized into `T`.
+    pub async fn find_by_id_as<T>(
+        &mut self,
+        table: &str,
+        id: &str,
+    ) -> Result<Option<T>, OhMyDbError>
+    where
+        T: DeserializeOwned,
+    {
+        match self.find_by_id(table, id).await? {
+            Some(value) => Ok(Some(serde_json::from_value(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Translates the name of a field of `T` to the key it is stored under, as declared by the `#[serde(rename)]`
+    /// attributes `define_struct_from!` was given, e.g. `db.resolve_field::<User>("user_name")` returns `"userName"`
+    /// for a field renamed to `userName`, so it can be used with `where_`, `order_by` or `select`.
+    /// Queries can also translate the names they are given on their own, see `Query::entity`.
+    ///
+    /// Stored keys are returned unchanged. Only the first key of a dot-separated chain is translated.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The name of the field in the Rust struct.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::InvalidField` if `T` has no field with this name or stored key.
+    pub fn resolve_field<T>(&self, field: &str) -> Result<String, OhMyDbError>
+    where
+        T: Entity,
+    {
+        fields::resolve::<T>(field)
+    }
+
+    /// Starts an update replacing the record of a table with the same id as `item`, e.g. `db.update("todos", &todo)`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table holding the record.
+    /// * `item` - The new version of the record, which must contain the `id` of the record to replace.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` performing the update when run, returning the updated record. Running it returns an
+    /// `OhMyDbError::RecordNotFound` if the table has no record with this id.
+    pub fn update<T>(&self, table: &str, item: &T) -> Query
+    where
+        T: Serialize,
+    {
+        let runner = match serde_json::to_value(item) {
+            Ok(value) => Runner::Method(MethodName::Update(table.to_string(), value)),
+            Err(e) => Runner::Invalid(e.to_string()),
+        };
+        Query::new(runner)
+    }
+
+    /// Replaces the record of a table with the provided id by `item`, without building a query.
+    ///
+    /// `item` gets the id of the stored record, so it doesn't need one, and the write goes through the same checks
+    /// as `db.update(table, item).run(&mut db)` (table options, constraints, row-level security and quotas).
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to update.
+    /// * `id` - The id of the record, compared as a string like in `find_by_id`.
+    /// * `item` - The new value of the record.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the error the update fails with, e.g. an `OhMyDbError::UniqueViolation`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether a record was updated, `false` if the table has no record with this id.
+    pub async fn update_by_id<T>(
+        &mut self,
+        table: &str,
+        id: &str,
+        item: &T,
+    ) -> Result<bool, OhMyDbError>
+    where
+        T: Serialize,
+    {
+        let mut item = serde_json::to_value(item)?;
+        let (Some(stored_id), Value::Object(fields)) = (self.stored_id(table, id), &mut item)
+        else {
+            return Ok(false);
+        };
+        let key = self.primary_key(table).to_string();
+        fields.insert(key.clone(), stored_id);
+
+        match self
+            .update(table, &item)
+            .filter_with(has_id(&key, id))
+            .run(self)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(OhMyDbError::RecordNotFound { .. }) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the key of the stored record of a table whose key, compared as a string, is `id`,
+    /// with its stored type (e.g. the number `1` for `"1"`).
+    fn stored_id(&self, table: &str, id: &str) -> Option<Value> {
+        let enveloped = self.is_enveloped(table);
+        let key = self.primary_key(table);
+
+        self.value
+            .get(table)?
+            .iter()
+            .map(|record| match enveloped {
+                true => envelope::data(record),
+                false => record,
+            })
+            .find(|data| has_id(key, id)(data))
+            .and_then(|data| get_nested_ref(data, key).cloned())
+    }
+
+    /// Adds a `Runner::Method(MethodName::Upsert)` to the end of the runners queue, replacing the stored record with the
+    /// same id as `item` if there is one and inserting `item` otherwise, in a single operation.
+    ///
+    /// The write goes through the same checks as `update` or `insert` would, depending on which one it turns into.
+    /// Only the records the query selects are replaced: a soft-deleted record (unless `Query::with_deleted` is used)
+    /// or one hidden by the row-level security policy keeps its id taken, so the upsert fails with
+    /// `OhMyDbError::RecordExists` instead of inserting a second record with the same id.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to write to.
+    /// * `item` - The `T` item to insert or replace.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` performing the upsert when run.
+    pub fn upsert<T>(&self, table: &str, item: &T) -> Query
+    where
+        T: Serialize,
+    {
+        let runner = match serde_json::to_value(item) {
+            Ok(value) => Runner::Method(MethodName::Upsert(table.to_string(), value)),
+            Err(e) => Runner::Invalid(e.to_string()),
+        };
+        Query::new(runner)
+    }
+
+    /// Adds a `Runner::Method(MethodName::Merge)` to the end of the runners queue, deep-merging `item` into the stored
+    /// record with the same id instead of replacing it. Fields absent from `item` are kept as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table holding the record.
+    /// * `item` - The partial record to merge, which must contain the `id` of the record to update.
+    /// * `options` - The `MergeOptions` controlling how arrays are combined.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` performing the merge when run, returning the merged record.
+    pub fn merge<T>(&self, table: &str, item: &T, options: MergeOptions) -> Query
+    where
+        T: Serialize,
+    {
+        let runner = match serde_json::to_value(item) {
+            Ok(value) => Runner::Method(MethodName::Merge(table.to_string(), value, options)),
+            Err(e) => Runner::Invalid(e.to_string()),
+        };
+
+        Query::new(runner)
+    }
+
+    /// Applies a JSON Merge Patch (RFC 7386) to the stored record of a table with the provided id,
+    /// e.g. `db.patch("users", "id-1", &json!({"occupation": "CTO"}))`.
+    ///
+    /// Fields absent from the patch are kept, `null` fields are removed from the record, nested objects are patched
+    /// recursively and every other value, arrays included, replaces the stored one. The id of the record is kept.
+    /// The write goes through the same checks as `merge`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table holding the record.
+    /// * `id` - The id of the record, compared as a string like in `find_by_id`.
+    /// * `patch` - The merge patch, which must be an object.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` applying the patch when run. Running it returns an `OhMyDbError::RecordNotFound`
+    /// if the table has no record with this id, or an `OhMyDbError::InvalidRecord` if the patch is not an object.
+    pub fn patch<T>(&self, table: &str, id: &str, patch: &T) -> Query
+    where
+        T: Serialize,
+    {
+        let runner = match serde_json::to_value(patch) {
+            Ok(Value::Object(mut fields)) => {
+                let id = self.stored_id(table, id).unwrap_or_else(|| id.into());
+                fields.insert(self.primary_key(table).to_string(), id);
+                Runner::Method(MethodName::Merge(
+                    table.to_string(),
+                    Value::Object(fields),
+                    MergeOptions::merge_patch(),
+                ))
+            }
+            Ok(_) => Runner::Invalid("a merge patch must be an object".to_string()),
+            Err(e) => Runner::Invalid(e.to_string()),
+        };
+
+        Query::new(runner)
+    }
+
+    /// Applies a JSON Patch (RFC 6902) to the stored record of a table with the provided id,
+    /// e.g. `db.apply_patch("users", "id-1", &json!([{"op": "remove", "path": "/tags/0"}]))`.
+    ///
+    /// The operations (`add`, `remove`, `replace`, `move`, `copy` and `test`) are applied in order to the user data
+    /// of the record, so a failing `test` works as a precondition. Either every operation is applied or,
+    /// if one of them fails, the record is left unchanged. The write goes through the same checks as `update_where`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table holding the record.
+    /// * `id` - The id of the record, compared as a string like in `find_by_id`.
+    /// * `ops` - The patch: a `Vec<PatchOp>`, or any value serializing to an array of JSON Patch operations.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` applying the patch when run, returning the patched record, or no record if the table
+    /// has none with this id. Running it returns an `OhMyDbError::InvalidField` naming the path of the
+    /// operation that failed, or an `OhMyDbError::InvalidRecord` if `ops` is not a valid patch.
+    pub fn apply_patch<T>(&self, table: &str, id: &str, ops: &T) -> Query
+    where
+        T: Serialize + ?Sized,
+    {
+        let mut query = Query::new(Runner::Method(MethodName::Modify(table.to_string())));
+        query.filter_with(has_id(self.primary_key(table), id));
+
+        let runner = match serde_json::to_value(ops).and_then(serde_json::from_value) {
+            Ok(ops) => Runner::Operator(UpdateOp::Patch(ops)),
+            Err(e) => Runner::Invalid(e.to_string()),
+        };
+        query.runners.push_back(runner);
+
+        query
+    }
+
+    /// Adds a `Runner::Method(MethodName::Modify)` to the end of the runners queue, indicating that the current
+    /// operation changes fields of every record selected by the where-chain in place,
+    /// e.g. `db.update_where("todos").where_("id").equals("1").add_to_set("tags", "urgent")`.
+    ///
+    /// The changes are the update operators of the query (`set`, `add_to_set`, `pull_all`, ...), applied in order.
+    /// Either every selected record is updated or, if an operator cannot be applied to one of them, none is.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to update records in.
+    ///
+    /// # Returns
+    ///
+    /// A `Query` performing the update, `run` returning the updated records.
+    pub fn update_where(&self, table: &str) -> Query {
+        Query::new(Runner::Method(MethodName::Modify(table.to_string())))
+    }
+
+    /// Adds a `Runner::Method(MethodName::Modify)` selecting the record of a table with the provided id, to change
+    /// its fields in place, e.g. `db.update_field("counters", "page-1").increment("views", 1).run(&mut db)`.
+    ///
+    /// The changes are the update operators of the query, applied to the stored record while the database is
+    /// borrowed mutably, so concurrent callers can't lose each other's increments. The write goes through the same
+    /// checks as `update_where`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table holding the record.
+    /// * `id` - The id of the record, compared as a string like in `find_by_id`.
+    ///
+    /// # Returns
+    ///
+    /// A `Query` performing the update, `run` returning the updated record, or no record if the table has none with this id.
+    pub fn update_field(&self, table: &str, id: &str) -> Query {
+        let mut query = self.update_where(table);
+        query.filter_with(has_id(self.primary_key(table), id));
+
+        query
+    }
+
+    /// Starts a delete of the records of a table matching the filters added to the query,
+    /// e.g. `db.delete("todos").where_("is_completed").equals(true)`.
+    ///
+    /// Tables with soft deletes (see `TableOptions::soft_delete`) only mark the records as deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to delete records from.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` performing the delete when run, returning the deleted records.
+    pub fn delete(&self, table: &str) -> Query {
+        Query::new(Runner::Method(MethodName::Delete(table.to_string())))
+    }
+
+    /// Adds a `Runner::Method(MethodName::Restore)` to the end of the runners queue, bringing the soft-deleted records
+    /// of a table with soft deletes (see `TableOptions::soft_delete`) matching the filters back to life,
+    /// e.g. `db.restore("todos").where_("id").equals("1")`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table holding the records.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` performing the restore when run, returning the restored records.
+    /// It selects nothing in tables without soft deletes.
+    pub fn restore(&self, table: &str) -> Query {
+        Query::new(Runner::Method(MethodName::Restore(table.to_string())))
+    }
+
+    /// Adds a `Runner::Method(MethodName::Purge)` to the end of the runners queue, removing for good the soft-deleted
+    /// records of a table with soft deletes (see `TableOptions::soft_delete`) matching the filters.
+    ///
+    /// The purge goes through the same checks as a delete from a table without soft deletes, so row-level security
+    /// and the `OnDelete` policies of the relations to the table apply.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table holding the records.
+    ///
+    /// # Returns
+    ///
+    /// A new `Query` performing the purge when run, returning the purged records.
+    /// It selects nothing in tables without soft deletes.
+    pub fn purge(&self, table: &str) -> Query {
+        Query::new(Runner::Method(MethodName::Purge(table.to_string())))
+    }
+
+    /// Deletes the record of a table with the provided id, without building a query.
+    ///
+    /// The delete goes through the same checks as `db.delete(table).run(&mut db)`, so row-level security
+    /// and the `OnDelete` policies of the relations to the table apply.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The name of the table to delete from.
+    /// * `id` - The id of the record, compared as a string like in `find_by_id`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether a record was deleted, `false` if the table has no record with this id.
+    pub async fn delete_by_id(&mut self, table: &str, id: &str) -> Result<bool, OhMyDbError> {
+        let key = self.primary_key(table).to_string();
+        let deleted = self
+            .delete(table)
+            .filter_with(has_id(&key, id))
+            .run(self)
+            .await?;
+
+        Ok(!deleted.is_empty())
+    }
+
+    /// Parses a query written in a small SQL subset into a `Query` performing the operations it stands for,
+    /// e.g. `query("SELECT * FROM todos WHERE is_completed = false AND title LIKE '%milk%' ORDER BY created_at LIMIT 10")`.
+    ///
+    /// Supported: `SELECT *`, a list of fields or `DISTINCT field`, `FROM table`, `WHERE` conditions joined by
+    /// `AND` / `OR` (`AND` binding tighter) using `=`, `!=`, `<>`, `<`, `<=`, `>`, `>=`, `[NOT] LIKE`, `[NOT] IN (...)`,
+    /// `[NOT] BETWEEN .. AND ..` and `IS [NOT] NULL`, `ORDER BY field [ASC | DESC], ...`, `LIMIT` and `OFFSET`.
+    /// Keywords are case-insensitive and fields may be nested, dot-separated key chains.
+    ///
+    /// If the query can't be parsed, running it returns `OhMyDbError::InvalidPattern`.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - The query to parse.
+    ///
+    /// # Returns
+    ///
+    /// A `Query` performing the parsed operations.
+    pub fn query(&self, sql: &str) -> Query {
+        let runners = sql::parse(sql).unwrap_or_else(|reason| {
+            vec![Runner::InvalidPattern {
+                pattern: sql.to_string(),
+                reason,
+            }]
+        });
+
+        Query {
+            runners: runners.into(),
+            ..Query::default()
+        }
+    }
+
+    /// Runs `query`, recording it in the operation history and the slow query log.
+    pub(crate) async fn run_query(&mut self, query: &Query) -> Result<Vec<Value>, OhMyDbError> {
+        self.run_query_outcome(query)
+            .await
+            .map(|outcome| outcome.records)
+    }
+
+    /// Runs `query` like `run_query`, returning what it did.
+    pub(crate) async fn run_query_outcome(
+        &mut self,
+        query: &Query,
+    ) -> Result<RunOutcome, OhMyDbError> {
+        let started = Instant::now();
+        let plan = self.plan(query);
+        let outcome = match self.warm_up(&plan).await {
+            Ok(()) => self.execute(&plan).await,
+            Err(e) => Err(e),
+        };
+        let matched = outcome.as_ref().map_or(0, |(_, matched)| *matched);
+        let outcome = outcome.map(|(outcome, _)| outcome);
+
+        self.finish_operation(&plan, started, matched, &outcome, |outcome| {
+            outcome.records.len()
+        })
+        .await;
+
+        outcome
+    }
+
+    /// Executes the operation described by `plan`,
+    /// returning what it did and the number of records that matched the filters.
+    async fn execute(&mut self, plan: &Plan) -> Result<(RunOutcome, usize), OhMyDbError> {
+        let mut result: Vec<Value> = self.select_records(plan)?.into_iter().cloned().collect();
+        let matched = result.len();
+        let mut previous = Vec::new();
+        let mut affected = 0;
+        // Taking the data is cheap (copy-on-write), and restores it if the remote tier rejects the writes.
+        let snapshot = Arc::clone(&self.value);
+        let mut writes = RemoteWrites::default();
+
+        // An upsert replaces the selected record with the same id if there is one, and inserts the item otherwise.
+        let method = match plan.method.clone() {
+            Some(MethodName::Upsert(table, item)) => {
+                let id = record_id(&item, self.primary_key(&table));
+                let exists = result
+                    .iter()
+                    .any(|t| id.is_some() && self.record_key(&table, t) == id);
+                Some(match exists {
+                    true => MethodName::Update(table, item),
+                    false => MethodName::Create(table, item, false),
+                })
+            }
+            method => method,
+        };
+
+        match method {
+            Some(MethodName::Read(table)) => {
+                result = result
+                    .into_iter()
+                    .map(|item| self.finalize_read(plan, &table, item))
+                    .collect();
+                self.notify(&MethodName::Read(table));
+            }
+            Some(MethodName::Create(table, new_item, or)) => {
+                if let Some(original) = self.idempotent_insert(plan, &table) {
+                    let records = vec![self.finalize_read(plan, &table, original)];
+                    return Ok((
+                        RunOutcome {
+                            records,
+                            ..RunOutcome::default()
+                        },
+                        matched,
+                    ));
+                }
+                let new_item = self.insert_record(plan, &table, new_item, or)?;
+                if let Some(key) = &plan.idempotency_key {
+                    let at = unix_millis(self.now());
+                    self.reserved_table_mut(IDEMPOTENCY_TABLE).insert(json!({
+                        "id": idempotency_id(&table, key),
+                        "record": new_item,
+                        "inserted_at": at,
+                    }));
+                }
+                affected = 1;
+                self.track_stored(&mut writes, &table, &new_item);
+                result = vec![self.finalize_read(plan, &table, new_item.clone())];
+                self.notify(&MethodName::Create(table, new_item, or));
+            }
+            Some(MethodName::Update(table, new_item)) => {
+                let new_item = self.prepare_write(&table, new_item)?;
+                self.check_write(plan, &table, &new_item)?;
+                self.check_constraints(&table, &new_item)?;
+                self.check_unique(&table, &new_item, &[])?;
+                let key = self.primary_key(&table);
+                let new_item_id = record_id(&new_item, key)
+                    .ok_or_else(|| OhMyDbError::MissingField(key.to_string()))?;
+
+                let existing = self.find_by_id_in(&result, &table, &new_item_id)?;
+                let mut stored = match plan.envelope {
+                    true => envelope::rewrap(existing, new_item.clone(), self.clock.now()),
+                    false => new_item.clone(),
+                };
+                expiry::keep(existing, &mut stored);
+                self.check_quotas(&table, &[existing], std::slice::from_ref(&stored))?;
+                previous.push(self.finalize_read(plan, &table, existing.clone()));
+                self.track_stored(&mut writes, &table, &stored);
+                result = vec![self.finalize_read(plan, &table, stored.clone())];
+                self.replace_record(&table, &new_item_id, stored)?;
+
+                affected = 1;
+
+                self.notify(&MethodName::Update(table, new_item));
+            }
+            Some(MethodName::Merge(table, patch, options)) => {
+                let key = self.primary_key(&table);
+                let id = record_id(&patch, key)
+                    .ok_or_else(|| OhMyDbError::MissingField(key.to_string()))?;
+                let existing = self.find_by_id_in(&result, &table, &id)?;
+                let mut new_item = match plan.envelope {
+                    true => envelope::data(existing).clone(),
+                    false => existing.clone(),
+                };
+                expiry::strip(&mut new_item);
+
+                deep_merge(&mut new_item, patch, &options);
+
+                let new_item = self.prepare_write(&table, new_item)?;
+                self.check_write(plan, &table, &new_item)?;
+                self.check_constraints(&table, &new_item)?;
+                self.check_unique(&table, &new_item, &[])?;
+                let mut stored = match plan.envelope {
+                    true => envelope::rewrap(existing, new_item.clone(), self.clock.now()),
+                    false => new_item.clone(),
+                };
+                expiry::keep(existing, &mut stored);
+                self.check_quotas(&table, &[existing], std::slice::from_ref(&stored))?;
+                previous.push(self.finalize_read(plan, &table, existing.clone()));
+                self.track_stored(&mut writes, &table, &stored);
+                result = vec![self.finalize_read(plan, &table, stored.clone())];
+                self.replace_record(&table, &id, stored)?;
+
+                affected = 1;
+
+                self.notify(&MethodName::Merge(table, new_item, options));
+            }
+            Some(MethodName::Modify(table)) => {
+                let mut changes = Vec::with_capacity(result.len());
+
+                for existing in &result {
+                    let mut new_item = self.prepare_read(&table, existing.clone());
+                    for operator in &plan.operators {
+                        operator.apply(&mut new_item)?;
+                    }
+
+                    let new_item = self.prepare_write(&table, new_item)?;
+                    self.check_write(plan, &table, &new_item)?;
+                    self.check_constraints(&table, &new_item)?;
+                    changes.push((existing, new_item));
+                }
+
+                let written: Vec<Value> = changes.iter().map(|(_, item)| item.clone()).collect();
+                for item in &written {
+                    self.check_unique(&table, item, &written)?;
+                }
+                let now = self.clock.now();
+                let changes: Vec<(&Value, Value)> = changes
+                    .into_iter()
+                    .map(|(existing, new_item)| {
+                        let mut stored = match plan.envelope {
+                            true => envelope::rewrap(existing, new_item, now),
+                            false => new_item,
+                        };
+                        expiry::keep(existing, &mut stored);
+                        (existing, stored)
+                    })
+                    .collect();
+                let replaced: Vec<&Value> = changes.iter().map(|(existing, _)| *existing).collect();
+                let stored: Vec<Value> = changes.iter().map(|(_, stored)| stored.clone()).collect();
+                self.check_quotas(&table, &replaced, &stored)?;
+                previous = replaced
+                    .iter()
+                    .map(|existing| self.finalize_read(plan, &table, (*existing).clone()))
+                    .collect();
+                for stored in &stored {
+                    self.track_stored(&mut writes, &table, stored);
+                }
+
+                let table_hash = self.get_table_mut(&table)?;
+                for (existing, _) in &changes {
+                    table_hash.remove(*existing);
+                }
+                let updated: Vec<Value> = changes.into_iter().map(|(_, stored)| stored).collect();
+                table_hash.extend(updated.iter().cloned());
+
+                affected = updated.len();
+                result = updated
+                    .into_iter()
+                    .map(|item| self.finalize_read(plan, &table, item))
+                    .collect();
+
+                self.notify(&MethodName::Modify(table));
+            }
+            Some(MethodName::Delete(table)) if plan.soft_delete => {
+                // Records that are not objects can't carry the marker, so they are left as they are.
+                result.retain(Value::is_object);
+                let deleted_at = Value::from(envelope::unix_millis(self.clock.now()));
+                previous = result
+                    .iter()
+                    .map(|record| self.finalize_read(plan, &table, record.clone()))
+                    .collect();
+                affected = result.len();
+
+                let marked: Vec<Value> = result
+                    .iter()
+                    .map(|record| {
+                        let mut marked = record.clone();
+                        if let Some(fields) = marked.as_object_mut() {
+                            fields.insert(DELETED_AT.to_string(), deleted_at.clone());
+                        }
+                        marked
+                    })
+                    .collect();
+                for record in &marked {
+                    self.track_stored(&mut writes, &table, record);
+                }
+                let table_hash = self.get_table_mut(&table)?;
+                for record in &result {
+                    table_hash.remove(record);
+                }
+                table_hash.extend(marked);
+
+                self.notify(&MethodName::Delete(table));
+            }
+            Some(MethodName::Restore(table)) => {
+                let enveloped = plan.envelope;
+                let restored: Vec<Value> = result
+                    .iter()
+                    .map(|record| {
+                        let mut restored = record.clone();
+                        match (enveloped, &mut restored) {
+                            (true, Value::Object(fields)) => {
+                                fields.insert(DELETED_AT.to_string(), Value::Null);
+                            }
+                            (false, Value::Object(fields)) => {
+                                fields.remove(DELETED_AT);
+                            }
+                            (_, _) => {}
+                        }
+                        restored
+                    })
+                    .collect();
+                for record in &restored {
+                    self.track_stored(&mut writes, &table, record);
+                }
+                let table_hash = self.get_table_mut(&table)?;
+                for record in &result {
+                    table_hash.remove(record);
+                }
+                table_hash.extend(restored.iter().cloned());
+
+                affected = restored.len();
+                result = restored
+                    .into_iter()
+                    .map(|record| self.finalize_read(plan, &table, record))
+                    .collect();
+
+                self.notify(&MethodName::Restore(table));
+            }
+            Some(method @ (MethodName::Delete(_) | MethodName::Purge(_))) => {
+                let table = method.table().to_string();
+                // The selected records are clones of the stored ones, so they identify them exactly,
+                // even when a record has no usable id.
+                let cascade = self.plan_cascade(&table, &result)?;
+                previous = result
+                    .iter()
+                    .map(|record| self.finalize_read(plan, &table, record.clone()))
+                    .collect();
+                affected = result.len();
+
+                for (name, deleted) in &cascade.deleted {
+                    for record in deleted {
+                        self.track_removed(&mut writes, name, record);
+                    }
+                    self.get_table_mut(name)?.retain(|t| !deleted.contains(t));
+                }
+                for (name, detached) in cascade.detached {
+                    let deleted = cascade.deleted.get(&name);
+                    for (old, new) in &detached {
+                        if deleted.is_none_or(|deleted| !deleted.contains(old)) {
+                            self.track_stored(&mut writes, &name, new);
+                        }
+                    }
+                    let table_hash = self.get_table_mut(&name)?;
+                    for (old, new) in detached {
+                        if deleted.is_none_or(|deleted| !deleted.contains(&old)) {
+                            table_hash.remove(&old);
+                            table_hash.insert(new);
                         }
-                        Some(MethodName::Create(table, ref new_item, or)) => {
-                            self.insert_into_table(table.as_str(), &new_item, or)?;
-                            MethodName::Create(table, new_item.clone(), or).notify();
+                    }
+                }
+
+                self.notify(&method);
+            }
+            Some(MethodName::Upsert(..)) => {
+                unreachable!("upserts are resolved into updates or inserts")
+            }
+            None => {}
+        }
+
+        if !writes.is_empty() {
+            if let Err(e) = self.write_through(&writes).await {
+                self.value = snapshot;
+                return Err(e);
+            }
+        }
+
+        for event in &plan.events {
+            self.push_event(event.clone());
+        }
+        self.save().await?;
+
+        Ok((
+            RunOutcome {
+                records: result,
+                previous,
+                affected,
+            },
+            matched,
+        ))
+    }
+
+    /// Prepares `item`, checks it against the policies and constraints of the table and inserts it,
+    /// returning the stored record. Nothing is saved or notified.
+    fn insert_record(
+        &mut self,
+        plan: &Plan,
+        table: &str,
+        item: Value,
+        or: bool,
+    ) -> Result<Value, OhMyDbError> {
+        let mut item = wrap_record(item);
+        if let Some(template) = self.templates.get(table).filter(|t| t.is_object()) {
+            let mut defaults = template.clone();
+            deep_merge(&mut defaults, item, &MergeOptions::new());
+            item = defaults;
+        }
+        let auto_increment = self
+            .options
+            .get(table)
+            .is_some_and(TableOptions::is_auto_increment);
+        if auto_increment && id::needs_id(&item, self.primary_key(table)) {
+            let next = Value::from(self.next_sequence(table));
+            if let Some(id) = get_nested_entry(&mut item, self.primary_key(table)) {
+                *id = next;
+            }
+        }
+
+        let mut new_item = self.prepare_insert(table, item)?;
+        self.check_write(plan, table, &new_item)?;
+        self.check_constraints(table, &new_item)?;
+        self.check_unique(table, &new_item, &[])?;
+        if plan.envelope {
+            new_item = envelope::wrap(new_item, self.clock.now());
+        }
+        if let Some(at) = plan.expires_at {
+            expiry::set(&mut new_item, at);
+        }
+        self.check_quotas(table, &[], std::slice::from_ref(&new_item))?;
+        self.insert_into_table(table, &new_item, or)?;
+
+        Ok(new_item)
+    }
+
+    /// Returns the next value of the auto-increment id sequence of a table, persisted in the reserved sequences table.
+    fn next_sequence(&mut self, table_name: &str) -> u64 {
+        let last = match self.reserved_table(SEQUENCES_TABLE).and_then(|sequences| {
+            sequences
+                .iter()
+                .find(|sequence| record_id(sequence, "id").as_deref() == Some(table_name))
+        }) {
+            Some(sequence) => sequence["last"].as_u64().unwrap_or(0),
+            // Start after the largest integer id already stored, e.g. by inserts made before enabling the sequence.
+            None => self
+                .value
+                .get(table_name)
+                .into_iter()
+                .flatten()
+                .filter_map(|record| {
+                    self.record_field(table_name, record, self.primary_key(table_name))?
+                        .as_u64()
+                })
+                .max()
+                .unwrap_or(0),
+        };
+
+        let sequences = self.reserved_table_mut(SEQUENCES_TABLE);
+        sequences.retain(|sequence| record_id(sequence, "id").as_deref() != Some(table_name));
+        sequences.insert(json!({ "id": table_name, "last": last + 1 }));
+
+        last + 1
+    }
+
+    /// Returns the stored record inserted by an earlier insert into `table` with the idempotency key of the plan, if any.
+    fn idempotent_insert(&self, plan: &Plan, table: &str) -> Option<Value> {
+        let id = idempotency_id(table, plan.idempotency_key.as_ref()?);
+
+        self.reserved_table(IDEMPOTENCY_TABLE)?
+            .iter()
+            .find(|entry| entry["id"] == id.as_str())
+            .map(|entry| entry["record"].clone())
+    }
+
+    /// Checks that the row-level security policy of the table, if any, lets the context of the plan
+    /// write `item`, reporting an `OhMyDbError::AccessDenied` otherwise.
+    fn check_write(&self, plan: &Plan, table: &str, item: &Value) -> Result<(), OhMyDbError> {
+        let policy = self.options.get(table).and_then(TableOptions::policy);
+
+        match policy.is_none_or(|p| p.allows(&plan.context, item)) {
+            true => Ok(()),
+            false => Err(OhMyDbError::AccessDenied {
+                table: table.to_string(),
+            }),
+        }
+    }
+
+    /// Checks that `item` (the user data of a record) satisfies every check constraint of the table,
+    /// reporting an `OhMyDbError::CheckViolation` naming the first one it violates otherwise.
+    fn check_constraints(&self, table: &str, item: &Value) -> Result<(), OhMyDbError> {
+        let checks = self.checks.get(table).into_iter().flatten();
+
+        match checks.into_iter().find(|check| !check.holds(item)) {
+            None => Ok(()),
+            Some(check) => Err(OhMyDbError::CheckViolation {
+                table: table.to_string(),
+                check: check.expression().to_string(),
+            }),
+        }
+    }
+
+    /// Checks that replacing the `removed` stored records of a table with the `added` ones keeps it within its quotas,
+    /// reporting an `OhMyDbError::QuotaExceeded` naming the first quota it would exceed otherwise.
+    fn check_quotas(
+        &self,
+        table: &str,
+        removed: &[&Value],
+        added: &[Value],
+    ) -> Result<(), OhMyDbError> {
+        let Some(options) = self.options.get(table) else {
+            return Ok(());
+        };
+        let kept = || {
+            self.value
+                .get(table)
+                .into_iter()
+                .flatten()
+                .filter(|record| !removed.contains(record))
+        };
+        let size = |record: &Value| serde_json::to_string(record).map_or(0, |json| json.len());
+
+        for quota in options.quotas() {
+            let within = match *quota {
+                Quota::Records(max) => kept().count() + added.len() <= max,
+                Quota::RecordBytes(max) => added.iter().all(|record| size(record) <= max),
+                Quota::TableBytes(max) => kept().chain(added).map(size).sum::<usize>() <= max,
+            };
+            if !within {
+                return Err(OhMyDbError::QuotaExceeded {
+                    table: table.to_string(),
+                    quota: *quota,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collects the changes deleting the `doomed` records of `table` makes to the records referencing them,
+    /// following the `OnDelete` policies of the declared relations, transitively for cascaded deletes.
+    ///
+    /// Nothing is changed yet, so a `Restrict` relation can fail the whole delete with `OhMyDbError::StillReferenced`.
+    fn plan_cascade(&self, table: &str, doomed: &[Value]) -> Result<Cascade, OhMyDbError> {
+        let mut cascade = Cascade::default();
+        cascade
+            .deleted
+            .insert(table.to_string(), doomed.iter().cloned().collect());
+        let mut queue = VecDeque::from([(table.to_string(), doomed.to_vec())]);
+
+        while let Some((table, deleted)) = queue.pop_front() {
+            for (referencing, options) in &self.options {
+                let Some(records) = self.value.get(referencing) else {
+                    continue;
+                };
+
+                for (field, relation) in options.relations() {
+                    let action = relation.delete_action();
+                    if relation.table() != table || action == OnDelete::NoAction {
+                        continue;
+                    }
+                    let keys: Vec<&Value> = deleted
+                        .iter()
+                        .filter_map(|record| self.record_field(&table, record, relation.field()))
+                        .filter(|key| !key.is_null())
+                        .collect();
+                    let mut cascaded = Vec::new();
+
+                    for record in records {
+                        if cascade
+                            .deleted
+                            .get(referencing)
+                            .is_some_and(|deleted| deleted.contains(record))
+                        {
+                            continue;
                         }
-                        Some(MethodName::Update(table, new_item)) => {
-                            let new_item_id: Value =
-                                get_nested_value(new_item.clone(), "id").unwrap();
-                            let search_result = result
+                        let current = cascade
+                            .detached
+                            .get(referencing)
+                            .and_then(|detached| detached.get(record))
+                            .unwrap_or(record);
+                        let references = match self.record_field(referencing, current, field) {
+                            Some(Value::Array(items)) => items
                                 .iter()
-                                .find(|t| {
-                                    let current_item_id: Value = get_nested_value(t, "id").unwrap();
-                                    current_item_id.as_str().unwrap()
-                                        == new_item_id.as_str().unwrap()
-                                })
-                                .ok_or(io::Error::new(
-                                    ErrorKind::NotFound,
-                                    format!(
-                                        "Schade! Record with id \"{}\" not found in table {}",
-                                        new_item_id.as_str().unwrap(),
-                                        table.bright_cyan().bold()
-                                    ),
-                                ));
-
-                            match search_result {
-                                Ok(search_value) => {
-                                    let table_hash = self.get_table_mut(&table)?;
-                                    let search_value_id: Value =
-                                        get_nested_value(search_value, "id").unwrap();
-
-                                    table_hash.retain(|t| {
-                                        let current_id: Value = get_nested_value(t, "id").unwrap();
-                                        current_id.as_str().unwrap()
-                                            != search_value_id.as_str().unwrap()
-                                    });
-
-                                    table_hash.insert(new_item.clone());
-
-                                    result.clear();
-                                    result.push(new_item.clone());
-
-                                    MethodName::Update(table, new_item.to_owned()).notify();
-                                }
-
-                                Err(err) => {
-                                    println!(
-                                        "{}  {} {}\n\t\t{} {}\n",
-                                        "(update_table)".bright_cyan().bold(),
-                                        "✗".bright_red().bold(),
-                                        err.to_string().bright_red().bold(),
-                                        "✔".bright_green().bold().blink(),
-                                        "Consider adding new record".bright_green().bold()
-                                    );
-                                    return Err(err);
-                                }
-                            };
+                                .any(|item| keys.iter().any(|key| values_equal(item, key))),
+                            Some(value) => keys.iter().any(|key| values_equal(value, key)),
+                            None => false,
+                        };
+                        if !references {
+                            continue;
                         }
-                        Some(MethodName::Delete(table)) => {
-                            let table_hash = self.get_table_mut(&table)?;
-
-                            for r in result.iter() {
-                                table_hash.retain(|t| {
-                                    let t_id: Value = get_nested_value(t, "id").unwrap();
-                                    let r_id: Value = get_nested_value(r, "id").unwrap();
-                                    t_id.as_str().unwrap() != r_id.as_str().unwrap()
-                                });
-                            }
 
-                            MethodName::Delete(table).notify();
+                        match action {
+                            OnDelete::Restrict => {
+                                return Err(OhMyDbError::StillReferenced {
+                                    table: table.clone(),
+                                    referencing_table: referencing.clone(),
+                                    field: field.clone(),
+                                })
+                            }
+                            OnDelete::Cascade => cascaded.push(record.clone()),
+                            OnDelete::SetNull => {
+                                let detached = self.detach(referencing, current, field, &keys);
+                                cascade
+                                    .detached
+                                    .entry(referencing.clone())
+                                    .or_default()
+                                    .insert(record.clone(), detached);
+                            }
+                            OnDelete::NoAction => {}
                         }
-                        _ => {}
                     }
 
-                    self.save().await?;
+                    if !cascaded.is_empty() {
+                        cascade
+                            .deleted
+                            .entry(referencing.clone())
+                            .or_default()
+                            .extend(cascaded.iter().cloned());
+                        queue.push_back((referencing.clone(), cascaded));
+                    }
+                }
+            }
+        }
+
+        Ok(cascade)
+    }
+
+    /// Returns a copy of a stored record of `table` whose `field` no longer references any of `keys`:
+    /// the field is set to `null`, or the references are removed from it if it holds an array.
+    fn detach(&self, table: &str, record: &Value, field: &str, keys: &[&Value]) -> Value {
+        let enveloped = self.is_enveloped(table);
+        let mut data = match enveloped {
+            true => envelope::data(record).clone(),
+            false => record.clone(),
+        };
+
+        if let Some(value) = get_nested_mut(&mut data, field) {
+            match value {
+                Value::Array(items) => {
+                    items.retain(|item| !keys.iter().any(|key| values_equal(item, key)))
+                }
+                value => *value = Value::Null,
+            }
+        }
+
+        let mut detached = match enveloped {
+            true => envelope::rewrap(record, data, self.clock.now()),
+            false => data,
+        };
+        expiry::keep(record, &mut detached);
+
+        detached
+    }
+
+    /// Checks that storing `item` (the user data of a record) keeps the unique constraints of the table satisfied,
+    /// reporting an `OhMyDbError::UniqueViolation` otherwise.
+    ///
+    /// Stored records with the same key as `item` are the ones it replaces, so they are ignored. `batch` holds the user data
+    /// of every record written together with `item`: their stored versions are ignored too, and their new values are checked instead.
+    fn check_unique(&self, table: &str, item: &Value, batch: &[Value]) -> Result<(), OhMyDbError> {
+        let Some(options) = self.options.get(table) else {
+            return Ok(());
+        };
+        let primary_key = self.primary_key(table);
+        let id = record_id(item, primary_key);
+        let batch_ids: HashSet<String> = batch
+            .iter()
+            .filter_map(|r| record_id(r, primary_key))
+            .collect();
+
+        // A missing field and a `null` one are the same key part, compared according to the constraint.
+        fn key_part<'a>(data: &'a Value, field: &str) -> Option<&'a Value> {
+            get_nested_ref(data, field).filter(|value| !value.is_null())
+        }
+
+        for (fields, nulls) in options.unique_constraints() {
+            let key: Vec<Option<&Value>> =
+                fields.iter().map(|field| key_part(item, field)).collect();
+            if *nulls == Nulls::Distinct && key.iter().any(Option::is_none) {
+                continue;
+            }
+            let same_key = |data: &Value| {
+                fields
+                    .iter()
+                    .zip(&key)
+                    .all(|(field, value)| match (key_part(data, field), value) {
+                        (Some(other), Some(value)) => values_equal(other, value),
+                        (other, value) => other.is_none() && value.is_none(),
+                    })
+            };
+            let stored = self
+                .value
+                .get(table)
+                .into_iter()
+                .flatten()
+                .map(|record| match options.is_enveloped() {
+                    true => envelope::data(record),
+                    false => record,
+                })
+                .filter(|data| {
+                    let other = record_id(data, primary_key);
+                    other != id && other.is_none_or(|other| !batch_ids.contains(&other))
+                });
+            let written = batch
+                .iter()
+                .filter(|data| record_id(data, primary_key) != id);
+
+            if stored.chain(written).any(same_key) {
+                return Err(OhMyDbError::UniqueViolation {
+                    table: table.to_string(),
+                    fields: fields.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Finds the record with the provided id among the selected records of an update,
+    /// reporting an `OhMyDbError::RecordNotFound` if there is none.
+    fn find_by_id_in<'a>(
+        &self,
+        records: &'a [Value],
+        table: &str,
+        id: &str,
+    ) -> Result<&'a Value, OhMyDbError> {
+        records
+            .iter()
+            .find(|t| self.record_key(table, t).as_deref() == Some(id))
+            .ok_or_else(|| {
+                let err = OhMyDbError::RecordNotFound {
+                    table: table.to_string(),
+                    id: id.to_string(),
+                };
+                if self.logging {
+                    println!(
+                        "{}  {} {}\n\t\t{} {}\n",
+                        "(update_table)".bright_cyan().bold(),
+                        "✗".bright_red().bold(),
+                        err.to_string().bright_red().bold(),
+                        "✔".bright_green().bold().blink(),
+                        "Consider adding new record".bright_green().bold()
+                    );
+                }
+                err
+            })
+    }
+
+    /// Replaces the record with the provided id in the table by `new_item`.
+    fn replace_record(
+        &mut self,
+        table: &str,
+        id: &str,
+        new_item: Value,
+    ) -> Result<(), OhMyDbError> {
+        let key = self.primary_key(table).to_string();
+        let enveloped = self.is_enveloped(table);
+        let table_hash = self.get_table_mut(table)?;
+
+        table_hash.retain(|t| {
+            let data = match enveloped {
+                true => envelope::data(t),
+                false => t,
+            };
+            record_id(data, &key).as_deref() != Some(id)
+        });
+        table_hash.insert(new_item);
+
+        Ok(())
+    }
+
+    /// Counts the records matching `query` without cloning or writing anything.
+    pub(crate) async fn count_query(&mut self, query: &Query) -> Result<usize, OhMyDbError> {
+        let started = Instant::now();
+        let plan = self.plan(query);
+        let warmed = self.warm_up(&plan).await;
+        let outcome = warmed
+            .and_then(|()| self.select_records(&plan))
+            .map(|records| records.len());
+
+        if let Some(MethodName::Read(table)) = &plan.method {
+            self.notify(&MethodName::Read(table.clone()));
+        }
+
+        let matched = outcome.as_ref().map_or(0, |count| *count);
+        self.finish_operation(&plan, started, matched, &outcome, |count| *count)
+            .await;
+
+        outcome
+    }
+
+    /// Returns the first record matching `query`.
+    pub(crate) async fn first_query(
+        &mut self,
+        query: &Query,
+    ) -> Result<Option<Value>, OhMyDbError> {
+        let started = Instant::now();
+        let mut plan = self.plan(query);
+        plan.limit = Some(plan.limit.unwrap_or(1).min(1));
+
+        let warmed = self.warm_up(&plan).await;
+        let outcome = warmed
+            .and_then(|()| self.select_records(&plan))
+            .map(|records| {
+                let first = records.first().map(|v| (*v).clone());
+                match (&plan.method, first) {
+                    (Some(method), Some(item)) => {
+                        Some(self.finalize_read(&plan, method.table(), item))
+                    }
+                    (_, first) => first,
+                }
+            });
+
+        if let Some(MethodName::Read(table)) = &plan.method {
+            self.notify(&MethodName::Read(table.clone()));
+        }
+
+        let matched = outcome
+            .as_ref()
+            .map_or(0, |first| usize::from(first.is_some()));
+        self.finish_operation(&plan, started, matched, &outcome, |_| matched)
+            .await;
+
+        outcome
+    }
+
+    /// Returns the unique values of `field` across the records matching `query`.
+    pub(crate) async fn distinct_values_query(
+        &mut self,
+        query: &Query,
+        field: &str,
+    ) -> Result<Vec<Value>, OhMyDbError> {
+        let started = Instant::now();
+        let field = &query.stored_key(field);
+        let mut plan = self.plan(query);
+        plan.distinct = Some(field.clone());
+
+        let warmed = self.warm_up(&plan).await;
+        let outcome = warmed
+            .and_then(|()| self.select_records(&plan))
+            .map(|records| {
+                // Decoding may map different stored values to the same one.
+                let mut seen = HashSet::new();
+                records
+                    .into_iter()
+                    .filter_map(|record| self.read_field(&plan, record, field))
+                    .filter(|value| !value.is_null() && seen.insert(value.clone()))
+                    .collect::<Vec<Value>>()
+            });
 
-                    break;
+        if let Some(MethodName::Read(table)) = &plan.method {
+            self.notify(&MethodName::Read(table.clone()));
+        }
+
+        let matched = outcome.as_ref().map_or(0, Vec::len);
+        self.finish_operation(&plan, started, matched, &outcome, Vec::len)
+            .await;
+
+        outcome
+    }
+
+    /// Returns the numeric values of `field` across the records matching `query`, skipping records where it is not a number.
+    pub(crate) async fn numbers_query(
+        &mut self,
+        query: &Query,
+        field: &str,
+    ) -> Result<Vec<Number>, OhMyDbError> {
+        let started = Instant::now();
+        let field = &query.stored_key(field);
+        let plan = self.plan(query);
+
+        let warmed = self.warm_up(&plan).await;
+        let outcome = warmed
+            .and_then(|()| self.select_records(&plan))
+            .map(|records| {
+                records
+                    .into_iter()
+                    .filter_map(|record| match self.read_field(&plan, record, field) {
+                        Some(Value::Number(n)) => Some(n),
+                        _ => None,
+                    })
+                    .collect::<Vec<Number>>()
+            });
+
+        if let Some(MethodName::Read(table)) = &plan.method {
+            self.notify(&MethodName::Read(table.clone()));
+        }
+
+        let matched = outcome.as_ref().map_or(0, Vec::len);
+        self.finish_operation(&plan, started, matched, &outcome, Vec::len)
+            .await;
+
+        outcome
+    }
+
+    /// Returns the value of `field` in a record selected by `plan`, decoded like the records returned by reads.
+    ///
+    /// Records are only cloned to be decoded if a codec of the table changes them.
+    fn read_field(&self, plan: &Plan, record: &Value, field: &str) -> Option<Value> {
+        let table = plan.method.as_ref().map_or("", MethodName::table);
+        match self.options.get(table) {
+            Some(options) if options.decodes() => {
+                get_nested_ref(&self.prepare_read(table, record.clone()), field).cloned()
+            }
+            _ => plan.field(record, field).cloned(),
+        }
+    }
+
+    /// Describes what running `query` would do, without running it.
+    pub(crate) fn explain_query(&self, query: &Query) -> Result<Explain, OhMyDbError> {
+        let plan = self.plan(query);
+        plan.check()?;
+
+        let table = plan
+            .method
+            .as_ref()
+            .map(|method| method.table().to_string());
+        let estimated_scan = table
+            .as_ref()
+            .and_then(|table| self.value.get(table))
+            .map_or(0, HashSet::len);
+        let groups: Vec<&Vec<(Field, Comparator)>> = plan
+            .filters
+            .iter()
+            .filter(|group| !group.is_empty())
+            .collect();
+        let filters = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|(field, comparator)| match field {
+                        Field::Key(key) => format!("{} {:?}", key, comparator),
+                        Field::Path(path) => format!("{} {:?}", path, comparator),
+                        Field::Record => format!("<record> {:?}", comparator),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // Estimates use the cached statistics even if they are stale, as computing them would need `&mut self`.
+        let selectivities: Vec<Vec<Option<f64>>> = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|(field, comparator)| match (field, &table) {
+                        (Field::Key(key), Some(table)) => self
+                            .stats
+                            .get(&(table.clone(), key.clone()))
+                            .and_then(|cached| cached.stats.selectivity(comparator)),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .collect();
+        let rows = |selectivity: f64| (selectivity * estimated_scan as f64).round() as usize;
+        let estimated_matches = selectivities
+            .iter()
+            .map(|group| group.iter().map(|s| s.map(rows)).collect())
+            .collect();
+        let matching = match selectivities.is_empty() {
+            true => 1.0,
+            false => {
+                let missed: f64 = selectivities
+                    .iter()
+                    .map(|group| 1.0 - group.iter().map(|s| s.unwrap_or(1.0)).product::<f64>())
+                    .product();
+                1.0 - missed
+            }
+        };
+
+        Ok(Explain {
+            operation: plan.method.as_ref().map(MethodName::name),
+            table,
+            filters,
+            order_by: plan.order_by,
+            skip: plan.skip,
+            limit: plan.limit,
+            select: plan.select,
+            distinct: plan.distinct,
+            has_statistics: selectivities.iter().flatten().any(Option::is_some),
+            estimated_scan,
+            estimated_matches,
+            estimated_rows: rows(matching),
+        })
+    }
+
+    /// Returns the shape of the records `query` returns, see `Query::run_records`: whether they are whole envelopes
+    /// (`with_meta` on an enveloped table), and the primary key of their table.
+    pub(crate) fn record_shape(&self, query: &Query) -> (bool, String) {
+        let plan = self.plan(query);
+        let key = plan
+            .method
+            .as_ref()
+            .map_or("id", |method| self.primary_key(method.table()));
+
+        (plan.envelope && plan.with_meta, key.to_string())
+    }
+
+    /// Builds the `Plan` describing the operation `query` performs.
+    fn plan(&self, query: &Query) -> Plan {
+        let mut plan = Plan {
+            pipeline: format!("{:?}", query.runners),
+            cancellation: query.cancellation.clone(),
+            context: query.context.clone().unwrap_or_default(),
+            idempotency_key: query.idempotency_key.clone(),
+            expires_at: query
+                .expires_in
+                .map(|ttl| envelope::unix_millis(self.clock.now() + ttl)),
+            from_disk: query.from_disk,
+            events: query.events.clone(),
+            ..Plan::default()
+        };
+        let mut field = Field::Key(String::new());
+        let mut negate = false;
+
+        for runner in query.runners.iter().cloned() {
+            match runner {
+                Runner::Method(name) => plan.method = Some(name),
+                Runner::Where(f) => field = Field::Key(query.stored_key(&f)),
+                Runner::WherePath(path) => field = Field::Path(path),
+                Runner::OrWhere(f) => {
+                    field = Field::Key(query.stored_key(&f));
+
+                    if plan.filters.last().is_some_and(|group| !group.is_empty()) {
+                        plan.filters.push(Vec::new());
+                    }
+                }
+                Runner::Not => negate = !negate,
+                Runner::Compare(comparator) => {
+                    Self::push_condition(&mut plan, field.clone(), comparator, &mut negate)
+                }
+                Runner::Param { op, name } => {
+                    let comparator = match query.params.get(&name) {
+                        Some(value) => sql::comparator(&op, value.clone()),
+                        None => Err("no value bound".to_string()),
+                    };
+                    match comparator {
+                        Ok(comparator) => {
+                            Self::push_condition(&mut plan, field.clone(), comparator, &mut negate)
+                        }
+                        Err(reason) => {
+                            plan.error = Some(OhMyDbError::InvalidParameter { name, reason })
+                        }
+                    }
+                }
+                Runner::Filters(dnf) => match plan.filters.pop() {
+                    Some(group) => plan.filters.extend(filter_doc::and(vec![group], dnf)),
+                    None => plan.filters = dnf,
+                },
+                Runner::Custom(name, argument) => match self.comparators.get(&name) {
+                    Some(compare) => {
+                        let compare = Arc::clone(compare);
+                        let predicate = Predicate(Arc::new(move |value| compare(value, &argument)));
+                        Self::push_condition(
+                            &mut plan,
+                            field.clone(),
+                            Comparator::Predicate(predicate),
+                            &mut negate,
+                        )
+                    }
+                    None => plan.error = Some(OhMyDbError::UnknownComparator(name)),
+                },
+                Runner::FilterWith(predicate) => Self::push_condition(
+                    &mut plan,
+                    Field::Record,
+                    Comparator::Predicate(predicate),
+                    &mut negate,
+                ),
+                Runner::OrderBy(field, order) => {
+                    plan.order_by.push((query.stored_key(&field), order))
+                }
+                Runner::Skip(n) => plan.skip = Some(n),
+                Runner::Limit(n) => plan.limit = Some(n),
+                Runner::Select(fields) => {
+                    plan.select = Some(fields.iter().map(|f| query.stored_key(f)).collect())
+                }
+                Runner::Distinct(field) => plan.distinct = Some(query.stored_key(&field)),
+                Runner::Operator(operator) => plan.operators.push(operator),
+                Runner::Join(join) => plan.joins.push(join),
+                Runner::Populate(field) => {
+                    let relation = plan
+                        .method
+                        .as_ref()
+                        .and_then(|method| self.options.get(method.table()))
+                        .and_then(|options| options.relation(&field));
+                    match relation {
+                        Some(relation) => plan.populate.push((field, relation.clone())),
+                        None => {
+                            plan.error = Some(OhMyDbError::InvalidField {
+                                field,
+                                reason: "no relation is declared for the field".to_string(),
+                            })
+                        }
+                    }
+                }
+                Runner::WithMeta => plan.with_meta = true,
+                Runner::WithDeleted => plan.with_deleted = true,
+                Runner::Invalid(reason) => plan.error = Some(OhMyDbError::InvalidRecord(reason)),
+                Runner::InvalidPattern { pattern, reason } => {
+                    plan.error = Some(OhMyDbError::InvalidPattern { pattern, reason })
                 }
             }
         }
 
-        Ok(result)
+        plan.envelope = plan
+            .method
+            .as_ref()
+            .is_some_and(|method| self.is_enveloped(method.table()));
+        plan.soft_delete = plan
+            .method
+            .as_ref()
+            .is_some_and(|method| self.is_soft_delete(method.table()));
+        if let Some(options) = plan
+            .method
+            .as_ref()
+            .and_then(|m| self.options.get(m.table()))
+        {
+            for (_, comparator) in plan.filters.iter_mut().flatten() {
+                options.normalize_operand(comparator);
+            }
+        }
+
+        plan
+    }
+
+    /// Adds a filter condition to the current AND group of the plan, negating it if `not` preceded it.
+    fn push_condition(plan: &mut Plan, field: Field, comparator: Comparator, negate: &mut bool) {
+        let comparator = if std::mem::take(negate) {
+            Comparator::Not(Box::new(comparator))
+        } else {
+            comparator
+        };
+
+        match plan.filters.last_mut() {
+            Some(group) => group.push((field, comparator)),
+            None => plan.filters.push(vec![(field, comparator)]),
+        }
+    }
+
+    /// Returns references to the records of the plan's table matching its filters,
+    /// sorted, deduplicated, skipped and limited as requested.
+    fn select_records<'a>(&'a self, plan: &Plan) -> Result<Vec<&'a Value>, OhMyDbError> {
+        plan.check()?;
+
+        let Some(table) = plan.method.as_ref().and_then(|m| self.value.get(m.table())) else {
+            return Ok(Vec::new());
+        };
+
+        let policy = plan
+            .method
+            .as_ref()
+            .and_then(|m| self.options.get(m.table()))
+            .and_then(TableOptions::policy);
+        let now = self.clock.now();
+        let matches = table
+            .iter()
+            .take_while(|_| !plan.is_cancelled())
+            .filter(|record| !expiry::is_expired(record, now))
+            .filter(|record| plan.selects_deleted_state(record))
+            .filter(|record| policy.is_none_or(|p| p.allows(&plan.context, plan.data(record))))
+            .filter(|record| self.matches_filters(record, plan));
+        let skip = plan.skip.unwrap_or(0);
+        let limit = plan.limit.unwrap_or(usize::MAX);
+        let mut seen: HashSet<&Value> = HashSet::new();
+        let is_first_of_kind = |record: &&'a Value| match &plan.distinct {
+            Some(field) => seen.insert(plan.field(record, field).unwrap_or(&Value::Null)),
+            None => true,
+        };
+
+        // Without sorting, scanning stops as soon as enough records matched
+        if plan.order_by.is_empty() {
+            let records = matches
+                .filter(is_first_of_kind)
+                .skip(skip)
+                .take(limit)
+                .collect();
+            return Self::unless_cancelled(plan, records);
+        }
+
+        let mut records: Vec<&Value> = matches.collect();
+        Self::sort_records(&mut records, plan);
+
+        let records = records
+            .into_iter()
+            .filter(is_first_of_kind)
+            .skip(skip)
+            .take(limit)
+            .collect();
+        Self::unless_cancelled(plan, records)
+    }
+
+    /// Returns `records` unless the plan was cancelled while they were selected.
+    fn unless_cancelled<'a>(
+        plan: &Plan,
+        records: Vec<&'a Value>,
+    ) -> Result<Vec<&'a Value>, OhMyDbError> {
+        if plan.is_cancelled() {
+            return Err(OhMyDbError::Cancelled);
+        }
+
+        Ok(records)
+    }
+
+    /// Returns whether a record matches the filter conditions of the plan.
+    fn matches_filters(&self, record: &Value, plan: &Plan) -> bool {
+        plan.filters.is_empty()
+            || plan.filters.iter().any(|group| {
+                group.iter().all(|(field, comparator)| match field {
+                    Field::Key(key_chain) => {
+                        let value = plan.field(record, key_chain).unwrap_or(&Value::Null);
+                        self.filter_with_conmpare(value, comparator)
+                    }
+                    Field::Record => self.filter_with_conmpare(plan.data(record), comparator),
+                    Field::Path(path) => {
+                        let nodes = path.query(plan.data(record));
+                        match nodes.is_empty() {
+                            true => self.filter_with_conmpare(&Value::Null, comparator),
+                            false => nodes
+                                .iter()
+                                .any(|value| self.filter_with_conmpare(value, comparator)),
+                        }
+                    }
+                })
+            })
+    }
+
+    /// Sorts records in place by the provided `(field, order)` keys, the first key being the primary one.
+    fn sort_records(records: &mut [&Value], plan: &Plan) {
+        records.sort_by(|a, b| {
+            plan.order_by
+                .iter()
+                .map(|(field, order)| {
+                    let ordering = compare_values(plan.field(a, field), plan.field(b, field));
+                    match order {
+                        Order::Asc => ordering,
+                        Order::Desc => ordering.reverse(),
+                    }
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(Ordering::Equal)
+        });
+    }
+
+    /// Compares a JSON value with a numeric bound, returning `None` if the value is not a number.
+    fn compare_number(value: &Value, bound: &Number) -> Option<Ordering> {
+        value.as_number().map(|n| compare_numbers(n, bound))
     }
 
     /// Filters a `Value` based on the provided `Comparator`.
@@ -572,20 +3986,50 @@ impl JsonDB {
     ///
     /// let json_db = JsonDB::new();
     /// let value = Value::from(42u64);
-    /// let comparator = Comparator::GreaterThan(30);
+    /// let comparator = Comparator::GreaterThan(30.into());
     /// assert!(json_db.filter_with_conmpare(value, &comparator));
     ///
-    fn filter_with_conmpare(&self, value: Value, comparator: &Comparator) -> bool {
+    fn filter_with_conmpare(&self, value: &Value, comparator: &Comparator) -> bool {
         match comparator {
-            Comparator::Equals(v) => value.as_str() == Some(v.as_str()),
-            Comparator::NotEquals(v) => value.as_str() != Some(v.as_str()),
-            Comparator::LessThan(v) => value.as_u64().map_or(false, |x| x < *v),
-            Comparator::GreaterThan(v) => value.as_u64().map_or(false, |x| x > *v),
-            Comparator::In(vs) => value
-                .as_str()
-                .map_or(false, |x| vs.contains(&x.to_string())),
+            Comparator::Equals(v) => values_equal(value, v),
+            Comparator::NotEquals(v) => !values_equal(value, v),
+            Comparator::LessThan(v) => Self::compare_number(value, v).is_some_and(Ordering::is_lt),
+            Comparator::GreaterThan(v) => {
+                Self::compare_number(value, v).is_some_and(Ordering::is_gt)
+            }
+            Comparator::GreaterThanOrEqual(v) => {
+                Self::compare_number(value, v).is_some_and(Ordering::is_ge)
+            }
+            Comparator::LessThanOrEqual(v) => {
+                Self::compare_number(value, v).is_some_and(Ordering::is_le)
+            }
+            Comparator::In(vs) => vs.iter().any(|v| values_equal(value, v)),
+            Comparator::NotIn(vs) => !vs.iter().any(|v| values_equal(value, v)),
             Comparator::Between((start, end)) => {
-                value.as_u64().map_or(false, |x| x >= *start && x <= *end)
+                Self::compare_number(value, start).is_some_and(Ordering::is_ge)
+                    && Self::compare_number(value, end).is_some_and(Ordering::is_le)
+            }
+            Comparator::BetweenStr((start, end)) => value
+                .as_str()
+                .is_some_and(|s| s >= start.as_str() && s <= end.as_str()),
+            Comparator::Not(comparator) => !self.filter_with_conmpare(value, comparator),
+            Comparator::Predicate(Predicate(predicate)) => predicate(value),
+            Comparator::Contains(v) => match (value, v) {
+                (Value::Array(items), v) => items.contains(v),
+                (Value::String(s), Value::String(v)) => s.contains(v.as_str()),
+                _ => false,
+            },
+            Comparator::Like(pattern) => value.as_str().is_some_and(|s| like_match(pattern, s)),
+            Comparator::SoundsLike(code) => value
+                .as_str()
+                .is_some_and(|s| !code.is_empty() && soundex(s) == *code),
+            Comparator::StartsWith(v) => value.as_str().is_some_and(|s| s.starts_with(v.as_str())),
+            Comparator::EndsWith(v) => value.as_str().is_some_and(|s| s.ends_with(v.as_str())),
+            Comparator::IsNull => value.is_null(),
+            Comparator::IsSet => !value.is_null(),
+            #[cfg(feature = "regex")]
+            Comparator::Matches(Pattern(regex)) => {
+                value.as_str().is_some_and(|s| regex.is_match(s))
             }
         }
     }
@@ -605,73 +4049,99 @@ impl JsonDB {
     ///
     /// # Returns
     ///
-    /// * `Result<&'a T, io::Error>` - A result containing either a reference to the inserted item or an error if the item already exists.
+    /// * `Result<&'a T, OhMyDbError>` - A result containing either a reference to the inserted item or an error if the item already exists.
     fn insert_into_table<'a>(
         &mut self,
         table_name: &str,
         new_item: &'a Value,
         or: bool,
-    ) -> Result<&'a Value, io::Error> {
-        let new_item_id: Value = get_nested_value(new_item, "id").unwrap();
+    ) -> Result<&'a Value, OhMyDbError> {
+        let logging = self.logging;
+        let new_item_id = self
+            .record_key(table_name, new_item)
+            .ok_or_else(|| OhMyDbError::MissingField(self.primary_key(table_name).to_string()))?;
+        let now = self.clock.now();
+        let duplicate_id = self
+            .value
+            .get(table_name)
+            .into_iter()
+            .flatten()
+            .filter(|t| !expiry::is_expired(t, now))
+            .any(|t| self.record_key(table_name, t).as_deref() == Some(new_item_id.as_str()));
+
+        self.invalidate_stats(table_name);
 
         let table = if or {
-            let db_hash = Arc::make_mut(&mut self.value);
-
-            match db_hash.get_mut(table_name) {
-                Some(t) => t,
-                None => {
-                    self.tables.insert(table_name.to_string());
-                    db_hash.insert(table_name.to_string(), HashSet::new());
-                    db_hash.get_mut(table_name).unwrap()
-                }
-            }
+            self.tables.insert(table_name.to_string());
+            Arc::make_mut(&mut self.value)
+                .entry(table_name.to_string())
+                .or_default()
         } else {
             self.get_table_mut(table_name)?
         };
+        // Expired records are gone for reads, so they don't keep their id taken either.
+        table.retain(|t| !expiry::is_expired(t, now));
 
         // Check if the new item already exists in the set for exact same properties
         if table.contains(new_item) {
-            println!(
-                "{} {}{}{} {}\n\t\t    {} {}\n",
-                "(insert_into_table)".bright_cyan().bold(),
-                "✗ Schade! Record with id \"".bright_red().bold(),
-                new_item_id.as_str().unwrap().bright_red().bold(),
-                "\" already exists in table".bright_red().bold(),
-                table_name.to_string().bright_cyan().bold(),
-                "✔".bright_green().bold().blink(),
-                "Try to add new record".bright_green().bold()
-            );
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                "Record already exists",
-            ));
+            if logging {
+                println!(
+                    "{} {}{}{} {}\n\t\t    {} {}\n",
+                    "(insert_into_table)".bright_cyan().bold(),
+                    "✗ Schade! Record with id \"".bright_red().bold(),
+                    new_item_id.bright_red().bold(),
+                    "\" already exists in table".bright_red().bold(),
+                    table_name.to_string().bright_cyan().bold(),
+                    "✔".bright_green().bold().blink(),
+                    "Try to add new record".bright_green().bold()
+                );
+            }
+            return Err(OhMyDbError::RecordExists {
+                table: table_name.to_string(),
+                id: new_item_id,
+            });
         }
 
         // Check for double entries with same id
-        let search_table = table.iter().find(|t| {
-            let current_id: Value = get_nested_value(t, "id").unwrap();
-
-            current_id.as_str().unwrap() == new_item_id.as_str().unwrap()
-        });
-
-        match search_table {
-            Some(t) => {
-                let t_id: Value = get_nested_value(t, "id").unwrap();
-
-                return Err(io::Error::new(
-                    io::ErrorKind::AlreadyExists,
-                    format!(
-                        "Record with id \"{}\" already exists",
-                        t_id.as_str().unwrap()
-                    ),
-                ));
-            }
-            None => {
-                // Insert the new item
-                table.insert(new_item.clone());
-            }
+        if duplicate_id {
+            return Err(OhMyDbError::RecordExists {
+                table: table_name.to_string(),
+                id: new_item_id,
+            });
         }
 
+        // Insert the new item
+        table.insert(new_item.clone());
+
         Ok(new_item)
     }
 }
+
+/// Returns a predicate matching the records whose `key` field, compared as a string, is `id`.
+fn has_id(key: &str, id: &str) -> impl Fn(&Value) -> bool + Send + Sync + 'static {
+    let (key, id) = (key.to_string(), id.to_string());
+    move |record| record_id(record, &key).as_deref() == Some(id.as_str())
+}
+
+/// Returns whether `table` is a user table, i.e. not a reserved one (outbox, queues, configuration, ...),
+/// whose names start with `_`.
+fn is_user_table(table: &str) -> bool {
+    !table.starts_with('_')
+}
+
+/// The key marking the soft-deleted records: the envelope marker in envelope mode, a field of the record otherwise.
+const DELETED_AT: &str = "deleted_at";
+
+/// Returns whether a stored record of a table with soft deletes is marked as deleted.
+fn is_soft_deleted(record: &Value) -> bool {
+    record.get(DELETED_AT).is_some_and(|at| !at.is_null())
+}
+
+/// Returns the id of the idempotency key of an insert into `table` in the reserved table: the JSON array of both,
+/// so keys of different tables never collide.
+fn idempotency_id(table: &str, key: &str) -> String {
+    json!([table, key]).to_string()
+}
+
+#[cfg(test)]
+mod tests;
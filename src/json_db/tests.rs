@@ -0,0 +1,1123 @@
+//! Tests of the database.
+//!
+//! The fuzz tests feed arbitrary JSON values (non-objects, missing or non-string ids, nested arrays, ...)
+//! through the query path, `insert_into_table` and the utils: whatever the shape of the data, the library
+//! must return an error rather than panic. The modules after them check the behavior of each feature.
+
+use super::*;
+use crate::utils::{get_field_by_name, get_key_chain_value, get_nested_value};
+use serde_json::Map;
+
+/// The number of values each test generates per case.
+const ITERATIONS: usize = 300;
+
+/// The keys of the generated objects and the fields queried, including the ones the library gives a meaning to.
+const KEYS: &[&str] = &[
+    "id",
+    "name",
+    "data",
+    "value",
+    "deleted_at",
+    "_expires_at",
+    "revision",
+    "wife",
+    "wife.name",
+    "tags",
+    "",
+    "é",
+];
+
+/// A deterministic (xorshift) generator of arbitrary JSON values, so failures are reproducible.
+struct Fuzz(u64);
+
+impl Fuzz {
+    fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn key(&mut self) -> &'static str {
+        KEYS[self.below(KEYS.len())]
+    }
+
+    fn string(&mut self) -> String {
+        const STRINGS: &[&str] = &[
+            "", "1", "a", "Jane Doe", "e\u{301}", "%_", "1.5", "null", "\u{0}",
+        ];
+        STRINGS[self.below(STRINGS.len())].to_string()
+    }
+
+    fn scalar(&mut self) -> Value {
+        match self.below(7) {
+            0 => Value::Null,
+            1 => Value::Bool(self.next().is_multiple_of(2)),
+            2 => Value::from(self.below(4) as i64 - 1),
+            3 => Value::from(u64::MAX - self.below(2) as u64),
+            4 => Value::from(i64::MIN),
+            5 => Value::from([0.5, -1.0, 1e300, 2.0][self.below(4)]),
+            _ => Value::String(self.string()),
+        }
+    }
+
+    /// Returns an arbitrary value, nesting arrays and objects up to `depth` levels.
+    fn value(&mut self, depth: usize) -> Value {
+        match (depth, self.below(4)) {
+            (0, _) | (_, 0 | 1) => self.scalar(),
+            (_, 2) => Value::Array((0..self.below(4)).map(|_| self.value(depth - 1)).collect()),
+            _ => self.object(depth - 1),
+        }
+    }
+
+    /// Returns an object of arbitrary fields, its `id` (if any) being of any type.
+    fn object(&mut self, depth: usize) -> Value {
+        let mut fields = Map::new();
+        for _ in 0..self.below(5) {
+            fields.insert(self.key().to_string(), self.value(depth));
+        }
+        match self.below(4) {
+            0 => {}
+            1 => {
+                fields.insert("id".to_string(), self.value(depth));
+            }
+            _ => {
+                fields.insert("id".to_string(), Value::from(self.below(8).to_string()));
+            }
+        }
+
+        Value::Object(fields)
+    }
+
+    /// Returns a record to write: mostly objects, sometimes any other value.
+    fn record(&mut self) -> Value {
+        match self.below(4) {
+            0 => self.value(3),
+            _ => self.object(3),
+        }
+    }
+}
+
+/// A database file in the temporary directory, removed when dropped.
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new(name: &str) -> Self {
+        let name = format!("ohmydb-fuzz-{}-{}", std::process::id(), name);
+        Self(std::env::temp_dir().join(name.to_lowercase()))
+    }
+
+    /// Opens the database, `JsonDB::new` keeping absolute paths as they are.
+    async fn open(&self) -> JsonDB {
+        JsonDB::new(self.0.to_str().unwrap()).await.unwrap()
+    }
+
+    fn file(&self) -> PathBuf {
+        self.0.with_extension("json")
+    }
+}
+
+/// Opens a fresh database whose table `t` holds `records`, with logging turned off.
+async fn table_with(temp: &TempDb, options: TableOptions, records: &[Value]) -> JsonDB {
+    let mut db = temp.open().await;
+    db.set_logging(false).set_table_options("t", options);
+    db.add_table("t").await.unwrap();
+    for record in records {
+        db.insert("t", record).run(&mut db).await.unwrap();
+    }
+
+    db
+}
+
+/// Returns the sorted ids of `records`.
+fn ids(records: &[Value]) -> Vec<String> {
+    let mut ids: Vec<String> = records.iter().filter_map(|r| record_id(r, "id")).collect();
+    ids.sort();
+    ids
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        std::fs::remove_file(self.file()).ok();
+    }
+}
+
+/// The table options the query path is fuzzed under.
+fn table_options() -> Vec<TableOptions> {
+    vec![
+        TableOptions::new(),
+        TableOptions::new().envelope(true),
+        TableOptions::new().envelope(true).soft_delete(true),
+        TableOptions::new().soft_delete(true),
+        TableOptions::new().auto_increment(true),
+        TableOptions::new().normalize_unicode(true),
+    ]
+}
+
+/// Runs an arbitrary query against table `t`, ignoring its outcome.
+async fn run_arbitrary_query(db: &mut JsonDB, fuzz: &mut Fuzz) {
+    let record = fuzz.record();
+    let (field, operand) = (fuzz.key(), fuzz.value(2));
+
+    let mut query = match fuzz.below(6) {
+        0 => db.insert("t", &record),
+        1 => db.update("t", &record),
+        2 => db.merge("t", &record, MergeOptions::default()),
+        3 => db.delete("t"),
+        _ => db.find("t"),
+    };
+    match fuzz.below(6) {
+        0 => query.where_(field).equals(operand),
+        1 => query.where_(field).contains(operand),
+        2 => query.where_(field).greater_than(fuzz.below(3) as i64),
+        3 => query.where_(field).like("%a%"),
+        4 => query.where_(field).in_(vec![operand, fuzz.scalar()]),
+        _ => &mut query,
+    };
+    if fuzz.below(2) == 0 {
+        query.order_by(fuzz.key(), Order::Desc);
+    }
+    match fuzz.below(4) {
+        0 => query.select(&[fuzz.key(), fuzz.key()]),
+        1 => query.distinct(fuzz.key()),
+        2 => query.with_meta(),
+        _ => &mut query,
+    };
+
+    let _ = query.run(db).await;
+}
+
+#[tokio::test]
+async fn run_never_panics_on_arbitrary_records() {
+    for (seed, options) in table_options().into_iter().enumerate() {
+        let temp = TempDb::new(&format!("run-{}", seed));
+        let mut db = temp.open().await;
+        db.set_table_options("t", options);
+        db.add_table("t").await.unwrap();
+        let mut fuzz = Fuzz::new(seed as u64);
+
+        for _ in 0..ITERATIONS {
+            run_arbitrary_query(&mut db, &mut fuzz).await;
+        }
+        let id = fuzz.string();
+        let _ = db.delete_by_id("t", &id).await;
+        let _ = db.find_by_id("t", &id).await;
+        let mut records = db.iter_all();
+        while records.next_record().await.is_some() {}
+        drop(records);
+        db.integrity_report();
+    }
+}
+
+#[tokio::test]
+async fn odd_shaped_files_never_panic() {
+    for (seed, options) in table_options().into_iter().enumerate() {
+        let temp = TempDb::new(&format!("file-{}", seed));
+        let mut fuzz = Fuzz::new(100 + seed as u64);
+        let mut records: Vec<Value> = (0..ITERATIONS / 10).map(|_| fuzz.value(3)).collect();
+        records.extend([
+            json!(1),
+            json!("x"),
+            json!(null),
+            json!([1, [2]]),
+            json!({ "id": [1] }),
+            json!({ "id": 1.5 }),
+            json!({ "data": 3, "id": "d" }),
+            json!({ "id": "e", "data": "not an object", "revision": "1" }),
+            json!({ "id": "f", "_expires_at": "soon", "deleted_at": [] }),
+        ]);
+        std::fs::write(
+            temp.file(),
+            json!({ "t": records, "_cold": [1, {}] }).to_string(),
+        )
+        .unwrap();
+
+        let mut db = temp.open().await;
+        db.set_table_options("t", options);
+        for _ in 0..ITERATIONS / 10 {
+            run_arbitrary_query(&mut db, &mut fuzz).await;
+        }
+        let mut records = db.iter_all();
+        while records.next_record().await.is_some() {}
+        drop(records);
+        let _ = db.field_stats("t", fuzz.key());
+        db.integrity_report();
+        let _ = db.doctor().await;
+    }
+}
+
+#[tokio::test]
+async fn records_without_usable_id_are_rejected() {
+    let temp = TempDb::new("rejected");
+    let mut db = temp.open().await;
+    db.add_table("t").await.unwrap();
+
+    for record in [
+        json!({}),
+        json!({ "id": null }),
+        json!({ "id": [1] }),
+        json!({ "id": { "id": 1 } }),
+        json!({ "id": true }),
+    ] {
+        assert!(db.insert("t", &record).run(&mut db).await.is_err());
+        assert!(db.update("t", &record).run(&mut db).await.is_err());
+        assert!(db.insert_into_table("t", &record, true).is_err());
+    }
+}
+
+#[tokio::test]
+async fn insert_into_table_accepts_any_value() {
+    let temp = TempDb::new("insert-into-table");
+    let mut db = temp.open().await;
+    let mut fuzz = Fuzz::new(7);
+
+    for _ in 0..ITERATIONS {
+        let record = fuzz.record();
+        let inserted = db.insert_into_table("t", &record, true);
+        if record_id(&record, "id").is_none() {
+            assert!(inserted.is_err(), "inserted {} without an id", record);
+        }
+    }
+}
+
+#[test]
+fn utils_accept_any_value() {
+    let mut fuzz = Fuzz::new(42);
+
+    for _ in 0..ITERATIONS {
+        let (mut a, b) = (fuzz.value(3), fuzz.value(3));
+        let key_chain = [fuzz.key(), fuzz.key()].join(".");
+
+        get_nested_ref(&a, &key_chain);
+        record_id(&a, fuzz.key());
+        project(&a, &[key_chain.clone(), fuzz.key().to_string()]);
+        get_key_chain_value(&a, &key_chain);
+        let _ = get_nested_value::<_, Value>(&a, &key_chain);
+        let _ = get_field_by_name::<_, Value>(&a, fuzz.key());
+        values_equal(&a, &b);
+        like_match(&fuzz.string(), &fuzz.string());
+        soundex(&fuzz.string());
+        slugify(&fuzz.string());
+        get_nested_mut(&mut a, &key_chain);
+        get_nested_entry(&mut a, &key_chain);
+        wrap_record(a.clone());
+
+        // Sorting panics on inconsistent orders, so the comparison must be antisymmetric.
+        assert_eq!(
+            compare_values(Some(&a), Some(&b)),
+            compare_values(Some(&b), Some(&a)).reverse(),
+            "comparing {} and {}",
+            a,
+            b
+        );
+    }
+}
+
+mod explain {
+    use super::*;
+
+    #[tokio::test]
+    async fn estimates_come_from_cached_statistics() {
+        let temp = TempDb::new("explain");
+        let records: Vec<Value> = (0..10)
+            .map(|n| json!({ "id": n.to_string(), "n": n, "done": n < 3 }))
+            .collect();
+        let mut db = table_with(&temp, TableOptions::new(), &records).await;
+
+        let mut query = db.find("t");
+        query.where_("n").less_than(3).where_("done").equals(true);
+        let explain = query.explain(&db).unwrap();
+        assert!(!explain.has_statistics);
+        assert_eq!(explain.estimated_scan, 10);
+        assert_eq!(explain.estimated_matches, vec![vec![None, None]]);
+        assert_eq!(explain.estimated_rows, 10);
+
+        db.field_stats("t", "n").unwrap();
+        let explain = query.explain(&db).unwrap();
+        assert!(explain.has_statistics);
+        // Values are assumed uniformly distributed between the smallest and largest one.
+        assert_eq!(explain.estimated_matches, vec![vec![Some(3), None]]);
+        assert_eq!(explain.estimated_rows, 3);
+
+        db.field_stats("t", "done").unwrap();
+        let explain = query.explain(&db).unwrap();
+        // Equality is assumed to match one distinct value out of two, independently of `n`.
+        assert_eq!(explain.estimated_matches, vec![vec![Some(3), Some(5)]]);
+        assert_eq!(explain.estimated_rows, 2);
+    }
+}
+
+mod aggregates {
+    use super::*;
+    use crate::options::FieldCodec;
+
+    #[tokio::test]
+    async fn fields_are_decoded_like_run_returns_them() {
+        let temp = TempDb::new("aggregates-decode");
+        // Prices are stored in cents and read in units, so 150 and 151 both read as 1.
+        let cents = FieldCodec::new().on_read(|value| json!(value.as_i64().unwrap_or(0) / 100));
+        let records: Vec<Value> = [150, 151, 300]
+            .iter()
+            .enumerate()
+            .map(|(id, cents)| json!({ "id": id.to_string(), "price": cents }))
+            .collect();
+        let mut db = table_with(&temp, TableOptions::new().codec("price", cents), &records).await;
+
+        let prices: Vec<Value> = db
+            .find("t")
+            .run(&mut db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|record| record["price"].clone())
+            .collect();
+        assert_eq!(prices.len(), 3);
+        assert!(prices
+            .iter()
+            .all(|price| *price == json!(1) || *price == json!(3)));
+
+        assert_eq!(db.find("t").sum(&mut db, "price").await.unwrap(), 5.0);
+        assert_eq!(
+            db.find("t").max(&mut db, "price").await.unwrap(),
+            Some(3.into())
+        );
+        let mut distinct = db
+            .find("t")
+            .distinct_values(&mut db, "price")
+            .await
+            .unwrap();
+        distinct.sort_by(|a, b| compare_values(Some(a), Some(b)));
+        assert_eq!(distinct, vec![json!(1), json!(3)]);
+    }
+
+    #[tokio::test]
+    #[allow(unused_imports)]
+    async fn entity_fields_are_translated() {
+        crate::define_struct_from!(Item {
+            #[serde(rename = "unitPrice")]
+            unit_price: u32
+        });
+        let temp = TempDb::new("aggregates-entity");
+        let records: Vec<Value> = [2, 2, 5]
+            .iter()
+            .enumerate()
+            .map(|(id, price)| json!({ "id": id.to_string(), "unitPrice": price }))
+            .collect();
+        let mut db = table_with(&temp, TableOptions::new(), &records).await;
+
+        let mut query = db.find("t");
+        query.entity::<Item>();
+        assert_eq!(query.sum(&mut db, "unit_price").await.unwrap(), 9.0);
+        assert_eq!(
+            query.min(&mut db, "unit_price").await.unwrap(),
+            Some(2.into())
+        );
+        let mut distinct = query.distinct_values(&mut db, "unit_price").await.unwrap();
+        distinct.sort_by(|a, b| compare_values(Some(a), Some(b)));
+        assert_eq!(distinct, vec![json!(2), json!(5)]);
+    }
+}
+
+mod upsert {
+    use super::*;
+    use crate::security::Context;
+
+    #[tokio::test]
+    async fn soft_deleted_record_keeps_its_id() {
+        let temp = TempDb::new("upsert-soft-delete");
+        let options = TableOptions::new().soft_delete(true);
+        let mut db = table_with(&temp, options, &[json!({ "id": "1", "n": 1 })]).await;
+        db.delete_by_id("t", "1").await.unwrap();
+
+        let upserted = db
+            .upsert("t", &json!({ "id": "1", "n": 2 }))
+            .run(&mut db)
+            .await;
+        assert!(
+            matches!(upserted, Err(OhMyDbError::RecordExists { .. })),
+            "{:?}",
+            upserted
+        );
+        let records = db.find("t").with_deleted().run(&mut db).await.unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0]["n"], 1);
+    }
+
+    #[tokio::test]
+    async fn update_branch_applies_the_row_policy() {
+        let temp = TempDb::new("upsert-row-policy");
+        let options =
+            TableOptions::new().row_policy(|ctx, record| ctx.user_id() == record["owner"].as_str());
+        let mut db = table_with(&temp, options, &[]).await;
+        let ann = Context::new().user("ann");
+        let bob = Context::new().user("bob");
+        db.insert("t", &json!({ "id": "1", "owner": "ann" }))
+            .with_context(ann.clone())
+            .run(&mut db)
+            .await
+            .unwrap();
+
+        let stolen = db
+            .upsert("t", &json!({ "id": "1", "owner": "bob" }))
+            .with_context(bob)
+            .run(&mut db)
+            .await;
+        assert!(
+            matches!(stolen, Err(OhMyDbError::RecordExists { .. })),
+            "{:?}",
+            stolen
+        );
+        let given = db
+            .upsert("t", &json!({ "id": "1", "owner": "bob" }))
+            .with_context(ann.clone())
+            .run(&mut db)
+            .await;
+        assert!(
+            matches!(given, Err(OhMyDbError::AccessDenied { .. })),
+            "{:?}",
+            given
+        );
+        let records = db.find("t").with_context(ann).run(&mut db).await.unwrap();
+        assert_eq!(records, vec![json!({ "id": "1", "owner": "ann" })]);
+    }
+}
+
+mod idempotency {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[tokio::test]
+    async fn pruned_keys_are_forgotten() {
+        let temp = TempDb::new("idempotency-prune");
+        let mut db = table_with(&temp, TableOptions::new(), &[]).await;
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000_000));
+        db.set_clock(clock.clone());
+        let insert = |db: &JsonDB, id: &str, key: &str| {
+            let mut query = db.insert("t", &json!({ "id": id }));
+            query.with_idempotency_key(key);
+            query
+        };
+
+        insert(&db, "1", "a").run(&mut db).await.unwrap();
+        clock.advance(Duration::from_secs(60));
+        insert(&db, "2", "b").run(&mut db).await.unwrap();
+        assert_eq!(
+            insert(&db, "3", "a").run(&mut db).await.unwrap()[0]["id"],
+            "1"
+        );
+
+        assert_eq!(
+            db.prune_idempotency(Duration::from_secs(30)).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            db.prune_idempotency(Duration::from_secs(30)).await.unwrap(),
+            0
+        );
+        // The key "a" is forgotten, so the retry inserts its item, while "b" still deduplicates.
+        assert_eq!(
+            insert(&db, "3", "a").run(&mut db).await.unwrap()[0]["id"],
+            "3"
+        );
+        assert_eq!(
+            insert(&db, "4", "b").run(&mut db).await.unwrap()[0]["id"],
+            "2"
+        );
+        assert_eq!(db.find("t").count(&mut db).await.unwrap(), 3);
+    }
+}
+
+mod remote {
+    use super::*;
+    use crate::remote::{RemoteFuture, RemoteTier};
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+    /// A remote holding a record for every id, counting the lookups.
+    #[derive(Clone, Default)]
+    struct Everything(Arc<AtomicUsize>);
+
+    impl RemoteTier for Everything {
+        fn fetch<'a>(&'a self, _: &'a str, id: &'a str) -> RemoteFuture<'a, Option<Value>> {
+            self.0.fetch_add(1, AtomicOrdering::SeqCst);
+            Box::pin(async move { Ok(Some(json!({ "id": id }))) })
+        }
+
+        fn store<'a>(&'a self, _: &'a str, _: &'a Value) -> RemoteFuture<'a, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn remove<'a>(&'a self, _: &'a str, _: &'a str) -> RemoteFuture<'a, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn read_through_needs_the_table() {
+        let temp = TempDb::new("remote-missing-table");
+        let mut db = table_with(&temp, TableOptions::new(), &[]).await;
+        let remote = Everything::default();
+        db.set_remote_tier("t", remote.clone())
+            .set_remote_tier("missing", remote.clone());
+
+        let found = db.find_by_id("missing", "1").await;
+        assert!(
+            matches!(found, Err(OhMyDbError::TableNotFound(_))),
+            "{:?}",
+            found
+        );
+        assert_eq!(remote.0.load(AtomicOrdering::SeqCst), 0);
+
+        assert_eq!(
+            db.find_by_id("t", "1").await.unwrap(),
+            Some(json!({ "id": "1" }))
+        );
+        assert_eq!(remote.0.load(AtomicOrdering::SeqCst), 1);
+        // The record is cached in the table, so the remote isn't asked again.
+        db.find_by_id("t", "1").await.unwrap();
+        assert_eq!(remote.0.load(AtomicOrdering::SeqCst), 1);
+    }
+}
+
+mod planning {
+    use super::*;
+
+    async fn people(temp: &TempDb) -> JsonDB {
+        let records = [
+            json!({ "id": "1", "role": "admin", "age": 40, "team": "a" }),
+            json!({ "id": "2", "role": "admin", "age": 20, "team": "b" }),
+            json!({ "id": "3", "role": "user", "age": 40, "team": null }),
+            json!({ "id": "4", "role": "guest", "age": 30 }),
+        ];
+        table_with(temp, TableOptions::new(), &records).await
+    }
+
+    #[tokio::test]
+    async fn and_binds_tighter_than_or() {
+        let temp = TempDb::new("planning-or");
+        let mut db = people(&temp).await;
+
+        let mut query = db.find("t");
+        query
+            .where_("role")
+            .equals("admin")
+            .and_where("age")
+            .greater_than(30)
+            .or_where("role")
+            .equals("guest");
+        assert_eq!(ids(&query.run(&mut db).await.unwrap()), ["1", "4"]);
+
+        let sql = "SELECT * FROM t WHERE role = 'admin' AND age > 30 OR role = 'guest'";
+        assert_eq!(ids(&db.query(sql).run(&mut db).await.unwrap()), ["1", "4"]);
+    }
+
+    #[tokio::test]
+    async fn not_negates_the_next_comparator_only() {
+        let temp = TempDb::new("planning-not");
+        let mut db = people(&temp).await;
+
+        let mut query = db.find("t");
+        query.where_("role").not().in_(vec!["admin", "user"]);
+        assert_eq!(ids(&query.run(&mut db).await.unwrap()), ["4"]);
+
+        // Missing and null fields are both null, so `not().is_null()` keeps the records with a team.
+        let mut query = db.find("t");
+        query
+            .where_("team")
+            .not()
+            .is_null()
+            .and_where("age")
+            .greater_than(30);
+        assert_eq!(ids(&query.run(&mut db).await.unwrap()), ["1"]);
+
+        let mut query = db.find("t");
+        query
+            .where_("age")
+            .not()
+            .equals(40)
+            .or_where("team")
+            .equals("a");
+        assert_eq!(ids(&query.run(&mut db).await.unwrap()), ["1", "2", "4"]);
+    }
+}
+
+mod unique {
+    use super::*;
+    use crate::options::Nulls;
+
+    async fn insert(db: &mut JsonDB, record: Value) -> Result<Vec<Value>, OhMyDbError> {
+        db.insert("t", &record).run(db).await
+    }
+
+    #[tokio::test]
+    async fn distinct_nulls_are_not_constrained() {
+        let temp = TempDb::new("unique-distinct");
+        let options = TableOptions::new().unique(&["user", "day"]);
+        let mut db = table_with(&temp, options, &[]).await;
+
+        insert(&mut db, json!({ "id": "1", "user": "ann", "day": 1 }))
+            .await
+            .unwrap();
+        let duplicate = insert(&mut db, json!({ "id": "2", "user": "ann", "day": 1 })).await;
+        assert!(
+            matches!(duplicate, Err(OhMyDbError::UniqueViolation { .. })),
+            "{:?}",
+            duplicate
+        );
+        insert(&mut db, json!({ "id": "3", "user": "ann", "day": 2 }))
+            .await
+            .unwrap();
+
+        insert(&mut db, json!({ "id": "4", "user": "ann", "day": null }))
+            .await
+            .unwrap();
+        insert(&mut db, json!({ "id": "5", "user": "ann", "day": null }))
+            .await
+            .unwrap();
+        insert(&mut db, json!({ "id": "6", "user": "ann" }))
+            .await
+            .unwrap();
+        assert_eq!(db.find("t").count(&mut db).await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn not_distinct_nulls_are_equal() {
+        let temp = TempDb::new("unique-not-distinct");
+        let options = TableOptions::new().unique_with(&["user", "day"], Nulls::NotDistinct);
+        let mut db = table_with(&temp, options, &[]).await;
+
+        insert(&mut db, json!({ "id": "1", "user": "ann", "day": null }))
+            .await
+            .unwrap();
+        // A missing field counts as null.
+        let duplicate = insert(&mut db, json!({ "id": "2", "user": "ann" })).await;
+        assert!(
+            matches!(duplicate, Err(OhMyDbError::UniqueViolation { .. })),
+            "{:?}",
+            duplicate
+        );
+        insert(&mut db, json!({ "id": "3", "user": "bob" }))
+            .await
+            .unwrap();
+        insert(&mut db, json!({ "id": "4", "user": "ann", "day": 1 }))
+            .await
+            .unwrap();
+
+        // Updating a record doesn't conflict with its own previous version.
+        db.update("t", &json!({ "id": "1", "user": "ann" }))
+            .run(&mut db)
+            .await
+            .unwrap();
+        let moved = db
+            .update("t", &json!({ "id": "4", "user": "ann" }))
+            .run(&mut db)
+            .await;
+        assert!(
+            matches!(moved, Err(OhMyDbError::UniqueViolation { .. })),
+            "{:?}",
+            moved
+        );
+        assert_eq!(
+            ids(&db.find("t").run(&mut db).await.unwrap()),
+            ["1", "3", "4"]
+        );
+    }
+}
+
+mod on_delete {
+    use super::*;
+    use crate::options::{OnDelete, Relation};
+
+    /// Users have posts (cascade) and likes (set null), and posts may be pinned (restrict).
+    async fn blog(temp: &TempDb) -> JsonDB {
+        let mut db = temp.open().await;
+        db.set_logging(false)
+            .set_table_options(
+                "posts",
+                TableOptions::new().reference(
+                    "author",
+                    Relation::new("users", "id").on_delete(OnDelete::Cascade),
+                ),
+            )
+            .set_table_options(
+                "likes",
+                TableOptions::new().reference(
+                    "user",
+                    Relation::new("users", "id").on_delete(OnDelete::SetNull),
+                ),
+            )
+            .set_table_options(
+                "pins",
+                TableOptions::new().reference(
+                    "post",
+                    Relation::new("posts", "id").on_delete(OnDelete::Restrict),
+                ),
+            );
+        let records = [
+            ("users", json!({ "id": "ann" })),
+            ("users", json!({ "id": "bob" })),
+            ("posts", json!({ "id": "p1", "author": "ann" })),
+            ("posts", json!({ "id": "p2", "author": "bob" })),
+            ("likes", json!({ "id": "l1", "user": "ann" })),
+            ("likes", json!({ "id": "l2", "user": "bob" })),
+            ("pins", json!({ "id": "pin", "post": "p2" })),
+        ];
+        for table in ["users", "posts", "likes", "pins"] {
+            db.add_table(table).await.unwrap();
+        }
+        for (table, record) in records {
+            db.insert(table, &record).run(&mut db).await.unwrap();
+        }
+
+        db
+    }
+
+    async fn all(db: &mut JsonDB, table: &str) -> Vec<Value> {
+        let mut records = db.find(table).run(db).await.unwrap();
+        records.sort_by(|a, b| compare_values(a.get("id"), b.get("id")));
+        records
+    }
+
+    #[tokio::test]
+    async fn cascade_and_set_null_apply_together() {
+        let temp = TempDb::new("on-delete-cascade");
+        let mut db = blog(&temp).await;
+
+        assert!(db.delete_by_id("users", "ann").await.unwrap());
+        assert_eq!(ids(&all(&mut db, "users").await), ["bob"]);
+        assert_eq!(ids(&all(&mut db, "posts").await), ["p2"]);
+        assert_eq!(
+            all(&mut db, "likes").await,
+            [
+                json!({ "id": "l1", "user": null }),
+                json!({ "id": "l2", "user": "bob" })
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn restrict_rolls_the_whole_delete_back() {
+        let temp = TempDb::new("on-delete-restrict");
+        let mut db = blog(&temp).await;
+        let before = db.value.clone();
+
+        // Deleting bob cascades to p2, which is pinned.
+        let deleted = db.delete_by_id("users", "bob").await;
+        assert!(
+            matches!(deleted, Err(OhMyDbError::StillReferenced { .. })),
+            "{:?}",
+            deleted
+        );
+        assert_eq!(db.value, before);
+        let reopened = temp.open().await;
+        assert_eq!(reopened.value, before);
+    }
+}
+
+mod quotas {
+    use super::*;
+    use crate::options::Quota;
+
+    fn exceeded(outcome: Result<Vec<Value>, OhMyDbError>, expected: Quota) {
+        match outcome {
+            Err(OhMyDbError::QuotaExceeded { quota, .. }) => assert_eq!(quota, expected),
+            outcome => panic!("expected the quota to be exceeded, got {:?}", outcome),
+        }
+    }
+
+    #[tokio::test]
+    async fn record_count_allows_updates_and_deletes() {
+        let temp = TempDb::new("quota-records");
+        let options = TableOptions::new().quota(Quota::Records(2));
+        let records = [json!({ "id": "1" }), json!({ "id": "2" })];
+        let mut db = table_with(&temp, options, &records).await;
+
+        exceeded(
+            db.insert("t", &json!({ "id": "3" })).run(&mut db).await,
+            Quota::Records(2),
+        );
+        db.update("t", &json!({ "id": "1", "n": 1 }))
+            .run(&mut db)
+            .await
+            .unwrap();
+        db.delete_by_id("t", "2").await.unwrap();
+        db.insert("t", &json!({ "id": "3" }))
+            .run(&mut db)
+            .await
+            .unwrap();
+        assert_eq!(ids(&db.find("t").run(&mut db).await.unwrap()), ["1", "3"]);
+    }
+
+    #[tokio::test]
+    async fn sizes_are_checked_on_insert_and_update() {
+        let temp = TempDb::new("quota-bytes");
+        // `{"id":"1","s":""}` is 17 bytes long.
+        let options = TableOptions::new()
+            .quota(Quota::RecordBytes(20))
+            .quota(Quota::TableBytes(40));
+        let mut db = table_with(&temp, options, &[json!({ "id": "1", "s": "" })]).await;
+
+        let big = json!({ "id": "2", "s": "abcd" });
+        exceeded(
+            db.insert("t", &big).run(&mut db).await,
+            Quota::RecordBytes(20),
+        );
+        let grown = json!({ "id": "1", "s": "abcd" });
+        exceeded(
+            db.update("t", &grown).run(&mut db).await,
+            Quota::RecordBytes(20),
+        );
+
+        db.insert("t", &json!({ "id": "2", "s": "ab" }))
+            .run(&mut db)
+            .await
+            .unwrap();
+        let third = json!({ "id": "3", "s": "" });
+        exceeded(
+            db.insert("t", &third).run(&mut db).await,
+            Quota::TableBytes(40),
+        );
+        assert_eq!(ids(&db.find("t").run(&mut db).await.unwrap()), ["1", "2"]);
+    }
+}
+
+mod soft_delete {
+    use super::*;
+
+    #[tokio::test]
+    async fn deleted_records_can_be_restored_or_purged() {
+        for options in [
+            TableOptions::new().soft_delete(true),
+            TableOptions::new().soft_delete(true).envelope(true),
+        ] {
+            let temp = TempDb::new("soft-delete");
+            let records: Vec<Value> = (1..=3).map(|n| json!({ "id": n.to_string() })).collect();
+            let mut db = table_with(&temp, options, &records).await;
+
+            let mut query = db.delete("t");
+            query.where_("id").in_(vec!["1", "2"]);
+            assert_eq!(ids(&query.run(&mut db).await.unwrap()), ["1", "2"]);
+            assert_eq!(ids(&db.find("t").run(&mut db).await.unwrap()), ["3"]);
+            assert_eq!(db.find_by_id("t", "1").await.unwrap(), None);
+            let all = db.find("t").with_deleted().run(&mut db).await.unwrap();
+            assert_eq!(ids(&all), ["1", "2", "3"]);
+            // Deleted records are not selected again.
+            assert!(db
+                .delete("t")
+                .where_("id")
+                .equals("1")
+                .run(&mut db)
+                .await
+                .unwrap()
+                .is_empty());
+
+            let mut query = db.restore("t");
+            query.where_("id").equals("1");
+            assert_eq!(ids(&query.run(&mut db).await.unwrap()), ["1"]);
+            assert_eq!(ids(&db.find("t").run(&mut db).await.unwrap()), ["1", "3"]);
+
+            // Only deleted records are purged, so the live ones stay.
+            assert_eq!(ids(&db.purge("t").run(&mut db).await.unwrap()), ["2"]);
+            let all = db.find("t").with_deleted().run(&mut db).await.unwrap();
+            assert_eq!(ids(&all), ["1", "3"]);
+            assert!(db.restore("t").run(&mut db).await.unwrap().is_empty());
+        }
+    }
+}
+
+mod row_policy {
+    use super::*;
+    use crate::security::Context;
+
+    /// Records belong to a tenant, and admins see every record.
+    async fn tenants(temp: &TempDb) -> JsonDB {
+        let options = TableOptions::new().row_policy(|ctx, record| {
+            ctx.get("role") == Some(&json!("admin")) || ctx.get("tenant") == record.get("tenant")
+        });
+        let records = [
+            json!({ "id": "1", "tenant": "a" }),
+            json!({ "id": "2", "tenant": "b" }),
+        ];
+        let mut db = table_with(temp, TableOptions::new(), &records).await;
+        db.set_table_options("t", options);
+
+        db
+    }
+
+    fn tenant(name: &str) -> Context {
+        Context::new().attribute("tenant", name)
+    }
+
+    #[tokio::test]
+    async fn hidden_records_are_out_of_reach() {
+        let temp = TempDb::new("row-policy-reads");
+        let mut db = tenants(&temp).await;
+
+        let records = db
+            .find("t")
+            .with_context(tenant("a"))
+            .run(&mut db)
+            .await
+            .unwrap();
+        assert_eq!(ids(&records), ["1"]);
+        let admin = Context::new().attribute("role", "admin");
+        let records = db.find("t").with_context(admin).run(&mut db).await.unwrap();
+        assert_eq!(ids(&records), ["1", "2"]);
+        // The anonymous context sees nothing.
+        assert!(db.find("t").run(&mut db).await.unwrap().is_empty());
+        assert_eq!(db.find_by_id("t", "1").await.unwrap(), None);
+
+        // Even a write the policy allows can't take over a hidden record.
+        let updated = db
+            .update("t", &json!({ "id": "2", "tenant": "a" }))
+            .with_context(tenant("a"))
+            .run(&mut db)
+            .await;
+        assert!(
+            matches!(updated, Err(OhMyDbError::RecordNotFound { .. })),
+            "{:?}",
+            updated
+        );
+        let deleted = db
+            .delete("t")
+            .with_context(tenant("a"))
+            .run(&mut db)
+            .await
+            .unwrap();
+        assert_eq!(ids(&deleted), ["1"]);
+        let records = db
+            .find("t")
+            .with_context(tenant("b"))
+            .run(&mut db)
+            .await
+            .unwrap();
+        assert_eq!(records, vec![json!({ "id": "2", "tenant": "b" })]);
+    }
+
+    #[tokio::test]
+    async fn writes_must_stay_visible() {
+        let temp = TempDb::new("row-policy-writes");
+        let mut db = tenants(&temp).await;
+
+        let inserted = db
+            .insert("t", &json!({ "id": "3", "tenant": "b" }))
+            .with_context(tenant("a"))
+            .run(&mut db)
+            .await;
+        assert!(
+            matches!(inserted, Err(OhMyDbError::AccessDenied { .. })),
+            "{:?}",
+            inserted
+        );
+        let moved = db
+            .update("t", &json!({ "id": "1", "tenant": "b" }))
+            .with_context(tenant("a"))
+            .run(&mut db)
+            .await;
+        assert!(
+            matches!(moved, Err(OhMyDbError::AccessDenied { .. })),
+            "{:?}",
+            moved
+        );
+
+        db.insert("t", &json!({ "id": "3", "tenant": "a" }))
+            .with_context(tenant("a"))
+            .run(&mut db)
+            .await
+            .unwrap();
+        let records = db
+            .find("t")
+            .with_context(tenant("a"))
+            .run(&mut db)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record["tenant"] == "a"));
+    }
+}
+
+mod auto_increment {
+    use super::*;
+
+    async fn insert_name(db: &mut JsonDB, name: &str) -> Value {
+        let inserted = db
+            .insert("t", &json!({ "name": name }))
+            .run(db)
+            .await
+            .unwrap();
+        inserted[0]["id"].clone()
+    }
+
+    #[tokio::test]
+    async fn sequence_survives_reopening_and_deletes() {
+        let temp = TempDb::new("auto-increment");
+        let options = || TableOptions::new().auto_increment(true);
+        // The sequence starts after the largest integer id already stored.
+        let mut db = table_with(&temp, options(), &[json!({ "id": 5, "name": "e" })]).await;
+        assert_eq!(insert_name(&mut db, "f").await, 6);
+        assert_eq!(insert_name(&mut db, "g").await, 7);
+        db.delete_by_id("t", "7").await.unwrap();
+        // Ids consumed by failed inserts are not reused either.
+        db.set_table_options("t", options().unique(&["name"]));
+        assert!(db
+            .insert("t", &json!({ "name": "f" }))
+            .run(&mut db)
+            .await
+            .is_err());
+        assert_eq!(insert_name(&mut db, "h").await, 9);
+        drop(db);
+
+        let mut db = temp.open().await;
+        db.set_logging(false).set_table_options("t", options());
+        assert_eq!(insert_name(&mut db, "i").await, 10);
+        // Explicit ids are kept, and don't move the sequence.
+        let inserted = db
+            .insert("t", &json!({ "id": 100 }))
+            .run(&mut db)
+            .await
+            .unwrap();
+        assert_eq!(inserted[0]["id"], 100);
+        assert_eq!(insert_name(&mut db, "j").await, 11);
+    }
+}
+
+mod between_str {
+    use super::*;
+
+    #[tokio::test]
+    async fn bounds_are_inclusive_and_lexicographic() {
+        let temp = TempDb::new("between-str");
+        let records = [
+            json!({ "id": "1", "day": "2025-01-01" }),
+            json!({ "id": "2", "day": "2025-01-15" }),
+            json!({ "id": "3", "day": "2025-02-01" }),
+            json!({ "id": "4", "day": "2025-02-01T08:00" }),
+            json!({ "id": "5", "day": 20250110 }),
+            json!({ "id": "6" }),
+        ];
+        let mut db = table_with(&temp, TableOptions::new(), &records).await;
+
+        let mut query = db.find("t");
+        query.where_("day").between_str("2025-01-01", "2025-02-01");
+        // Only strings match, and a longer string sorts after its prefix.
+        assert_eq!(ids(&query.run(&mut db).await.unwrap()), ["1", "2", "3"]);
+
+        let mut query = db.find("t");
+        query
+            .where_("day")
+            .not()
+            .between_str("2025-01-02", "2025-12-31");
+        assert_eq!(ids(&query.run(&mut db).await.unwrap()), ["1", "5", "6"]);
+
+        let mut query = db.find("t");
+        query.where_("day").between_str("2025-02-01", "2025-01-01");
+        assert!(query.run(&mut db).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::utils::values_equal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// An operation of a JSON Patch (RFC 6902), applied to a record by `JsonDB::apply_patch`.
+///
+/// Paths are JSON Pointers (RFC 6901), e.g. `/tags/0` or `/address/city`, and `-` designates the end of an array.
+/// Operations serialize to their standard JSON form, e.g. `{"op": "remove", "path": "/tags/0"}`,
+/// so a patch document received as JSON can be deserialized into a `Vec<PatchOp>` or passed as is.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    /// Adds the value at the path, inserting it into an array or replacing an existing object member.
+    Add { path: String, value: Value },
+    /// Removes the value at the path.
+    Remove { path: String },
+    /// Replaces the value at the path, which must exist.
+    Replace { path: String, value: Value },
+    /// Removes the value at `from` and adds it at the path.
+    Move { from: String, path: String },
+    /// Adds a copy of the value at `from` at the path.
+    Copy { from: String, path: String },
+    /// Checks that the value at the path equals the value, failing the whole patch otherwise.
+    Test { path: String, value: Value },
+}
+
+impl PatchOp {
+    /// Returns the path the operation targets.
+    pub(crate) fn path(&self) -> &str {
+        match self {
+            PatchOp::Add { path, .. }
+            | PatchOp::Remove { path }
+            | PatchOp::Replace { path, .. }
+            | PatchOp::Move { path, .. }
+            | PatchOp::Copy { path, .. }
+            | PatchOp::Test { path, .. } => path,
+        }
+    }
+
+    /// Applies the operation to a document, returning why it cannot be applied if it fails.
+    pub(crate) fn apply(&self, doc: &mut Value) -> Result<(), String> {
+        match self {
+            PatchOp::Add { path, value } => add(doc, &tokens(path)?, value.clone()),
+            PatchOp::Remove { path } => remove(doc, &tokens(path)?).map(|_| ()),
+            PatchOp::Replace { path, value } => {
+                *get_mut(doc, &tokens(path)?).ok_or("the path does not exist")? = value.clone();
+                Ok(())
+            }
+            PatchOp::Move { from, path } => {
+                if path.starts_with(&format!("{}/", from)) {
+                    return Err("a value cannot be moved into one of its children".to_string());
+                }
+                let value = remove(doc, &tokens(from)?)?;
+                add(doc, &tokens(path)?, value)
+            }
+            PatchOp::Copy { from, path } => {
+                let value = get_mut(doc, &tokens(from)?)
+                    .ok_or("the path to copy from does not exist")?
+                    .clone();
+                add(doc, &tokens(path)?, value)
+            }
+            PatchOp::Test { path, value } => match get_mut(doc, &tokens(path)?) {
+                Some(current) if values_equal(current, value) => Ok(()),
+                _ => Err("the test failed".to_string()),
+            },
+        }
+    }
+}
+
+/// Splits a JSON Pointer into its unescaped reference tokens.
+fn tokens(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(pointer) = pointer.strip_prefix('/') else {
+        return Err(format!("'{}' is not a JSON Pointer", pointer));
+    };
+
+    Ok(pointer
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+/// Parses an array index token, accepting `-` and the length of the array (its end) if `end` is set.
+fn index(token: &str, len: usize, end: bool) -> Result<usize, String> {
+    let invalid = || format!("'{}' is not a valid array index", token);
+
+    match token {
+        "-" if end => Ok(len),
+        token if token.len() > 1 && token.starts_with('0') => Err(invalid()),
+        token => match token.parse::<usize>() {
+            Ok(index) if index < len || (end && index == len) => Ok(index),
+            _ => Err(invalid()),
+        },
+    }
+}
+
+fn get_mut<'a>(doc: &'a mut Value, tokens: &[String]) -> Option<&'a mut Value> {
+    tokens.iter().try_fold(doc, |current, token| match current {
+        Value::Object(map) => map.get_mut(token),
+        Value::Array(items) => {
+            let index = index(token, items.len(), false).ok()?;
+            items.get_mut(index)
+        }
+        _ => None,
+    })
+}
+
+fn add(doc: &mut Value, tokens: &[String], value: Value) -> Result<(), String> {
+    let Some((last, parent)) = tokens.split_last() else {
+        *doc = value;
+        return Ok(());
+    };
+
+    match get_mut(doc, parent).ok_or("the parent of the path does not exist")? {
+        Value::Object(map) => {
+            map.insert(last.clone(), value);
+        }
+        Value::Array(items) => {
+            let index = index(last, items.len(), true)?;
+            items.insert(index, value);
+        }
+        _ => return Err("the parent of the path is not an object or an array".to_string()),
+    }
+
+    Ok(())
+}
+
+fn remove(doc: &mut Value, tokens: &[String]) -> Result<Value, String> {
+    let Some((last, parent)) = tokens.split_last() else {
+        return Err("the whole record cannot be removed".to_string());
+    };
+
+    match get_mut(doc, parent) {
+        Some(Value::Object(map)) => map
+            .remove(last)
+            .ok_or_else(|| "the path does not exist".to_string()),
+        Some(Value::Array(items)) => {
+            let index = index(last, items.len(), false)?;
+            Ok(items.remove(index))
+        }
+        _ => Err("the path does not exist".to_string()),
+    }
+}
@@ -1,9 +1,80 @@
+mod archive;
+mod backup;
+mod batch;
+mod bench;
+mod check;
+mod clock;
+mod config;
+mod diff;
+mod doctor;
+mod envelope;
+mod error;
+mod expiry;
+mod explain;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod fields;
+mod filter_doc;
+mod history;
+mod id;
+mod integrity;
 mod json_db;
+mod json_patch;
 mod macros;
+mod merge;
+mod options;
+mod outbox;
+mod outcome;
+mod progress;
+mod query;
+mod queue;
+mod record;
+mod remote;
+mod scrub;
+mod security;
+mod seed;
+mod sink;
+mod slow_log;
+mod sql;
+mod stats;
+mod stream;
+mod tiering;
 mod types;
+mod update;
 mod utils;
 
+pub use batch::BatchReport;
+pub use bench::BenchReport;
+pub use clock::{Clock, ManualClock, SystemClock};
 pub use colored;
+pub use config::Config;
+pub use diff::{DatabaseDiff, RecordChange, TableDiff};
+pub use doctor::{DoctorFinding, DoctorReport};
+pub use error::OhMyDbError;
+pub use explain::Explain;
+pub use fields::Entity;
+pub use history::{Operation, OperationOutcome};
+pub use id::{IdGenerator, Prefixed, Snowflake, Uuid};
+pub use integrity::{IntegrityIssue, IntegrityReport};
 pub use json_db::*;
+pub use json_patch::PatchOp;
+pub use merge::{ArrayMerge, MergeOptions};
+pub use options::{FieldCodec, Nulls, OnDelete, Quota, Relation, TableOptions, Transform};
+pub use outbox::{Outbox, OutboxEvent};
+pub use outcome::RunOutcome;
+pub use progress::Progress;
+pub use query::Query;
+pub use queue::{Job, JobQueue};
+pub use record::Record;
+pub use remote::{RemoteFuture, RemoteTier};
+pub use scrub::{Scrub, ScrubPolicy};
+pub use security::Context;
+pub use seed::{OnConflict, SeedReport};
 pub use serde;
+pub use sink::{BackupSink, DirectorySink, Retention, SinkFuture};
+pub use slow_log::{SlowQuery, SlowQueryLog};
+pub use stats::{FieldStats, IndexStats};
+pub use stream::RecordStream;
+pub use tokio_util::sync::CancellationToken;
+pub use types::Order;
 pub use utils::{get_field_by_name, get_key_chain_value, get_nested_value};
@@ -4,15 +4,36 @@
 /// This macro takes a struct name and a list of field names and types, and generates a struct
 /// with those fields. It also implements the `Debug`, `Serialize`, `Deserialize`, `Clone`,
 /// `PartialEq`, `Eq`, and `Hash` traits for the generated struct.
+///
+/// Fields can be stored under another key with `#[serde(rename = "...")]`. The struct implements `Entity`,
+/// listing the key each field is stored under, so queries can address fields by their Rust name.
 macro_rules! derive_for_struct {
-    ($name:ident, {$($field:ident : $type:ty),*}) => {
+    ($name:ident, {$($(#[serde(rename = $rename:literal)])? $field:ident : $type:ty),*}) => {
         #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
         struct $name {
-            $($field: $type),*
+            $($(#[serde(rename = $rename)])? $field: $type),*
+        }
+
+        impl $crate::Entity for $name {
+            const FIELDS: &'static [(&'static str, &'static str)] = &[
+                $((stringify!($field), $crate::stored_key!($field $(, $rename)?))),*
+            ];
         }
     };
 }
 
+#[doc(hidden)]
+#[macro_export]
+/// Returns the key a field generated by `derive_for_struct` is stored under: its rename, or its name.
+macro_rules! stored_key {
+    ($field:ident) => {
+        stringify!($field)
+    };
+    ($field:ident, $rename:literal) => {
+        $rename
+    };
+}
+
 #[macro_export]
 /// A macro that generates a `Display` implementation for a struct, with colored output.
 ///
@@ -23,7 +44,6 @@ macro_rules! derive_for_struct {
 /// Each field will be displayed on a new line, with the field name in bright yellow and
 /// the field value in bright cyan. The entire output will be enclosed in bright green
 /// curly braces.
-
 #[deprecated(since = "2.1.1", note = "Use `display_object` instead")]
 macro_rules! display_colored {
     ($t:ty , {$($field:ident: $value:ty),*}) => {
@@ -56,15 +76,18 @@ macro_rules! display_colored {
 /// `Deserialize`, `Clone`, `PartialEq`, `Eq`, and `Hash` traits for the generated struct.
 /// Additionally, it generates a `Display` implementation for the struct that formats the
 /// output with colored text using the `display_colored` macro.
+///
+/// Fields can be stored under another key with `#[serde(rename = "...")]`, e.g. `#[serde(rename = "userName")] user_name: String`.
+/// Queries given the struct with `Query::entity` then address the field as `user_name`.
 macro_rules! define_struct_from {
-    ($($t:ident {$($field:ident: $type:ty),*}),* ) => {
+    ($($t:ident {$($(#[serde(rename = $rename:literal)])? $field:ident: $type:ty),*}),* ) => {
         use std::fmt::Display;
         use $crate::serde::{Deserialize, Serialize};
         use $crate::colored::Colorize;
         use $crate::derive_for_struct;
 
         $(
-            derive_for_struct!($t, {$($field: $type),* });
+            derive_for_struct!($t, {$($(#[serde(rename = $rename)])? $field: $type),* });
             // display_colored!($t, {$($field: $type),* });
         )*
     };
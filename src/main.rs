@@ -1,4 +1,7 @@
-use ohmydb::{define_struct_from, JsonDB};
+use ohmydb::{define_struct_from, JsonDB, OhMyDbError, OnConflict, Record};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::Duration;
 
 define_struct_from!(
     User {
@@ -20,8 +23,488 @@ define_struct_from!(
     }
 );
 
+/// A command of the `ohmydb` command line. Without one, the demo below runs.
+enum Command {
+    /// `ohmydb completions <shell>`: prints the completion script of a shell.
+    Completions { shell: String },
+    /// `ohmydb diff <a> <b>`: compares two database files or backups.
+    Diff { a: String, b: String },
+    /// `ohmydb doctor <file>`: diagnoses a database file or archive, suggesting fixes.
+    Doctor { file: String },
+    /// `ohmydb edit <table> <id> [--db <name>]`: edits a record in `$EDITOR`, applying it as an update.
+    Edit {
+        table: String,
+        id: String,
+        db: String,
+    },
+    /// `ohmydb exec <file> [--db <name>] [--json]`: runs the queries of a script, see `JsonDB::query`.
+    Exec {
+        file: String,
+        db: String,
+        json: bool,
+    },
+    /// `ohmydb seed <dir> [--db <name>] [--on-conflict skip|replace|fail]`: loads fixture files into a database.
+    Seed {
+        dir: String,
+        db: String,
+        on_conflict: OnConflict,
+    },
+    /// `ohmydb watch <table> [--where <condition>] [--db <name>] [--interval <ms>] [--json]`: prints the changes
+    /// to the matching records of a table as another process writes them.
+    Watch {
+        table: String,
+        condition: Option<String>,
+        db: String,
+        interval: Duration,
+        json: bool,
+    },
+}
+
+const USAGE: &str = "usage:
+  ohmydb completions bash|zsh|fish
+  ohmydb diff <a> <b>
+  ohmydb doctor <file>
+  ohmydb edit <table> <id> [--db <name>]
+  ohmydb exec <file> [--db <name>] [--json]
+  ohmydb seed <dir> [--db <name>] [--on-conflict skip|replace|fail]
+  ohmydb watch <table> [--where <condition>] [--db <name>] [--interval <ms>] [--json]";
+
+/// The commands with the options they take, the ones in `FLAGS` taking no value.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("completions", &[]),
+    ("diff", &[]),
+    ("doctor", &[]),
+    ("edit", &["--db"]),
+    ("exec", &["--db", "--json"]),
+    ("seed", &["--db", "--on-conflict"]),
+    ("watch", &["--where", "--db", "--interval", "--json"]),
+];
+
+const FLAGS: &[&str] = &["--json"];
+
+/// The arguments of a command: its positional arguments, then its options.
+struct Args<'a> {
+    positional: Vec<&'a str>,
+    options: Vec<(&'a str, Option<&'a str>)>,
+}
+
+impl<'a> Args<'a> {
+    /// Splits the arguments of `command`, rejecting the options it doesn't take.
+    fn parse(command: &str, args: &'a [String]) -> Result<Self, String> {
+        let allowed = COMMANDS
+            .iter()
+            .find(|(name, _)| *name == command)
+            .map_or(&[][..], |(_, options)| *options);
+        let mut parsed = Self {
+            positional: Vec::new(),
+            options: Vec::new(),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                parsed.positional.push(arg);
+                continue;
+            }
+            if !allowed.contains(&arg.as_str()) {
+                return Err(format!("unknown option '{}' for '{}'", arg, command));
+            }
+            let value = match FLAGS.contains(&arg.as_str()) {
+                true => None,
+                false => Some(
+                    args.next()
+                        .ok_or(format!("{} needs a value", arg))?
+                        .as_str(),
+                ),
+            };
+            parsed.options.push((arg, value));
+        }
+
+        Ok(parsed)
+    }
+
+    /// Returns the value of an option, if given.
+    fn value(&self, option: &str) -> Option<&'a str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == option)
+            .and_then(|(_, value)| *value)
+    }
+
+    /// Returns whether a flag is given.
+    fn flag(&self, flag: &str) -> bool {
+        self.options.iter().any(|(name, _)| *name == flag)
+    }
+}
+
+impl Command {
+    /// Parses the arguments following the program name, returning `None` without any, or why they are invalid.
+    fn parse(args: &[String]) -> Result<Option<Self>, String> {
+        let Some((command, args)) = args.split_first() else {
+            return Ok(None);
+        };
+        if !COMMANDS.iter().any(|(name, _)| name == command) {
+            return Err(format!("unknown command '{}'", command));
+        }
+        let args = Args::parse(command, args)?;
+        let db = args.value("--db").unwrap_or_default().to_string();
+
+        let command = match (command.as_str(), args.positional.as_slice()) {
+            ("completions", [shell]) => Self::Completions {
+                shell: shell.to_string(),
+            },
+            ("diff", [a, b]) => Self::Diff {
+                a: a.to_string(),
+                b: b.to_string(),
+            },
+            ("doctor", [file]) => Self::Doctor {
+                file: file.to_string(),
+            },
+            ("edit", [table, id]) => Self::Edit {
+                table: table.to_string(),
+                id: id.to_string(),
+                db,
+            },
+            ("exec", [file]) => Self::Exec {
+                file: file.to_string(),
+                db,
+                json: args.flag("--json"),
+            },
+            ("seed", [dir]) => Self::Seed {
+                dir: dir.to_string(),
+                db,
+                on_conflict: match args.value("--on-conflict") {
+                    Some(value) => OnConflict::parse(value)
+                        .ok_or(format!("unknown conflict handling '{}'", value))?,
+                    None => OnConflict::default(),
+                },
+            },
+            ("watch", [table]) => Self::Watch {
+                table: table.to_string(),
+                condition: args.value("--where").map(str::to_string),
+                db,
+                interval: match args.value("--interval") {
+                    Some(ms) => Duration::from_millis(
+                        ms.parse()
+                            .map_err(|_| format!("invalid interval '{}'", ms))?,
+                    ),
+                    None => Duration::from_secs(1),
+                },
+                json: args.flag("--json"),
+            },
+            (command, _) => return Err(format!("wrong arguments for '{}'", command)),
+        };
+
+        Ok(Some(command))
+    }
+
+    /// Runs the command, printing its report. `doctor` exits with the status `1` if it found a problem.
+    async fn run(self) -> Result<(), OhMyDbError> {
+        match self {
+            Self::Completions { shell } => match completions(&shell) {
+                Some(script) => print!("{}", script),
+                None => {
+                    eprintln!("unsupported shell '{}', expected bash, zsh or fish", shell);
+                    std::process::exit(2);
+                }
+            },
+            Self::Diff { a, b } => print!("{}", JsonDB::diff_files(a, b).await?),
+            Self::Doctor { file } => {
+                let report = JsonDB::doctor_file(file).await?;
+                print!("{}", report);
+                if !report.is_ok() {
+                    std::process::exit(1);
+                }
+            }
+            Self::Edit { table, id, db } => {
+                let mut db = JsonDB::new(&db).await?;
+                db.set_logging(false);
+                match edit(&mut db, &table, &id).await? {
+                    Some(record) => println!("updated {}", serde_json::to_string(&record)?),
+                    None => println!("no changes"),
+                }
+            }
+            Self::Exec { file, db, json } => {
+                let script = tokio::fs::read_to_string(file).await?;
+                let mut db = JsonDB::new(&db).await?;
+                db.set_logging(false);
+
+                for (n, statement) in statements(&script).iter().enumerate() {
+                    let records = match db.query(statement).run(&mut db).await {
+                        Ok(records) => records,
+                        Err(e) => {
+                            eprintln!("statement {} failed: {}", n + 1, statement);
+                            return Err(e);
+                        }
+                    };
+                    match json {
+                        // One array per statement, on a line of its own, e.g. for `jq`.
+                        true => println!("{}", serde_json::to_string(&records)?),
+                        false => {
+                            println!("-- {}", statement);
+                            for record in &records {
+                                println!("{}", serde_json::to_string_pretty(record)?);
+                            }
+                            println!("({} records)\n", records.len());
+                        }
+                    }
+                }
+            }
+            Self::Seed {
+                dir,
+                db,
+                on_conflict,
+            } => {
+                let mut db = JsonDB::new(&db).await?;
+                print!("{}", db.seed(dir, on_conflict).await?);
+            }
+            Self::Watch {
+                table,
+                condition,
+                db,
+                interval,
+                json,
+            } => {
+                let sql = match condition {
+                    Some(condition) => format!("SELECT * FROM {} WHERE {}", table, condition),
+                    None => format!("SELECT * FROM {}", table),
+                };
+                watch(&db, &sql, interval, json).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens the record of `table` with the provided id in `$VISUAL` or `$EDITOR` (`vi` by default), then updates
+/// it with the edited JSON, returning the updated record, or `None` if it was left unchanged.
+///
+/// The edited record must be valid JSON and keep its id. Tables have no schema to validate it against,
+/// and the options of the table are configured in code, so only the constraints stored with the data apply.
+async fn edit(db: &mut JsonDB, table: &str, id: &str) -> Result<Option<Value>, OhMyDbError> {
+    let record = db
+        .find_by_id(table, id)
+        .await?
+        .ok_or_else(|| OhMyDbError::RecordNotFound {
+            table: table.to_string(),
+            id: id.to_string(),
+        })?;
+
+    let path =
+        std::env::temp_dir().join(format!("ohmydb-edit-{}-{}.json", table, std::process::id()));
+    tokio::fs::write(&path, serde_json::to_string_pretty(&record)? + "\n").await?;
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // The editor is run through the shell, so `$EDITOR` can hold arguments, e.g. `code --wait`.
+    let status = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .status()
+        .await;
+    let edited = tokio::fs::read_to_string(&path).await;
+    tokio::fs::remove_file(&path).await.ok();
+
+    if !status?.success() {
+        return Err(OhMyDbError::InvalidParameter {
+            name: "editor".to_string(),
+            reason: format!("'{}' failed, the record is unchanged", editor),
+        });
+    }
+    let edited: Value = serde_json::from_str(&edited?)
+        .map_err(|e| OhMyDbError::InvalidRecord(format!("{}, the record is unchanged", e)))?;
+    if edited == record {
+        return Ok(None);
+    }
+    if Record::from(edited.clone()).id().as_deref() != Some(id) {
+        return Err(OhMyDbError::InvalidRecord(format!(
+            "the id must stay {:?}, the record is unchanged",
+            id
+        )));
+    }
+
+    let updated = db.update(table, &edited).run(db).await?;
+
+    Ok(updated.into_iter().next())
+}
+
+/// Polls the database file every `interval`, printing the records matching `sql` that were added, changed
+/// or removed since the last poll (every matching record on the first one). Runs until interrupted.
+///
+/// The file is only read again once its modification time changes. As writers rewrite the whole file,
+/// a poll may read it half written: the poll is then skipped, except the first one.
+async fn watch(db: &str, sql: &str, interval: Duration, json: bool) -> Result<(), OhMyDbError> {
+    let mut watched: Option<BTreeMap<String, Value>> = None;
+    let mut modified = None;
+
+    loop {
+        let mut db = JsonDB::new(db).await?;
+        db.set_logging(false);
+        let changed = std::fs::metadata(db.get_db_path()).and_then(|m| m.modified())?;
+
+        if modified != Some(changed) {
+            let polled = db.query(sql).run(&mut db).await;
+            let records = match (polled, &watched) {
+                (Ok(records), _) => records,
+                (Err(e), None) => return Err(e),
+                (Err(_), Some(_)) => {
+                    tokio::time::sleep(interval).await;
+                    continue;
+                }
+            };
+            let records: BTreeMap<String, Value> = records
+                .into_iter()
+                .map(|record| {
+                    (
+                        Record::from(record.clone()).id().unwrap_or_default(),
+                        record,
+                    )
+                })
+                .collect();
+
+            let previous = watched.take().unwrap_or_default();
+            for (id, record) in &records {
+                match previous.get(id) {
+                    None => print_change("added", record, json)?,
+                    Some(old) if old != record => print_change("changed", record, json)?,
+                    Some(_) => {}
+                }
+            }
+            for (id, record) in &previous {
+                if !records.contains_key(id) {
+                    print_change("removed", record, json)?;
+                }
+            }
+            watched = Some(records);
+            modified = Some(changed);
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Prints a change to a watched record, as a JSON line with `--json`.
+fn print_change(change: &str, record: &Value, json: bool) -> Result<(), OhMyDbError> {
+    match json {
+        true => println!("{}", json!({ "change": change, "record": record })),
+        false => println!("{:<8}{}", change, serde_json::to_string(record)?),
+    }
+
+    Ok(())
+}
+
+/// Splits a script into its statements, separated by `;` outside of quoted strings.
+/// `--` starts a comment running to the end of the line.
+fn statements(script: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut statement = String::new();
+    let mut quoted = false;
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                quoted = !quoted;
+                statement.push(c);
+            }
+            ';' if !quoted => statements.push(std::mem::take(&mut statement)),
+            '-' if !quoted && chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        statement.push(c);
+                        break;
+                    }
+                }
+            }
+            c => statement.push(c),
+        }
+    }
+    statements.push(statement);
+
+    statements
+        .into_iter()
+        .map(|statement| statement.trim().to_string())
+        .filter(|statement| !statement.is_empty())
+        .collect()
+}
+
+/// Returns the completion script of the commands and their options for `shell`, or `None` if it is not supported.
+fn completions(shell: &str) -> Option<String> {
+    let names: Vec<&str> = COMMANDS.iter().map(|(name, _)| *name).collect();
+    let names = names.join(" ");
+
+    match shell {
+        "bash" | "zsh" => {
+            let mut script = String::new();
+            if shell == "zsh" {
+                script.push_str("#compdef ohmydb\nautoload -U +X bashcompinit && bashcompinit\n");
+            }
+            script.push_str("_ohmydb() {\n    local cur=${COMP_WORDS[COMP_CWORD]}\n");
+            script.push_str("    if [ \"$COMP_CWORD\" -eq 1 ]; then\n");
+            script.push_str(&format!(
+                "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n        return\n    fi\n",
+                names
+            ));
+            script.push_str("    case ${COMP_WORDS[1]} in\n");
+            for (name, options) in COMMANDS {
+                let words = match *name {
+                    "completions" => "bash zsh fish".to_string(),
+                    _ => options.join(" "),
+                };
+                script.push_str(&format!(
+                    "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")) ;;\n",
+                    name, words
+                ));
+            }
+            script.push_str("    esac\n}\ncomplete -o default -F _ohmydb ohmydb\n");
+            Some(script)
+        }
+        "fish" => {
+            let mut script = format!(
+                "complete -c ohmydb -n __fish_use_subcommand -f -a \"{}\"\n",
+                names
+            );
+            script.push_str(
+                "complete -c ohmydb -n \"__fish_seen_subcommand_from completions\" -f -a \"bash zsh fish\"\n",
+            );
+            for (name, options) in COMMANDS {
+                for option in *options {
+                    script.push_str(&format!(
+                        "complete -c ohmydb -n \"__fish_seen_subcommand_from {}\" -l {}{}\n",
+                        name,
+                        option.trim_start_matches("--"),
+                        if FLAGS.contains(option) { "" } else { " -r" }
+                    ));
+                }
+            }
+            Some(script)
+        }
+        _ => None,
+    }
+}
+
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match Command::parse(&args) {
+        Ok(Some(command)) => {
+            if let Err(e) = command.run().await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(None) => {}
+        Err(reason) => {
+            eprintln!("{}\n{}", reason, USAGE);
+            std::process::exit(2);
+        }
+    }
+
     println!("{}", "=".repeat(80));
     println!("JsonDB!");
     println!("{}", "=".repeat(80));
@@ -53,8 +536,8 @@ async fn main() {
     };
 
     // ! Insert data into the tables
-    db.insert("todos", &todo).run().await.ok();
-    db.insert("users", &user).run().await.ok();
+    db.insert("todos", &todo).run(&mut db).await.ok();
+    db.insert("users", &user).run(&mut db).await.ok();
 
     let updated_user = User {
         occupation: "Frontend Developer".to_string(),
@@ -62,20 +545,25 @@ async fn main() {
     };
 
     // ! Update data in the specified table
-    db.update("users", &updated_user).run().await.ok();
+    db.update("users", &updated_user).run(&mut db).await.ok();
 
     // ! Find data in the specified table
     let my_todo = db
         .find("todos")
         .where_("wife.name")
         .equals("Jane Doe")
-        .run()
+        .run(&mut db)
         .await
         .ok()
         .unwrap();
 
     println!("My Todo: {:#?}", my_todo);
 
+    // ! Find typed data in the specified table
+    let users: Vec<User> = db.find("users").run_as(&mut db).await.unwrap_or_default();
+
+    println!("Users: {:#?}", users);
+
     // ! Get the database tables
     let tables = db.get_db_tables().await;
     println!("Tables: {:#?}", tables);
@@ -88,10 +576,10 @@ async fn main() {
     db.delete("users")
         .where_("name")
         .not_equals("Jane Doe")
-        .run()
+        .run(&mut db)
         .await
         .ok();
 
     // ! Delete all datas from the specified table
-    db.delete("todos").run().await.ok();
+    db.delete("todos").run(&mut db).await.ok();
 }
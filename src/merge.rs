@@ -0,0 +1,95 @@
+use serde_json::{Map, Value};
+
+/// How arrays are combined when a record is deep-merged with a partial update.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// The incoming array replaces the stored one.
+    #[default]
+    Replace,
+    /// The incoming items are appended to the stored array.
+    Concat,
+    /// Items are matched by the value of the given key: matching items are deep-merged,
+    /// the others are appended. Items that aren't objects are appended only if not already present.
+    UnionBy(String),
+}
+
+/// Options controlling how `JsonDB::merge` combines a partial update with the stored record.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeOptions {
+    arrays: ArrayMerge,
+    /// Whether `null` values remove the fields they patch, as in a JSON Merge Patch.
+    null_removes: bool,
+}
+
+impl MergeOptions {
+    /// Creates a new `MergeOptions` instance replacing arrays.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the options applying a JSON Merge Patch (RFC 7386): arrays are replaced and `null` values remove fields.
+    pub(crate) fn merge_patch() -> Self {
+        Self {
+            arrays: ArrayMerge::Replace,
+            null_removes: true,
+        }
+    }
+
+    /// Sets how arrays are combined.
+    pub fn arrays(mut self, arrays: ArrayMerge) -> Self {
+        self.arrays = arrays;
+        self
+    }
+}
+
+/// Deep-merges `patch` into `target`.
+///
+/// Objects are merged key by key, arrays are combined according to `options`,
+/// and every other value of `patch` replaces the one in `target`.
+pub(crate) fn deep_merge(target: &mut Value, patch: Value, options: &MergeOptions) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (key, value) in patch {
+                if options.null_removes && value.is_null() {
+                    target.remove(&key);
+                    continue;
+                }
+                match target.get_mut(&key) {
+                    Some(existing) => deep_merge(existing, value, options),
+                    None => {
+                        let mut inserted = Value::Null;
+                        deep_merge(&mut inserted, value, options);
+                        target.insert(key, inserted);
+                    }
+                }
+            }
+        }
+        (Value::Array(target), Value::Array(patch)) => merge_arrays(target, patch, options),
+        // A merge patch replacing a value with an object still removes its `null` fields.
+        (target, patch) if options.null_removes && patch.is_object() => {
+            *target = Value::Object(Map::new());
+            deep_merge(target, patch, options);
+        }
+        (target, patch) => *target = patch,
+    }
+}
+
+fn merge_arrays(target: &mut Vec<Value>, patch: Vec<Value>, options: &MergeOptions) {
+    match &options.arrays {
+        ArrayMerge::Replace => *target = patch,
+        ArrayMerge::Concat => target.extend(patch),
+        ArrayMerge::UnionBy(key) => {
+            for item in patch {
+                let existing = match item.get(key) {
+                    Some(id) => target.iter_mut().find(|t| t.get(key) == Some(id)),
+                    None => target.iter_mut().find(|t| **t == item),
+                };
+
+                match existing {
+                    Some(existing) => deep_merge(existing, item, options),
+                    None => target.push(item),
+                }
+            }
+        }
+    }
+}
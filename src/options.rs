@@ -0,0 +1,462 @@
+use crate::error::OhMyDbError;
+use crate::id::{assign_id, IdGenerator};
+use crate::security::{Context, RowPolicy};
+use crate::types::Comparator;
+use crate::utils::get_nested_mut;
+use serde_json::Value;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
+
+type WriteFn = Arc<dyn Fn(&Value) -> Result<Value, String> + Send + Sync>;
+type ReadFn = Arc<dyn Fn(&Value) -> Value + Send + Sync>;
+
+/// A string transformation applied to a configured field whenever a record is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    /// Removes leading and trailing whitespace.
+    Trim,
+    /// Converts the string to lowercase.
+    Lowercase,
+    /// Replaces every run of whitespace with a single space and trims the ends.
+    CollapseWhitespace,
+}
+
+impl Transform {
+    fn apply(&self, s: &str) -> String {
+        match self {
+            Transform::Trim => s.trim().to_string(),
+            Transform::Lowercase => s.to_lowercase(),
+            Transform::CollapseWhitespace => s.split_whitespace().collect::<Vec<&str>>().join(" "),
+        }
+    }
+}
+
+/// How a unique constraint treats records holding `null` in, or missing, one of its fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Nulls {
+    /// Such records are not constrained, so any number of them may be stored (as in Postgres by default).
+    #[default]
+    Distinct,
+    /// A missing field counts as `null` and `null` values are equal to each other, so at most one record
+    /// may hold them for otherwise equal values.
+    NotDistinct,
+}
+
+/// A limit on the size of a table, enforced on every write by `TableOptions::quota`.
+///
+/// Sizes are measured on the stored records serialized as compact JSON, envelope included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quota {
+    /// The maximum number of records in the table.
+    Records(usize),
+    /// The maximum size of a single record, in bytes.
+    RecordBytes(usize),
+    /// The maximum size of all the records of the table together, in bytes.
+    TableBytes(usize),
+}
+
+impl fmt::Display for Quota {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quota::Records(max) => write!(f, "at most {} records", max),
+            Quota::RecordBytes(max) => write!(f, "at most {} bytes per record", max),
+            Quota::TableBytes(max) => write!(f, "at most {} bytes of records", max),
+        }
+    }
+}
+
+/// A custom serializer/validator for a single field.
+///
+/// The write function runs on every insert and update and may either return the value to store
+/// (e.g. a normalized phone number or a rounded price) or reject the write with a reason.
+/// The optional read function converts the stored value back whenever the record is queried.
+#[derive(Clone, Default)]
+pub struct FieldCodec {
+    write: Option<WriteFn>,
+    read: Option<ReadFn>,
+}
+
+impl FieldCodec {
+    /// Creates a new `FieldCodec` that leaves the field untouched.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the function invoked on write. Returning `Err(reason)` rejects the whole write.
+    pub fn on_write<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.write = Some(Arc::new(f));
+        self
+    }
+
+    /// Sets the function invoked on every record returned from a read.
+    pub fn on_read<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Value) -> Value + Send + Sync + 'static,
+    {
+        self.read = Some(Arc::new(f));
+        self
+    }
+}
+
+impl Debug for FieldCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FieldCodec")
+            .field("write", &self.write.is_some())
+            .field("read", &self.read.is_some())
+            .finish()
+    }
+}
+
+/// What happens to the records referencing a record through a `Relation` when that record is deleted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnDelete {
+    /// The referencing records are left unchanged, their references dangling.
+    #[default]
+    NoAction,
+    /// The referencing records are deleted too, cascading further through their own relations.
+    Cascade,
+    /// The referencing field is set to `null`, or the reference is removed from it if it holds an array.
+    SetNull,
+    /// The delete fails with `OhMyDbError::StillReferenced` while referencing records exist.
+    Restrict,
+}
+
+/// A reference from a field of a table to the records of another table, declared with `TableOptions::reference`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Relation {
+    table: String,
+    field: String,
+    on_delete: OnDelete,
+}
+
+impl Relation {
+    /// Creates a new `Relation` to the records of `table` whose `field` (a dot-separated key chain) equals the referencing field.
+    pub fn new(table: &str, field: &str) -> Self {
+        Self {
+            table: table.to_string(),
+            field: field.to_string(),
+            on_delete: OnDelete::NoAction,
+        }
+    }
+
+    /// Sets what happens to the referencing records when a referenced record is deleted (`OnDelete::NoAction` by default).
+    ///
+    /// Deletes apply the policies of every relation to the deleted table in the same `run`, ignoring row-level
+    /// security policies, and either apply all the resulting changes or, if a `Restrict` relation is hit, none.
+    pub fn on_delete(mut self, action: OnDelete) -> Self {
+        self.on_delete = action;
+        self
+    }
+
+    /// Returns the referenced table.
+    pub(crate) fn table(&self) -> &str {
+        &self.table
+    }
+
+    /// Returns the field of the referenced records.
+    pub(crate) fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Returns what happens to the referencing records when a referenced record is deleted.
+    pub(crate) fn delete_action(&self) -> OnDelete {
+        self.on_delete
+    }
+}
+
+/// Per-table write options applied by `JsonDB` before a record is stored.
+///
+/// Options are registered with `JsonDB::set_table_options` and are applied on every insert and update
+/// targeting the table.
+#[derive(Clone, Debug, Default)]
+pub struct TableOptions {
+    normalize_unicode: bool,
+    transforms: Vec<(String, Transform)>,
+    codecs: Vec<(String, FieldCodec)>,
+    id_generator: Option<Arc<dyn IdGenerator>>,
+    auto_increment: bool,
+    soft_delete: bool,
+    slug: Option<(String, String)>,
+    envelope: bool,
+    row_policy: Option<RowPolicy>,
+    unique: Vec<(Vec<String>, Nulls)>,
+    relations: Vec<(String, Relation)>,
+    quotas: Vec<Quota>,
+}
+
+impl TableOptions {
+    /// Creates a new `TableOptions` instance with every option disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enables or disables NFC normalization of every string value written to the table.
+    ///
+    /// With normalization enabled, visually identical strings (e.g. a precomposed "é" and "e" followed by a
+    /// combining accent) are stored identically, so they can't create duplicates or miss matches.
+    /// The operands of the filters of queries on the table (`equals`, `in_`, `like`, ...) are normalized the same way.
+    pub fn normalize_unicode(mut self, enabled: bool) -> Self {
+        self.normalize_unicode = enabled;
+        self
+    }
+
+    /// Registers a `Transform` for the field at `field` (a dot-separated key chain).
+    ///
+    /// Transforms only affect string values and are applied in the order they were registered,
+    /// so `.transform("email", Transform::Trim).transform("email", Transform::Lowercase)` trims first.
+    pub fn transform(mut self, field: &str, transform: Transform) -> Self {
+        self.transforms.push((field.to_string(), transform));
+        self
+    }
+
+    /// Registers a `FieldCodec` for the field at `field` (a dot-separated key chain).
+    ///
+    /// Codecs run after transforms and are skipped for records that don't contain the field.
+    pub fn codec(mut self, field: &str, codec: FieldCodec) -> Self {
+        self.codecs.push((field.to_string(), codec));
+        self
+    }
+
+    /// Sets the `IdGenerator` used to assign an `id` to records inserted into the table without one (or with a `null` or empty one).
+    pub fn id_generator<G>(mut self, generator: G) -> Self
+    where
+        G: IdGenerator + 'static,
+    {
+        self.id_generator = Some(Arc::new(generator));
+        self
+    }
+
+    /// Enables or disables auto-increment ids: records inserted without an `id` (or with a `null` or empty one)
+    /// get the next integer of a sequence persisted in the database file, starting after the largest integer id
+    /// already stored. Ids consumed by failed inserts are not reused. Takes precedence over `id_generator`.
+    pub fn auto_increment(mut self, enabled: bool) -> Self {
+        self.auto_increment = enabled;
+        self
+    }
+
+    /// Returns whether the table assigns auto-increment ids.
+    pub(crate) fn is_auto_increment(&self) -> bool {
+        self.auto_increment
+    }
+
+    /// Enables or disables envelope mode for the table.
+    ///
+    /// In envelope mode each record is stored under `data`, next to the library-managed `created_at`
+    /// and `updated_at` timestamps (milliseconds since the Unix epoch), a `revision` counter bumped on
+    /// every update and a `deleted_at` marker. Filters, sorting and results keep working on the user
+    /// data only, so user structs don't need any bookkeeping fields.
+    pub fn envelope(mut self, enabled: bool) -> Self {
+        self.envelope = enabled;
+        self
+    }
+
+    /// Enables or disables soft deletes for the table.
+    ///
+    /// With soft deletes, `delete` marks the records it selects with a `deleted_at` timestamp (milliseconds since
+    /// the Unix epoch) instead of removing them: the envelope marker in envelope mode, a `deleted_at` field otherwise.
+    /// Reads, updates, deletes, joins and populates then skip them, unless the query uses `with_deleted`.
+    /// `JsonDB::restore` brings them back and `JsonDB::purge` removes them for good, applying the `OnDelete`
+    /// policies of the relations to the table. Soft-deleted records keep their id taken.
+    pub fn soft_delete(mut self, enabled: bool) -> Self {
+        self.soft_delete = enabled;
+        self
+    }
+
+    /// Returns whether deletes only mark the records of the table as deleted.
+    pub(crate) fn is_soft_delete(&self) -> bool {
+        self.soft_delete
+    }
+
+    /// Returns whether the table stores its records in envelopes.
+    pub(crate) fn is_enveloped(&self) -> bool {
+        self.envelope
+    }
+
+    /// Sets a row-level security policy, evaluated with the `Context` of every operation on the table.
+    ///
+    /// Records the policy rejects are invisible to reads, updates and deletes, and inserting or updating a
+    /// record into one the policy rejects fails with `OhMyDbError::AccessDenied`. Policies see the user data,
+    /// even in envelope mode, e.g. `|ctx, record| ctx.user_id() == record["owner"].as_str()`.
+    pub fn row_policy<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(&Context, &Value) -> bool + Send + Sync + 'static,
+    {
+        self.row_policy = Some(RowPolicy::new(policy));
+        self
+    }
+
+    /// Limits the size of the table: inserts and updates that would exceed the quota fail with
+    /// `OhMyDbError::QuotaExceeded` and change nothing, while deletes are always allowed.
+    /// Quotas of different kinds combine, and setting a quota of the same kind again replaces it.
+    pub fn quota(mut self, quota: Quota) -> Self {
+        self.quotas
+            .retain(|q| std::mem::discriminant(q) != std::mem::discriminant(&quota));
+        self.quotas.push(quota);
+        self
+    }
+
+    /// Returns the quotas of the table.
+    pub(crate) fn quotas(&self) -> &[Quota] {
+        &self.quotas
+    }
+
+    /// Returns the row-level security policy of the table, if any.
+    pub(crate) fn policy(&self) -> Option<&RowPolicy> {
+        self.row_policy.as_ref()
+    }
+
+    /// Derives a unique slug stored in `field` from the string in `source` whenever a record is inserted
+    /// without one, e.g. a `title` of "Buy groceries" gives a `slug` of "buy-groceries".
+    ///
+    /// On collision with an existing record of the table, the slug is suffixed with `-2`, `-3`, ...
+    /// Records can then be looked up with `JsonDB::find_by_slug`.
+    pub fn slug(mut self, source: &str, field: &str) -> Self {
+        self.slug = Some((source.to_string(), field.to_string()));
+        self
+    }
+
+    /// Returns the `(source, field)` pair of the configured slug, if any.
+    pub(crate) fn slug_fields(&self) -> Option<(&str, &str)> {
+        self.slug
+            .as_ref()
+            .map(|(source, field)| (source.as_str(), field.as_str()))
+    }
+
+    /// Adds a unique constraint over the provided fields (dot-separated key chains), e.g. `&["user_id", "date"]`
+    /// for at most one attendance record per user and day, or `&["email"]` for a single unique field.
+    ///
+    /// Inserts and updates that would store two records with equal values for all the fields fail with
+    /// `OhMyDbError::UniqueViolation`. Records missing one of the fields, or holding `null` in it, are not constrained.
+    /// Several constraints can be added to the same table.
+    pub fn unique(self, fields: &[&str]) -> Self {
+        self.unique_with(fields, Nulls::Distinct)
+    }
+
+    /// Adds a unique constraint over the provided fields like `unique`, choosing with `nulls` whether records
+    /// missing one of the fields, or holding `null` in it, are left unconstrained or compared like any other value.
+    pub fn unique_with(mut self, fields: &[&str], nulls: Nulls) -> Self {
+        self.unique.push((
+            fields.iter().map(|field| field.to_string()).collect(),
+            nulls,
+        ));
+        self
+    }
+
+    /// Returns the field lists of the unique constraints of the table, with their handling of nulls.
+    pub(crate) fn unique_constraints(&self) -> &[(Vec<String>, Nulls)] {
+        &self.unique
+    }
+
+    /// Declares that `field` (a dot-separated key chain) references the records described by `relation`,
+    /// e.g. `.reference("assignee_id", Relation::new("users", "id"))`.
+    ///
+    /// Queries can then replace the field with the referenced records with `Query::populate`.
+    pub fn reference(mut self, field: &str, relation: Relation) -> Self {
+        self.relations.push((field.to_string(), relation));
+        self
+    }
+
+    /// Returns the declared relations, with their referencing field.
+    pub(crate) fn relations(&self) -> &[(String, Relation)] {
+        &self.relations
+    }
+
+    /// Returns the relation declared for `field`, if any.
+    pub(crate) fn relation(&self, field: &str) -> Option<&Relation> {
+        self.relations
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, relation)| relation)
+    }
+
+    /// Assigns a generated id to the `key` field of a record about to be inserted, if it has none and a generator is configured.
+    pub(crate) fn assign_id(&self, item: Value, key: &str) -> Value {
+        match &self.id_generator {
+            Some(generator) => assign_id(item, key, generator.as_ref()),
+            None => item,
+        }
+    }
+
+    /// Applies the configured options to a record that is about to be written.
+    ///
+    /// Returns an `OhMyDbError::InvalidField` error if a field codec rejects the record.
+    pub(crate) fn apply(&self, mut item: Value) -> Result<Value, OhMyDbError> {
+        if self.normalize_unicode {
+            normalize_strings(&mut item);
+        }
+
+        for (field, transform) in &self.transforms {
+            if let Some(Value::String(s)) = get_nested_mut(&mut item, field) {
+                *s = transform.apply(s);
+            }
+        }
+
+        for (field, codec) in &self.codecs {
+            if let (Some(write), Some(value)) = (&codec.write, get_nested_mut(&mut item, field)) {
+                *value = write(value).map_err(|reason| OhMyDbError::InvalidField {
+                    field: field.clone(),
+                    reason,
+                })?;
+            }
+        }
+
+        Ok(item)
+    }
+
+    /// NFC-normalizes the string operands of a filter condition on the table, if normalization is enabled,
+    /// so they compare equal to the normalized stored values.
+    pub(crate) fn normalize_operand(&self, comparator: &mut Comparator) {
+        if !self.normalize_unicode {
+            return;
+        }
+
+        match comparator {
+            Comparator::Equals(value)
+            | Comparator::NotEquals(value)
+            | Comparator::Contains(value) => normalize_strings(value),
+            Comparator::In(values) | Comparator::NotIn(values) => {
+                values.iter_mut().for_each(normalize_strings)
+            }
+            Comparator::BetweenStr((low, high)) => {
+                *low = low.nfc().collect();
+                *high = high.nfc().collect();
+            }
+            Comparator::Like(s)
+            | Comparator::StartsWith(s)
+            | Comparator::EndsWith(s)
+            | Comparator::SoundsLike(s) => *s = s.nfc().collect(),
+            Comparator::Not(comparator) => self.normalize_operand(comparator),
+            _ => {}
+        }
+    }
+
+    /// Returns whether a field codec changes the records returned by reads.
+    pub(crate) fn decodes(&self) -> bool {
+        self.codecs.iter().any(|(_, codec)| codec.read.is_some())
+    }
+
+    /// Applies the read side of the configured field codecs to a record returned from a query.
+    pub(crate) fn decode(&self, mut item: Value) -> Value {
+        for (field, codec) in &self.codecs {
+            if let (Some(read), Some(value)) = (&codec.read, get_nested_mut(&mut item, field)) {
+                *value = read(value);
+            }
+        }
+
+        item
+    }
+}
+
+/// Recursively NFC-normalizes every string value inside `value`.
+fn normalize_strings(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = s.nfc().collect(),
+        Value::Array(arr) => arr.iter_mut().for_each(normalize_strings),
+        Value::Object(obj) => obj.values_mut().for_each(normalize_strings),
+        _ => {}
+    }
+}
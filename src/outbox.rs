@@ -0,0 +1,84 @@
+use crate::error::OhMyDbError;
+use crate::json_db::JsonDB;
+use serde::Serialize;
+use serde_json::Value;
+use std::time::SystemTime;
+
+/// An event waiting in the outbox to be published, as returned by `Outbox::poll`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutboxEvent {
+    /// The id of the event, unique and increasing with the time it was enqueued.
+    pub id: String,
+    /// The event itself, as serialized when it was enqueued.
+    pub event: Value,
+    /// When the event was enqueued.
+    pub enqueued_at: SystemTime,
+}
+
+/// The outbox of a database, returned by `JsonDB::outbox`: events persisted in the database file until a
+/// publisher acknowledges them, e.g. to relay domain events to a message broker.
+///
+/// Events attached to a write with `Query::with_event` are persisted in the same save as the changes,
+/// so they are never lost nor published for changes that didn't happen. Publishers `poll` the pending events,
+/// publish them, then `ack` them; an event whose publisher crashed before acknowledging it is polled again,
+/// so delivery is at least once.
+pub struct Outbox<'a> {
+    db: &'a mut JsonDB,
+}
+
+impl<'a> Outbox<'a> {
+    pub(crate) fn new(db: &'a mut JsonDB) -> Self {
+        Self { db }
+    }
+
+    /// Enqueues an event on its own and saves the database file.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The `T` event to publish.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::Serde` error if the event cannot be serialized,
+    /// or the error saving the database file failed with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the id of the enqueued event.
+    pub async fn enqueue<T>(&mut self, event: &T) -> Result<String, OhMyDbError>
+    where
+        T: Serialize,
+    {
+        let id = self.db.push_event(serde_json::to_value(event)?);
+        self.db.save().await?;
+
+        Ok(id)
+    }
+
+    /// Returns the oldest pending events, in the order they were enqueued, without removing them.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of events to return.
+    pub fn poll(&self, limit: usize) -> Vec<OutboxEvent> {
+        self.db.pending_events(limit)
+    }
+
+    /// Acknowledges that an event was published, removing it from the outbox and saving the database file.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the event.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether the event was pending, `false` if it was already acknowledged.
+    pub async fn ack(&mut self, id: &str) -> Result<bool, OhMyDbError> {
+        let removed = self.db.remove_event(id);
+        if removed {
+            self.db.save().await?;
+        }
+
+        Ok(removed)
+    }
+}
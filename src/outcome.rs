@@ -0,0 +1,14 @@
+use serde_json::Value;
+
+/// What running a query did, returned by `Query::run_outcome`, so writes can be logged or audited
+/// without reading the records first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunOutcome {
+    /// The records `Query::run` returns: the read, inserted or updated records, or the deleted ones as stored.
+    pub records: Vec<Value>,
+    /// The records as a read returned them before the query changed them: the previous version of the updated
+    /// records, in the order of `records`, or the deleted records. Empty for reads and inserts.
+    pub previous: Vec<Value>,
+    /// The number of records inserted, updated or deleted, not counting the records changed by cascades.
+    pub affected: usize,
+}
@@ -0,0 +1,40 @@
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// A progress update emitted by long-running operations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Progress {
+    /// The name of the operation stage currently running (e.g. `"integrity"`).
+    pub stage: String,
+    /// The number of items processed so far in this stage.
+    pub processed: usize,
+    /// The total number of items the stage will process.
+    pub total: usize,
+}
+
+/// A callback receiving `Progress` updates, registered with `JsonDB::on_progress`.
+#[derive(Clone)]
+pub(crate) struct ProgressCallback(Arc<dyn Fn(&Progress) + Send + Sync>);
+
+impl ProgressCallback {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    pub(crate) fn report(&self, stage: &str, processed: usize, total: usize) {
+        (self.0)(&Progress {
+            stage: stage.to_string(),
+            processed,
+            total,
+        });
+    }
+}
+
+impl Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback")
+    }
+}
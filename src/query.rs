@@ -0,0 +1,1513 @@
+use crate::error::OhMyDbError;
+use crate::explain::Explain;
+use crate::fields::{self, Entity};
+use crate::filter_doc;
+use crate::json_db::JsonDB;
+use crate::outcome::RunOutcome;
+use crate::record::Record;
+use crate::security::Context;
+#[cfg(feature = "regex")]
+use crate::types::Pattern;
+use crate::types::{Comparator, Join, Order, Predicate, Runner};
+use crate::update::UpdateOp;
+use crate::utils::{compare_numbers, soundex};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Number, Value};
+use serde_json_path::JsonPath;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+/// A database operation being built, e.g. a read with its filters, sorting and projection.
+///
+/// Queries are created by the `JsonDB` entry points (`find`, `insert`, `update`, `merge`, `delete`, `query`)
+/// and own their runners queue, so several of them can be built at the same time and held across `.await`
+/// points without borrowing the database. The database is only borrowed when the query is run.
+///
+/// Running a query leaves it untouched, so a query can be built once, stored (or cloned) and run repeatedly,
+/// with different values bound to its parameters (see `param` and `bind`) each time.
+#[derive(Clone, Debug, Default)]
+pub struct Query {
+    pub(crate) runners: VecDeque<Runner>,
+    pub(crate) cancellation: Option<CancellationToken>,
+    pub(crate) context: Option<Context>,
+    pub(crate) params: HashMap<String, Value>,
+    pub(crate) idempotency_key: Option<String>,
+    pub(crate) expires_in: Option<Duration>,
+    pub(crate) from_disk: bool,
+    pub(crate) events: Vec<Value>,
+    pub(crate) fields: Option<&'static [(&'static str, &'static str)]>,
+}
+
+impl Query {
+    /// Creates a new query performing the provided method.
+    pub(crate) fn new(runner: Runner) -> Self {
+        Self {
+            runners: VecDeque::from([runner]),
+            ..Self::default()
+        }
+    }
+
+    /// Makes the query translate the Rust field names of `T` it is given (by `where_`, `or_where`, `order_by`,
+    /// `select`, `distinct` and the aggregates such as `sum`) to the keys they are stored under, e.g.
+    /// `db.find("users").entity::<User>().where_("user_name").equals("ann")` for a field renamed to `userName`.
+    ///
+    /// Only the first key of a dot-separated chain is translated, and names that are not Rust field names of `T`
+    /// (e.g. stored keys) are kept as they are. See `JsonDB::resolve_field` to translate a single name.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn entity<T>(&mut self) -> &mut Self
+    where
+        T: Entity,
+    {
+        self.fields = Some(T::FIELDS);
+
+        self
+    }
+
+    /// Returns the key a field name given to the query is stored under, see `entity`.
+    pub(crate) fn stored_key(&self, field: &str) -> String {
+        match self.fields {
+            Some(fields) => fields::translate(fields, field),
+            None => field.to_string(),
+        }
+    }
+
+    /// Attaches a `CancellationToken` to the operation being built.
+    ///
+    /// The token is checked while records are scanned and right before any change is applied: once it is cancelled,
+    /// the terminal (`run`, `count`, `first`, ...) returns `OhMyDbError::Cancelled` and the database is left untouched.
+    /// Changes that already started being applied are always completed, so the database stays consistent.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token the caller cancels to abort the operation.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn with_cancellation(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancellation = Some(token);
+
+        self
+    }
+
+    /// Attaches a `Context` to the operation being built, identifying who it runs on behalf of.
+    ///
+    /// The context is passed to the row-level security policies of the target table (see `TableOptions::row_policy`).
+    /// Operations without a context run with the default, anonymous one.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - The context of the operation.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn with_context(&mut self, context: Context) -> &mut Self {
+        self.context = Some(context);
+
+        self
+    }
+
+    /// Attaches an idempotency key to the insert being built, so retrying it (e.g. when a message is redelivered
+    /// by an at-least-once queue) doesn't insert the record twice.
+    ///
+    /// The first successful insert with the key persists it, along with the inserted record, in the database file.
+    /// Later inserts into the same table with the same key change nothing and return that original record,
+    /// whatever their item. Keys are scoped per table and kept until they are pruned with `JsonDB::prune_idempotency`.
+    /// Other operations ignore the key.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key identifying the insert across retries, e.g. a message id.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn with_idempotency_key(&mut self, key: &str) -> &mut Self {
+        self.idempotency_key = Some(key.to_string());
+
+        self
+    }
+
+    /// Makes the record inserted by the query being built expire after `ttl`, e.g. for session or cache tables:
+    /// `db.insert("sessions", &session).expires_in(Duration::from_secs(3600)).run(&mut db)`.
+    ///
+    /// The expiry is stored with the record, measured with the clock of the database (see `JsonDB::set_clock`)
+    /// and kept by later updates. Once it is due, the record is left out of reads, updates and deletes, its id
+    /// can be inserted again, and it is purged from the database file by the next save (see `JsonDB::purge_expired`).
+    /// Other operations ignore it.
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - How long the record lives after the insert.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn expires_in(&mut self, ttl: Duration) -> &mut Self {
+        self.expires_in = Some(ttl);
+
+        self
+    }
+
+    /// Makes the query being built read the database file instead of the in-memory copy of the data, for callers
+    /// that must see the writes of other processes immediately, e.g. `db.find("todos").from_disk().run(&mut db)`.
+    ///
+    /// Before running, the tables the query reads (its table, and the joined and populated ones) are reloaded from
+    /// the file, replacing their in-memory records, so later queries see the reloaded data too. This costs a read
+    /// and a parse of the whole file per run.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn from_disk(&mut self) -> &mut Self {
+        self.from_disk = true;
+
+        self
+    }
+
+    /// Makes the query being built read the in-memory copy of the data, which reflects every write made through
+    /// this `JsonDB` instance but not the ones of other processes. This is the default, undoing `from_disk`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn from_memory(&mut self) -> &mut Self {
+        self.from_disk = false;
+
+        self
+    }
+
+    /// Attaches an event to the operation being built, enqueued in the outbox (see `JsonDB::outbox`) when the
+    /// operation succeeds and persisted in the same save as its changes. Several events can be attached.
+    ///
+    /// If the event cannot be serialized, running the query returns `OhMyDbError::InvalidRecord`.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The `T` event to publish once the operation is persisted.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn with_event<T>(&mut self, event: &T) -> &mut Self
+    where
+        T: Serialize,
+    {
+        match serde_json::to_value(event) {
+            Ok(event) => self.events.push(event),
+            Err(e) => self.runners.push_back(Runner::Invalid(e.to_string())),
+        }
+
+        self
+    }
+
+    /// Adds a `Runner::Where(field.to_string())` to the end of the runners queue, filtering the data based on the provided field.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn where_(&mut self, field: &str) -> &mut Self {
+        self.runners.push_back(Runner::Where(field.to_string()));
+
+        self
+    }
+
+    /// Adds a `Runner::WherePath(..)` to the end of the runners queue, filtering the data based on the nodes a
+    /// JSONPath expression (RFC 9535) selects in each record, e.g. `where_path("$.items[?@.price > 10].sku")`.
+    ///
+    /// The following comparator keeps a record if any selected node satisfies it; records where the path
+    /// selects nothing are compared as `null`, so `where_path(..).is_set()` keeps records where it selects something.
+    /// The expression is parsed once here. If it is invalid, running the query returns `OhMyDbError::InvalidPattern`.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The JSONPath expression to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn where_path(&mut self, path: &str) -> &mut Self {
+        let runner = match JsonPath::parse(path) {
+            Ok(path) => Runner::WherePath(path),
+            Err(e) => Runner::InvalidPattern {
+                pattern: path.to_string(),
+                reason: e.to_string(),
+            },
+        };
+        self.runners.push_back(runner);
+
+        self
+    }
+
+    /// Adds another condition on the provided field, combined with the previous ones with AND semantics.
+    /// This is equivalent to calling `where_` again, and reads better in long chains.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn and_where(&mut self, field: &str) -> &mut Self {
+        self.where_(field)
+    }
+
+    /// Adds a `Runner::OrWhere(field.to_string())` to the end of the runners queue, starting a new group of conditions
+    /// combined with the previous ones with OR semantics.
+    ///
+    /// AND binds tighter than OR, so `.where_("a").equals("x").and_where("b").equals("y").or_where("c").equals("z")`
+    /// matches records where `(a == x AND b == y) OR c == z`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn or_where(&mut self, field: &str) -> &mut Self {
+        self.runners.push_back(Runner::OrWhere(field.to_string()));
+
+        self
+    }
+
+    /// Adds a `Runner::Not` to the end of the runners queue, negating the comparator that follows it.
+    ///
+    /// For example, `.where_("status").not().in_(statuses)` matches records whose status is not in `statuses`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn not(&mut self) -> &mut Self {
+        self.runners.push_back(Runner::Not);
+
+        self
+    }
+
+    /// Adds a `Runner::Custom` to the end of the runners queue, filtering the data with the comparator registered
+    /// under `name` with `JsonDB::register_comparator`, e.g. `.where_("name").custom("phonetic_eq", "smith")`.
+    ///
+    /// Running the query returns an `OhMyDbError::UnknownComparator` error if no comparator is registered under `name`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the registered comparator.
+    /// * `argument` - The value passed to the comparator along with the field value.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn custom<V>(&mut self, name: &str, argument: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners
+            .push_back(Runner::Custom(name.to_string(), argument.into()));
+
+        self
+    }
+
+    /// Adds a `Runner::Join` to the end of the runners queue, attaching to every result of the read the records
+    /// of `table` whose `foreign_field` equals the result's `local_field`, e.g. `.join("users", "assignee_id", "id")`.
+    ///
+    /// The matching records are attached as an array under the name of the joined table, which is empty if nothing matches.
+    /// They are decoded like the results themselves, the table's row-level security policy applying to the query's context.
+    /// Projections made with `select` do not remove the joined records.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The table to join.
+    /// * `local_field` - The (possibly nested, dot-separated) field of the queried records.
+    /// * `foreign_field` - The (possibly nested, dot-separated) field of the joined records.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn join(&mut self, table: &str, local_field: &str, foreign_field: &str) -> &mut Self {
+        self.join_as(table, local_field, foreign_field, table)
+    }
+
+    /// Adds a `Runner::Join` to the end of the runners queue like `join`, attaching the matching records under `key`
+    /// instead of the name of the joined table, e.g. `.join_as("users", "assignee_id", "id", "assignee")`.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - The table to join.
+    /// * `local_field` - The (possibly nested, dot-separated) field of the queried records.
+    /// * `foreign_field` - The (possibly nested, dot-separated) field of the joined records.
+    /// * `key` - The key the matching records are attached under.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn join_as(
+        &mut self,
+        table: &str,
+        local_field: &str,
+        foreign_field: &str,
+        key: &str,
+    ) -> &mut Self {
+        self.runners.push_back(Runner::Join(Join {
+            table: table.to_string(),
+            local: local_field.to_string(),
+            foreign: foreign_field.to_string(),
+            key: key.to_string(),
+        }));
+
+        self
+    }
+
+    /// Adds a `Runner::Populate` to the end of the runners queue, replacing the provided field of every result of the read
+    /// with the record it references, as declared with `TableOptions::reference`, e.g. `.populate("assignee_id")`.
+    ///
+    /// A field holding an array of references is replaced with the array of the referenced records. References to records
+    /// that do not exist, or that the row-level security policy of the referenced table hides, are replaced with `null`
+    /// (dropped from arrays). Running the query returns an `OhMyDbError::InvalidField` error if no relation is declared for the field.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) referencing field.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn populate(&mut self, field: &str) -> &mut Self {
+        self.runners.push_back(Runner::Populate(field.to_string()));
+
+        self
+    }
+
+    /// Adds a `Runner::Operator(UpdateOp::Set(..))` to the end of the runners queue, setting the field of the records
+    /// selected by `JsonDB::update_where` to `value`, e.g. `.set("is_completed", true)`.
+    ///
+    /// A missing field is created, along with the objects it is nested in. The other fields are left unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) field to set.
+    /// * `value` - The new value of the field.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn set<V>(&mut self, field: &str, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners.push_back(Runner::Operator(UpdateOp::Set(
+            field.to_string(),
+            value.into(),
+        )));
+
+        self
+    }
+
+    /// Adds a `Runner::Operator(UpdateOp::Push(..))` to the end of the runners queue, appending `value`
+    /// to the array field of the records selected by `JsonDB::update_where`, e.g. `.push("array", "milk")`.
+    ///
+    /// A missing field is created as an array holding `value`. Use `add_to_set` to skip values the array already contains.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) array field to append to.
+    /// * `value` - The value to append.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn push<V>(&mut self, field: &str, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners.push_back(Runner::Operator(UpdateOp::Push(
+            field.to_string(),
+            value.into(),
+        )));
+
+        self
+    }
+
+    /// Adds a `Runner::Operator(UpdateOp::AddToSet(..))` to the end of the runners queue, appending `value`
+    /// to the array field of the records selected by `JsonDB::update_where` unless the array already contains it.
+    ///
+    /// A missing field is created as an array holding `value`. Numbers compare numerically, so `1` and `1.0` are the same element.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) array field to add to.
+    /// * `value` - The value to add.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn add_to_set<V>(&mut self, field: &str, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners.push_back(Runner::Operator(UpdateOp::AddToSet(
+            field.to_string(),
+            value.into(),
+        )));
+
+        self
+    }
+
+    /// Adds a `Runner::Operator(UpdateOp::AddToSet(..))` like `add_to_set`, appending `value` to the array field
+    /// of the selected records unless the array already contains it.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) array field to add to.
+    /// * `value` - The value to add.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn add_unique<V>(&mut self, field: &str, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.add_to_set(field, value)
+    }
+
+    /// Adds a `Runner::Operator(UpdateOp::PullAll(..))` to the end of the runners queue, removing every
+    /// occurrence of the provided values from the array field of the records selected by `JsonDB::update_where`.
+    ///
+    /// Records missing the field are left unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) array field to remove from.
+    /// * `values` - The values to remove.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn pull_all<V>(&mut self, field: &str, values: Vec<V>) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners.push_back(Runner::Operator(UpdateOp::PullAll(
+            field.to_string(),
+            values.into_iter().map(Into::into).collect(),
+        )));
+
+        self
+    }
+
+    /// Adds a `Runner::Operator(UpdateOp::PullAll(..))` to the end of the runners queue, removing every
+    /// occurrence of `value` from the array field of the selected records, e.g. `.pull("array", "milk")`.
+    ///
+    /// Records missing the field are left unchanged. Use `pull_all` to remove several values at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) array field to remove from.
+    /// * `value` - The value to remove.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn pull<V>(&mut self, field: &str, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.pull_all(field, vec![value])
+    }
+
+    /// Adds a `Runner::Operator(UpdateOp::Increment(..))` to the end of the runners queue, adding `by` to the numeric
+    /// field of the records selected by `JsonDB::update_where` or `JsonDB::update_field`, e.g. `.increment("views", 1)`.
+    ///
+    /// A missing field is created holding `by`. Integers are added exactly, failing rather than overflowing,
+    /// and a float on either side makes the result a float.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) numeric field to increment.
+    /// * `by` - The number to add.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn increment<V>(&mut self, field: &str, by: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners.push_back(Runner::Operator(UpdateOp::Increment(
+            field.to_string(),
+            by.into(),
+        )));
+
+        self
+    }
+
+    /// Adds a `Runner::Operator(UpdateOp::Decrement(..))` to the end of the runners queue, subtracting `by` from
+    /// the numeric field of the records selected by `JsonDB::update_where` or `JsonDB::update_field`.
+    ///
+    /// A missing field counts as `0`, so it is created holding `-by`. Numbers are handled as in `increment`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) numeric field to decrement.
+    /// * `by` - The number to subtract.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn decrement<V>(&mut self, field: &str, by: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners.push_back(Runner::Operator(UpdateOp::Decrement(
+            field.to_string(),
+            by.into(),
+        )));
+
+        self
+    }
+
+    /// Adds a `Runner::Param` to the end of the runners queue, comparing the field with the value bound to the
+    /// parameter `name` when the query runs, e.g. `.where_("status").param("=", "status")`.
+    ///
+    /// Queries parsed with `JsonDB::query` add the same runner for `:name` placeholders.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The comparison operator: `=`, `!=`, `<`, `<=`, `>`, `>=`, `LIKE` or `IN` (which expects an array).
+    /// * `name` - The name of the parameter, without the leading `:`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn param(&mut self, op: &str, name: &str) -> &mut Self {
+        self.runners.push_back(Runner::Param {
+            op: op.to_string(),
+            name: name.to_string(),
+        });
+
+        self
+    }
+
+    /// Binds a value to the parameter `name`, replacing any value bound to it before.
+    ///
+    /// Running a query whose parameters are not all bound, or bound to values that do not suit their
+    /// comparison (e.g. a string for `<`), returns an `OhMyDbError::InvalidParameter` error.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the parameter, without the leading `:`.
+    /// * `value` - The value to compare with.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn bind<V>(&mut self, name: &str, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.params.insert(name.to_string(), value.into());
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::Equals(value.into()))` to the end of the runners queue, filtering the data based on the provided value.
+    ///
+    /// Values of any JSON type can be compared, e.g. `equals("done")`, `equals(42)` or `equals(true)`.
+    /// Numbers compare numerically, so `42` equals `42.0`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn equals<V>(&mut self, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners
+            .push_back(Runner::Compare(Comparator::Equals(value.into())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::NotEquals(value.into()))` to the end of the runners queue, filtering the data based on the provided value.
+    ///
+    /// Values of any JSON type can be compared, e.g. `not_equals("done")`, `not_equals(42)` or `not_equals(true)`.
+    /// Numbers compare numerically, so `42` equals `42.0`.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn not_equals<V>(&mut self, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners
+            .push_back(Runner::Compare(Comparator::NotEquals(value.into())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::In(values))` to the end of the runners queue, filtering the data based on the provided values,
+    /// e.g. `.in_(["open", "blocked"])` or `.in_(vec![1, 2, 3])`.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The values to filter the data by: strings, numbers, booleans or any other JSON values.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn in_<I, V>(&mut self, values: I) -> &mut Self
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<Value>,
+    {
+        self.runners.push_back(Runner::Compare(Comparator::In(
+            values.into_iter().map(Into::into).collect(),
+        )));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::NotIn(values))` to the end of the runners queue,
+    /// keeping records whose field is none of the provided values (records missing the field included).
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The values to exclude: strings, numbers, booleans or any other JSON values.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn not_in<I, V>(&mut self, values: I) -> &mut Self
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<Value>,
+    {
+        self.runners.push_back(Runner::Compare(Comparator::NotIn(
+            values.into_iter().map(Into::into).collect(),
+        )));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::LessThan(value))` to the end of the runners queue, filtering the data based on the provided value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn less_than<N>(&mut self, value: N) -> &mut Self
+    where
+        N: Into<Number>,
+    {
+        self.runners
+            .push_back(Runner::Compare(Comparator::LessThan(value.into())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::GreaterThan(value))` to the end of the runners queue, filtering the data based on the provided value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn greater_than<N>(&mut self, value: N) -> &mut Self
+    where
+        N: Into<Number>,
+    {
+        self.runners
+            .push_back(Runner::Compare(Comparator::GreaterThan(value.into())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::GreaterThanOrEqual(value))` to the end of the runners queue,
+    /// keeping records whose field is greater than or equal to the provided value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn gte<N>(&mut self, value: N) -> &mut Self
+    where
+        N: Into<Number>,
+    {
+        self.runners
+            .push_back(Runner::Compare(Comparator::GreaterThanOrEqual(
+                value.into(),
+            )));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::LessThanOrEqual(value))` to the end of the runners queue,
+    /// keeping records whose field is less than or equal to the provided value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn lte<N>(&mut self, value: N) -> &mut Self
+    where
+        N: Into<Number>,
+    {
+        self.runners
+            .push_back(Runner::Compare(Comparator::LessThanOrEqual(value.into())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::Between((start, end)))` to the end of the runners queue, filtering the data based on the provided start and end values.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The start value to filter the data by.
+    /// * `end` - The end value to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn between<N>(&mut self, start: N, end: N) -> &mut Self
+    where
+        N: Into<Number>,
+    {
+        self.runners.push_back(Runner::Compare(Comparator::Between((
+            start.into(),
+            end.into(),
+        ))));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::LessThan(..))` with a floating-point bound to the end of the runners queue,
+    /// keeping records whose numeric field is less than the provided value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by. Running the query fails if it is not finite.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn less_than_f64(&mut self, value: f64) -> &mut Self {
+        self.push_float_comparator(&[value], |bounds| Comparator::LessThan(bounds[0].clone()))
+    }
+
+    /// Adds a `Runner::Compare(Comparator::GreaterThan(..))` with a floating-point bound to the end of the runners queue,
+    /// keeping records whose numeric field is greater than the provided value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by. Running the query fails if it is not finite.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn greater_than_f64(&mut self, value: f64) -> &mut Self {
+        self.push_float_comparator(&[value], |bounds| {
+            Comparator::GreaterThan(bounds[0].clone())
+        })
+    }
+
+    /// Adds a `Runner::Compare(Comparator::GreaterThanOrEqual(..))` with a floating-point bound to the end of the runners queue,
+    /// keeping records whose numeric field is greater than or equal to the provided value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by. Running the query fails if it is not finite.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn gte_f64(&mut self, value: f64) -> &mut Self {
+        self.push_float_comparator(&[value], |bounds| {
+            Comparator::GreaterThanOrEqual(bounds[0].clone())
+        })
+    }
+
+    /// Adds a `Runner::Compare(Comparator::LessThanOrEqual(..))` with a floating-point bound to the end of the runners queue,
+    /// keeping records whose numeric field is less than or equal to the provided value.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to filter the data by. Running the query fails if it is not finite.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn lte_f64(&mut self, value: f64) -> &mut Self {
+        self.push_float_comparator(&[value], |bounds| {
+            Comparator::LessThanOrEqual(bounds[0].clone())
+        })
+    }
+
+    /// Adds a `Runner::Compare(Comparator::Between(..))` with floating-point bounds to the end of the runners queue,
+    /// keeping records whose numeric field lies between the provided values (inclusive).
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The start value to filter the data by.
+    /// * `end` - The end value to filter the data by.
+    ///
+    /// Running the query fails if either value is not finite.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn between_f64(&mut self, start: f64, end: f64) -> &mut Self {
+        self.push_float_comparator(&[start, end], |bounds| {
+            Comparator::Between((bounds[0].clone(), bounds[1].clone()))
+        })
+    }
+
+    /// Adds a `Runner::Compare(Comparator::BetweenStr((start, end)))` to the end of the runners queue,
+    /// keeping records whose string field lies between the provided values (inclusive), compared lexicographically.
+    ///
+    /// This suits alphabetical ranges and sortable date strings, e.g. `between_str("2025-01-01", "2025-02-01")`.
+    ///
+    /// # Arguments
+    ///
+    /// * `start` - The start value to filter the data by.
+    /// * `end` - The end value to filter the data by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn between_str(&mut self, start: &str, end: &str) -> &mut Self {
+        self.runners
+            .push_back(Runner::Compare(Comparator::BetweenStr((
+                start.to_string(),
+                end.to_string(),
+            ))));
+
+        self
+    }
+
+    /// Converts floating-point bounds into JSON numbers and pushes the comparator built from them,
+    /// or a `Runner::Invalid` if any bound is not finite.
+    fn push_float_comparator<F>(&mut self, values: &[f64], comparator: F) -> &mut Self
+    where
+        F: FnOnce(&[Number]) -> Comparator,
+    {
+        let bounds: Option<Vec<Number>> = values.iter().map(|v| Number::from_f64(*v)).collect();
+        let runner = match bounds {
+            Some(bounds) => Runner::Compare(comparator(&bounds)),
+            None => Runner::Invalid(format!("{:?} is not a finite number", values)),
+        };
+        self.runners.push_back(runner);
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::Contains(value.into()))` to the end of the runners queue,
+    /// keeping records whose array field contains the provided value, or whose string field contains it as a substring.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The array element or substring to look for.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn contains<V>(&mut self, value: V) -> &mut Self
+    where
+        V: Into<Value>,
+    {
+        self.runners
+            .push_back(Runner::Compare(Comparator::Contains(value.into())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::Like(pattern.to_string()))` to the end of the runners queue,
+    /// keeping records whose string field matches the provided SQL-style pattern.
+    ///
+    /// In the pattern, `%` matches any sequence of characters and `_` matches exactly one character,
+    /// e.g. `like("%groceries%")`. Matching is case-sensitive.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The pattern to match the field against.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn like(&mut self, pattern: &str) -> &mut Self {
+        self.runners
+            .push_back(Runner::Compare(Comparator::Like(pattern.to_string())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::SoundsLike(..))` to the end of the runners queue,
+    /// keeping records whose string field sounds like the provided name, e.g. `sounds_like("Jon")` matches "John".
+    ///
+    /// Names are compared by their American Soundex code, which is computed once when the query is built.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to compare the field with.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn sounds_like(&mut self, name: &str) -> &mut Self {
+        self.runners
+            .push_back(Runner::Compare(Comparator::SoundsLike(soundex(name))));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::StartsWith(prefix.to_string()))` to the end of the runners queue,
+    /// keeping records whose string field starts with the provided prefix. Matching is case-sensitive.
+    ///
+    /// # Arguments
+    ///
+    /// * `prefix` - The prefix the field must start with.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn starts_with(&mut self, prefix: &str) -> &mut Self {
+        self.runners
+            .push_back(Runner::Compare(Comparator::StartsWith(prefix.to_string())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::EndsWith(suffix.to_string()))` to the end of the runners queue,
+    /// keeping records whose string field ends with the provided suffix. Matching is case-sensitive.
+    ///
+    /// # Arguments
+    ///
+    /// * `suffix` - The suffix the field must end with.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn ends_with(&mut self, suffix: &str) -> &mut Self {
+        self.runners
+            .push_back(Runner::Compare(Comparator::EndsWith(suffix.to_string())));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::IsNull)` to the end of the runners queue,
+    /// keeping records where the field is missing or `null`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn is_null(&mut self) -> &mut Self {
+        self.runners.push_back(Runner::Compare(Comparator::IsNull));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::IsSet)` to the end of the runners queue,
+    /// keeping records where the field is present and not `null`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn is_set(&mut self) -> &mut Self {
+        self.runners.push_back(Runner::Compare(Comparator::IsSet));
+
+        self
+    }
+
+    /// Adds a `Runner::Compare(Comparator::Matches(..))` to the end of the runners queue,
+    /// keeping records whose string field matches the provided regular expression.
+    ///
+    /// The expression is compiled once here rather than per record. If it does not compile,
+    /// running the query returns `OhMyDbError::InvalidPattern`. Requires the `regex` feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - The regular expression to match the field against, e.g. `r"^Ja.*oe$"`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    #[cfg(feature = "regex")]
+    pub fn matches(&mut self, pattern: &str) -> &mut Self {
+        let runner = match regex::Regex::new(pattern) {
+            Ok(regex) => Runner::Compare(Comparator::Matches(Pattern(regex))),
+            Err(e) => Runner::InvalidPattern {
+                pattern: pattern.to_string(),
+                reason: e.to_string(),
+            },
+        };
+        self.runners.push_back(runner);
+
+        self
+    }
+
+    /// Adds a `Runner::FilterWith(..)` to the end of the runners queue, keeping records for which the
+    /// provided predicate returns `true`.
+    ///
+    /// The predicate receives the whole record (the user data in envelope mode) and is combined with the
+    /// other conditions like any comparator, so `not`, `or_where`, sorting and limits apply as usual.
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - The custom condition records must satisfy.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn filter_with<F>(&mut self, predicate: F) -> &mut Self
+    where
+        F: Fn(&Value) -> bool + Send + Sync + 'static,
+    {
+        self.runners
+            .push_back(Runner::FilterWith(Predicate(Arc::new(predicate))));
+
+        self
+    }
+
+    /// Adds the conditions of a Mongo-style filter document to the end of the runners queue,
+    /// e.g. `match_json(json!({"is_completed": false, "wife.name": {"$in": ["Jane"]}}))`.
+    ///
+    /// Each key is a (possibly nested, dot-separated) field that must equal the provided value, or satisfy
+    /// an object of operators: `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$in`, `$nin`, `$exists`,
+    /// `$regex` (with the `regex` feature) and `$not`. Top-level `$and` and `$or` keys combine arrays of
+    /// documents. The conditions are combined with the previous ones with AND semantics, like a `where_`.
+    ///
+    /// This makes it easy to accept filters from request bodies. If the document is invalid, running the
+    /// query returns `OhMyDbError::InvalidPattern`.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The filter document.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn match_json(&mut self, filter: Value) -> &mut Self {
+        let runner = match filter_doc::compile(&filter) {
+            Ok(dnf) => Runner::Filters(dnf),
+            Err(reason) => Runner::InvalidPattern {
+                pattern: filter.to_string(),
+                reason,
+            },
+        };
+        self.runners.push_back(runner);
+
+        self
+    }
+
+    /// Adds a `Runner::OrderBy(field.to_string(), order)` to the end of the runners queue, sorting the results by the provided field.
+    /// Multiple `order_by` calls are applied in sequence, the first one being the primary sort key.
+    ///
+    /// Numbers are compared numerically and strings lexicographically; records missing the field sort first.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) field to sort the data by.
+    /// * `order` - The sort direction, `Order::Asc` or `Order::Desc`.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn order_by(&mut self, field: &str, order: Order) -> &mut Self {
+        self.runners
+            .push_back(Runner::OrderBy(field.to_string(), order));
+
+        self
+    }
+
+    /// Adds a `Runner::Skip(n)` to the end of the runners queue, skipping the first `n` results.
+    /// Skipping is applied after filtering and sorting, regardless of where it appears in the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The number of results to skip.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn skip(&mut self, n: usize) -> &mut Self {
+        self.runners.push_back(Runner::Skip(n));
+
+        self
+    }
+
+    /// Adds a `Runner::Limit(n)` to the end of the runners queue, keeping at most `n` results.
+    /// The limit is applied after filtering, sorting and skipping, regardless of where it appears in the chain.
+    ///
+    /// # Arguments
+    ///
+    /// * `n` - The maximum number of results to return.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn limit(&mut self, n: usize) -> &mut Self {
+        self.runners.push_back(Runner::Limit(n));
+
+        self
+    }
+
+    /// Adds a `Runner::Select(fields)` to the end of the runners queue, projecting every result down to the provided fields.
+    ///
+    /// Fields may be nested, dot-separated key chains (e.g. `"wife.name"`), in which case the nesting is kept
+    /// in the projected record. Fields missing from a record are omitted.
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - The fields to keep in the results.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn select(&mut self, fields: &[&str]) -> &mut Self {
+        let fields = fields.iter().map(|field| field.to_string()).collect();
+        self.runners.push_back(Runner::Select(fields));
+
+        self
+    }
+
+    /// Adds a `Runner::Distinct(field.to_string())` to the end of the runners queue,
+    /// keeping only one representative record (the first one, after sorting) per value of the provided field.
+    /// Records missing the field are grouped together with records holding `null`.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The (possibly nested, dot-separated) field to deduplicate the results by.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn distinct(&mut self, field: &str) -> &mut Self {
+        self.runners.push_back(Runner::Distinct(field.to_string()));
+
+        self
+    }
+
+    /// Adds a `Runner::WithMeta` to the end of the runners queue, returning the records of tables in envelope
+    /// mode whole: their user data under `data`, next to the library-managed `created_at`, `updated_at`,
+    /// `revision` and `deleted_at` metadata.
+    ///
+    /// By default only the user data is returned. Records of other tables are not affected.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn with_meta(&mut self) -> &mut Self {
+        self.runners.push_back(Runner::WithMeta);
+
+        self
+    }
+
+    /// Adds a `Runner::WithDeleted` to the end of the runners queue, including the soft-deleted records of tables
+    /// with soft deletes (see `TableOptions::soft_delete`) in the records the query selects.
+    ///
+    /// Records of other tables are not affected.
+    ///
+    /// # Returns
+    ///
+    /// A mutable reference to the `Query`, allowing for method chaining.
+    pub fn with_deleted(&mut self) -> &mut Self {
+        self.runners.push_back(Runner::WithDeleted);
+
+        self
+    }
+
+    /// Runs the database operations specified in the runners queue against `db`.
+    ///
+    /// This method processes the runners queue, performing various database operations such as creating, reading, updating, and deleting records.
+    /// The method returns the resulting list of `T` items after applying the specified operations.
+    /// The query is left untouched, so it can be run again.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError` if the targeted table or record cannot be found, if an inserted record already exists,
+    /// or if there is an error saving the database state after the operations are completed.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec` of `T` items representing the final state of the database after the operations have been performed.
+    pub async fn run(&self, db: &mut JsonDB) -> Result<Vec<Value>, OhMyDbError> {
+        db.run_query(self).await
+    }
+
+    /// Runs the database operations specified in the runners queue like `run`, returning what they did:
+    /// the resulting records, the previous version of the updated or deleted records, and the number of records affected.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the same errors as `run`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `RunOutcome` of the operations.
+    pub async fn run_outcome(&self, db: &mut JsonDB) -> Result<RunOutcome, OhMyDbError> {
+        db.run_query_outcome(self).await
+    }
+
+    /// Runs the database operations specified in the runners queue and deserializes the resulting records into `T`.
+    ///
+    /// This is the typed counterpart of `run`, so callers don't have to convert every returned `Value` by hand.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the same errors as `run`, plus an `OhMyDbError::Serde` error if any resulting record
+    /// cannot be deserialized into `T`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec` of `T` items representing the result of the operations.
+    pub async fn run_as<T>(&self, db: &mut JsonDB) -> Result<Vec<T>, OhMyDbError>
+    where
+        T: DeserializeOwned,
+    {
+        self.run(db)
+            .await?
+            .into_iter()
+            .map(|value| serde_json::from_value(value).map_err(OhMyDbError::from))
+            .collect()
+    }
+
+    /// Runs the database operations specified in the runners queue like `run`, wrapping the resulting records
+    /// into `Record`s for their convenience accessors, e.g. `record.str_at("wife.name")`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec` of `Record` items representing the result of the operations.
+    pub async fn run_records(&self, db: &mut JsonDB) -> Result<Vec<Record>, OhMyDbError> {
+        let (envelope, key) = db.record_shape(self);
+        let records = self.run(db).await?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| Record::with_shape(record, envelope, &key))
+            .collect())
+    }
+
+    /// Counts the records matching the operations specified in the runners queue.
+    ///
+    /// Only the filters, `skip` and `limit` of the queue are evaluated: no record is cloned,
+    /// nothing is written and the database file is not saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the number of matching records. Querying a missing table counts zero records.
+    pub async fn count(&self, db: &mut JsonDB) -> Result<usize, OhMyDbError> {
+        db.count_query(self).await
+    }
+
+    /// Returns the first record matching the operations specified in the runners queue.
+    ///
+    /// Like `count`, only the filters, sorting and `skip` of the queue are evaluated and nothing is written.
+    /// Without `order_by`, scanning stops as soon as a matching record is found.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the first matching record, or `None` if no record matches.
+    pub async fn first(&self, db: &mut JsonDB) -> Result<Option<Value>, OhMyDbError> {
+        db.first_query(self).await
+    }
+
+    /// Returns the first record matching the operations specified in the runners queue, deserialized into `T`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::Serde` error if the record cannot be deserialized into `T`.
+    pub async fn first_as<T>(&self, db: &mut JsonDB) -> Result<Option<T>, OhMyDbError>
+    where
+        T: DeserializeOwned,
+    {
+        match self.first(db).await? {
+            Some(value) => Ok(Some(serde_json::from_value(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the sum of the provided numeric field across the records matching the runners queue,
+    /// e.g. `db.find("orders").where_("status").equals("paid").sum(&mut db, "total")`.
+    ///
+    /// Like `count`, nothing is written. The field is read like `run` returns it, i.e. after the codecs of the table
+    /// (see `TableOptions::codec`), records being only cloned if a codec decodes them.
+    /// Records where the field is missing or not a number are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    /// * `field` - The (possibly nested, dot-separated) field to aggregate.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the sum, `0.0` if no record has a numeric value for the field.
+    pub async fn sum(&self, db: &mut JsonDB, field: &str) -> Result<f64, OhMyDbError> {
+        let numbers = db.numbers_query(self, field).await?;
+
+        Ok(numbers
+            .iter()
+            .filter_map(Number::as_f64)
+            .fold(0.0, |sum, n| sum + n))
+    }
+
+    /// Returns the mean of the provided numeric field across the records matching the runners queue.
+    ///
+    /// Records where the field is missing or not a number are skipped, as with `sum`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    /// * `field` - The (possibly nested, dot-separated) field to aggregate.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the mean, or `None` if no record has a numeric value for the field.
+    pub async fn avg(&self, db: &mut JsonDB, field: &str) -> Result<Option<f64>, OhMyDbError> {
+        let numbers = db.numbers_query(self, field).await?;
+
+        Ok(match numbers.is_empty() {
+            true => None,
+            false => {
+                let sum: f64 = numbers.iter().filter_map(Number::as_f64).sum();
+                Some(sum / numbers.len() as f64)
+            }
+        })
+    }
+
+    /// Returns the smallest value of the provided numeric field across the records matching the runners queue.
+    ///
+    /// Records where the field is missing or not a number are skipped, as with `sum`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    /// * `field` - The (possibly nested, dot-separated) field to aggregate.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the smallest value, or `None` if no record has a numeric value for the field.
+    pub async fn min(&self, db: &mut JsonDB, field: &str) -> Result<Option<Number>, OhMyDbError> {
+        let numbers = db.numbers_query(self, field).await?;
+
+        Ok(numbers.into_iter().min_by(compare_numbers))
+    }
+
+    /// Returns the largest value of the provided numeric field across the records matching the runners queue.
+    ///
+    /// Records where the field is missing or not a number are skipped, as with `sum`.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    /// * `field` - The (possibly nested, dot-separated) field to aggregate.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the largest value, or `None` if no record has a numeric value for the field.
+    pub async fn max(&self, db: &mut JsonDB, field: &str) -> Result<Option<Number>, OhMyDbError> {
+        let numbers = db.numbers_query(self, field).await?;
+
+        Ok(numbers.into_iter().max_by(compare_numbers))
+    }
+
+    /// Describes what `run` would do with the runners queue, without running it, e.g. to understand slow queries.
+    ///
+    /// The description lists the table touched, the filter conditions in evaluation order, the sorting, paging
+    /// and projection, and how many records would be scanned and match, estimated from cached field statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database the query would run against.
+    ///
+    /// # Errors
+    ///
+    /// This method returns the error `run` would fail with while building the query, e.g. `OhMyDbError::InvalidParameter`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `Explain` description of the query.
+    pub fn explain(&self, db: &JsonDB) -> Result<Explain, OhMyDbError> {
+        db.explain_query(self)
+    }
+
+    /// Returns the unique values of the provided field across the records matching the runners queue,
+    /// e.g. to build filter dropdowns from table data.
+    ///
+    /// Like `count`, nothing is written. The field is read like `run` returns it, i.e. after the codecs of the table.
+    /// Records missing the field are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `db` - The database to run the query against.
+    /// * `field` - The (possibly nested, dot-separated) field to collect the values of.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the unique values, in the order they were first encountered.
+    pub async fn distinct_values(
+        &self,
+        db: &mut JsonDB,
+        field: &str,
+    ) -> Result<Vec<Value>, OhMyDbError> {
+        db.distinct_values_query(self, field).await
+    }
+}
@@ -0,0 +1,198 @@
+use crate::envelope::unix_millis;
+use crate::error::OhMyDbError;
+use crate::json_db::JsonDB;
+use crate::utils::record_id;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A job claimed from a `JobQueue`, leased to the worker that claimed it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    /// The id of the job, used to complete or retry it.
+    pub id: String,
+    /// The job itself, as serialized when it was pushed.
+    pub job: Value,
+    /// How many times the job was claimed, including this time.
+    pub attempts: u64,
+    /// When the lease ends and the job can be claimed again unless it is completed or retried.
+    pub leased_until: SystemTime,
+}
+
+/// A durable work queue persisted in the database file, returned by `JsonDB::queue`.
+///
+/// Workers `claim` a job for a lease duration, then either `complete` it or `retry_with_backoff` it.
+/// A job whose lease ends before either happens (e.g. because its worker crashed) can be claimed again,
+/// so every job runs at least once. Each queue is stored in its own reserved table, and every change is saved.
+pub struct JobQueue<'a> {
+    db: &'a mut JsonDB,
+    table: String,
+}
+
+impl<'a> JobQueue<'a> {
+    pub(crate) fn new(db: &'a mut JsonDB, name: &str) -> Self {
+        Self {
+            db,
+            table: format!("_queue_{}", name),
+        }
+    }
+
+    /// Pushes a job, available to workers right away.
+    ///
+    /// # Arguments
+    ///
+    /// * `job` - The `T` job to run.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::Serde` error if the job cannot be serialized,
+    /// or the error saving the database file failed with.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the id of the pushed job.
+    pub async fn push<T>(&mut self, job: &T) -> Result<String, OhMyDbError>
+    where
+        T: Serialize,
+    {
+        let id = self.db.next_internal_id();
+        let record = json!({
+            "id": id,
+            "job": serde_json::to_value(job)?,
+            "attempts": 0,
+            "available_at": unix_millis(self.db.now()),
+            "leased_until": null,
+        });
+        self.db.reserved_table_mut(&self.table).insert(record);
+        self.db.save().await?;
+
+        Ok(id)
+    }
+
+    /// Claims the oldest available job for `lease`: it is hidden from other claims until the lease ends.
+    ///
+    /// Jobs are available once their backoff delay has passed and they are not leased, or their lease ended.
+    ///
+    /// # Arguments
+    ///
+    /// * `lease` - How long the worker has to complete or retry the job.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the claimed job, or `None` if no job is available.
+    pub async fn claim(&mut self, lease: Duration) -> Result<Option<Job>, OhMyDbError> {
+        let now = self.db.now();
+        let Some(record) = self
+            .records()
+            .filter(|record| number(record, "available_at") <= unix_millis(now))
+            .filter(|record| number(record, "leased_until") <= unix_millis(now))
+            .min_by_key(|record| {
+                let id = record_id(record, "id").and_then(|id| id.parse::<u64>().ok());
+                (number(record, "available_at"), id)
+            })
+            .cloned()
+        else {
+            return Ok(None);
+        };
+
+        let mut claimed = record.clone();
+        let leased_until = now + lease;
+        claimed["attempts"] = json!(number(&record, "attempts") + 1);
+        claimed["leased_until"] = json!(unix_millis(leased_until));
+        self.replace(&record, claimed.clone());
+        self.db.save().await?;
+
+        Ok(Some(Job {
+            id: record_id(&claimed, "id").unwrap_or_default(),
+            job: claimed["job"].clone(),
+            attempts: number(&claimed, "attempts"),
+            leased_until: UNIX_EPOCH + Duration::from_millis(unix_millis(leased_until)),
+        }))
+    }
+
+    /// Completes a job, removing it from the queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the job.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether the job was in the queue.
+    pub async fn complete(&mut self, id: &str) -> Result<bool, OhMyDbError> {
+        let Some(record) = self.find(id) else {
+            return Ok(false);
+        };
+        self.db.reserved_table_mut(&self.table).remove(&record);
+        self.db.save().await?;
+
+        Ok(true)
+    }
+
+    /// Releases a job whose attempt failed, making it available again after an exponential backoff:
+    /// `base` after the first attempt, twice as long after the second one, and so on.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The id of the job.
+    /// * `base` - The delay before the job can be claimed again after its first attempt.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing whether the job was in the queue.
+    pub async fn retry_with_backoff(
+        &mut self,
+        id: &str,
+        base: Duration,
+    ) -> Result<bool, OhMyDbError> {
+        let Some(record) = self.find(id) else {
+            return Ok(false);
+        };
+
+        let attempts = number(&record, "attempts").max(1);
+        let factor = 2u32.saturating_pow((attempts - 1).min(31) as u32);
+        let mut retried = record.clone();
+        retried["available_at"] = json!(unix_millis(self.db.now() + base.saturating_mul(factor)));
+        retried["leased_until"] = Value::Null;
+        self.replace(&record, retried);
+        self.db.save().await?;
+
+        Ok(true)
+    }
+
+    /// Returns the number of jobs in the queue, whether they are available, leased or waiting for a retry.
+    pub fn len(&self) -> usize {
+        self.records().count()
+    }
+
+    /// Returns whether the queue has no jobs.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the jobs of the queue, skipping stored values that are not objects, so claiming one can't panic.
+    fn records(&self) -> impl Iterator<Item = &Value> {
+        self.db
+            .reserved_table(&self.table)
+            .into_iter()
+            .flatten()
+            .filter(|record| record.is_object())
+    }
+
+    fn find(&self, id: &str) -> Option<Value> {
+        self.records()
+            .find(|record| record_id(record, "id").as_deref() == Some(id))
+            .cloned()
+    }
+
+    fn replace(&mut self, old: &Value, new: Value) {
+        let table = self.db.reserved_table_mut(&self.table);
+        table.remove(old);
+        table.insert(new);
+    }
+}
+
+/// Returns a non-negative integer field of a job record, `0` if it is missing or `null`.
+fn number(record: &Value, field: &str) -> u64 {
+    record[field].as_u64().unwrap_or(0)
+}
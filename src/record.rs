@@ -0,0 +1,177 @@
+use crate::envelope::DATA;
+use crate::error::OhMyDbError;
+use crate::utils::{get_nested_ref, record_id};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::ops::{Deref, DerefMut};
+
+/// A record returned by a query, with accessors sparing the `get().and_then(...)` chains of a raw `Value`.
+///
+/// `Record` derefs to the wrapped `Value`, so code written against `Value` keeps working, and (de)serializes
+/// exactly like it. The `*_at` accessors take dot-separated key chains (e.g. `"wife.name"`) and read the user data,
+/// i.e. the `data` of records returned whole with `with_meta`.
+///
+/// Records returned by `Query::run_records` know whether they are such envelopes and the primary key of their table
+/// (see `JsonDB::set_primary_key`); records built from a `Value` are plain records keyed by `id`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Record {
+    value: Value,
+    #[serde(skip)]
+    envelope: bool,
+    #[serde(skip)]
+    key: Option<String>,
+}
+
+impl Record {
+    /// Wraps a value, as a plain record keyed by `id`.
+    pub fn new(value: Value) -> Self {
+        Self {
+            value,
+            envelope: false,
+            key: None,
+        }
+    }
+
+    /// Wraps a value returned by a query on a table with the provided primary key, `envelope` telling whether
+    /// it is an envelope returned with `with_meta`.
+    pub(crate) fn with_shape(value: Value, envelope: bool, key: &str) -> Self {
+        Self {
+            value,
+            envelope,
+            key: Some(key.to_string()),
+        }
+    }
+
+    /// Returns the wrapped value.
+    pub fn into_inner(self) -> Value {
+        self.value
+    }
+
+    /// Returns whether the record is an envelope returned with `with_meta`: its user data under `data`,
+    /// next to the `created_at`, `updated_at` and `revision` metadata.
+    pub fn is_envelope(&self) -> bool {
+        self.envelope
+    }
+
+    /// Returns the user data of the record: the `data` of an envelope, or the whole record otherwise.
+    pub fn data(&self) -> &Value {
+        match self.envelope {
+            true => &self.value[DATA],
+            false => &self.value,
+        }
+    }
+
+    /// Returns the value at a (possibly nested, dot-separated) field of the user data, if any.
+    pub fn at(&self, key_chain: &str) -> Option<&Value> {
+        get_nested_ref(self.data(), key_chain)
+    }
+
+    /// Returns the string at a field of the user data, if it is one.
+    pub fn str_at(&self, key_chain: &str) -> Option<&str> {
+        self.at(key_chain)?.as_str()
+    }
+
+    /// Returns the unsigned integer at a field of the user data, if it is one.
+    pub fn u64_at(&self, key_chain: &str) -> Option<u64> {
+        self.at(key_chain)?.as_u64()
+    }
+
+    /// Returns the integer at a field of the user data, if it is one.
+    pub fn i64_at(&self, key_chain: &str) -> Option<i64> {
+        self.at(key_chain)?.as_i64()
+    }
+
+    /// Returns the number at a field of the user data as a float, if it is one.
+    pub fn f64_at(&self, key_chain: &str) -> Option<f64> {
+        self.at(key_chain)?.as_f64()
+    }
+
+    /// Returns the boolean at a field of the user data, if it is one.
+    pub fn bool_at(&self, key_chain: &str) -> Option<bool> {
+        self.at(key_chain)?.as_bool()
+    }
+
+    /// Returns the primary key of the record as a string (numbers included), like the `*_by_id` methods compare it:
+    /// the field set with `JsonDB::set_primary_key` for records returned by a query, `id` otherwise.
+    pub fn id(&self) -> Option<String> {
+        record_id(self.data(), self.key.as_deref().unwrap_or("id"))
+    }
+
+    /// Returns the string or number at a field of the user data as a string, e.g. a primary key other than `id`.
+    pub fn key_at(&self, key_chain: &str) -> Option<String> {
+        record_id(self.data(), key_chain)
+    }
+
+    /// Returns when an envelope was created, in milliseconds since the Unix epoch.
+    pub fn created_at(&self) -> Option<u64> {
+        self.meta("created_at")
+    }
+
+    /// Returns when an envelope was last updated, in milliseconds since the Unix epoch.
+    pub fn updated_at(&self) -> Option<u64> {
+        self.meta("updated_at")
+    }
+
+    /// Returns the revision of an envelope, starting at `1` and bumped by every update.
+    pub fn revision(&self) -> Option<u64> {
+        self.meta("revision")
+    }
+
+    /// Returns when the record was soft-deleted (see `TableOptions::soft_delete`), in milliseconds since the Unix epoch.
+    pub fn deleted_at(&self) -> Option<u64> {
+        self.value.get("deleted_at")?.as_u64()
+    }
+
+    /// Deserializes the record, its user data for an envelope, into `T`.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::Serde` error if the record cannot be deserialized into `T`.
+    pub fn try_into_typed<T>(self) -> Result<T, OhMyDbError>
+    where
+        T: DeserializeOwned,
+    {
+        let value = match (self.envelope, self.value) {
+            (true, Value::Object(mut envelope)) => envelope.remove(DATA).unwrap_or_default(),
+            (_, value) => value,
+        };
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Returns the metadata field of an envelope.
+    fn meta(&self, key: &str) -> Option<u64> {
+        match self.envelope {
+            true => self.value.get(key)?.as_u64(),
+            false => None,
+        }
+    }
+}
+
+impl Deref for Record {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.value
+    }
+}
+
+impl DerefMut for Record {
+    fn deref_mut(&mut self) -> &mut Value {
+        &mut self.value
+    }
+}
+
+impl From<Value> for Record {
+    fn from(value: Value) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<Record> for Value {
+    fn from(record: Record) -> Self {
+        record.value
+    }
+}
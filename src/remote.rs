@@ -0,0 +1,40 @@
+use crate::error::OhMyDbError;
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+
+/// The future returned by `RemoteTier` methods.
+pub type RemoteFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, OhMyDbError>> + Send + 'a>>;
+
+/// An external system backing a table, such as a REST API or another database, registered with
+/// `JsonDB::set_remote_tier` so the table acts as a local cache of it.
+///
+/// Lookups by id that miss the table read through to the remote (`fetch`), and every write to the table
+/// is written through to it (`store` and `remove`) before being saved, so the remote stays the source of truth.
+/// Records are exchanged as reads return them, with table options decoded and envelopes removed.
+pub trait RemoteTier: Send + Sync {
+    /// Returns the record of `table` with the id, or `None` if the remote has none.
+    fn fetch<'a>(&'a self, table: &'a str, id: &'a str) -> RemoteFuture<'a, Option<Value>>;
+
+    /// Stores a record inserted into or updated in `table`, replacing any record with the same id.
+    fn store<'a>(&'a self, table: &'a str, record: &'a Value) -> RemoteFuture<'a, ()>;
+
+    /// Removes the record of `table` with the id.
+    fn remove<'a>(&'a self, table: &'a str, id: &'a str) -> RemoteFuture<'a, ()>;
+}
+
+/// The writes of an operation to the tables backed by a `RemoteTier`, written through before saving.
+#[derive(Default)]
+pub(crate) struct RemoteWrites {
+    /// The records inserted or updated, per table, as reads return them.
+    pub stored: Vec<(String, Value)>,
+    /// The ids of the records removed, per table.
+    pub removed: Vec<(String, String)>,
+}
+
+impl RemoteWrites {
+    /// Returns whether no write has to be written through.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.stored.is_empty() && self.removed.is_empty()
+    }
+}
@@ -0,0 +1,122 @@
+use crate::utils::get_nested_mut;
+use serde_json::{Number, Value};
+
+/// How `JsonDB::scrub` replaces the values of a field.
+///
+/// Replacements are derived from a hash of the original value, so equal values get equal replacements
+/// across every table (references keep matching) while the originals can't be read back.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scrub {
+    /// Replaces every letter with a letter of the same case and every digit with a digit, keeping the other
+    /// characters, e.g. `"Jane Doe"` becomes `"Qmwx Bto"` and `"+1 555-0100"` keeps its formatting.
+    /// Integers become integers with at most as many digits.
+    Shape,
+    /// Replaces the local part of an email address like `Shape` and its domain with `example.com`.
+    Email,
+    /// Replaces every character with `*`, keeping the length, e.g. for tokens.
+    Mask,
+    /// Replaces the value with a fixed one.
+    Fixed(Value),
+}
+
+impl Scrub {
+    /// Returns the replacement of a value. Strings and numbers nested in arrays and objects are scrubbed one by one.
+    fn apply(&self, value: &Value) -> Value {
+        match (self, value) {
+            (Scrub::Fixed(fixed), _) => fixed.clone(),
+            (_, Value::Null | Value::Bool(_)) => value.clone(),
+            (_, Value::Array(items)) => Value::Array(items.iter().map(|v| self.apply(v)).collect()),
+            (_, Value::Object(fields)) => Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, v)| (key.clone(), self.apply(v)))
+                    .collect(),
+            ),
+            (Scrub::Mask, Value::String(s)) => Value::String("*".repeat(s.chars().count())),
+            (Scrub::Email, Value::String(s)) => match s.split_once('@') {
+                Some((local, _)) => Value::String(format!("{}@example.com", shape(local))),
+                None => Value::String(shape(s)),
+            },
+            (_, Value::String(s)) => Value::String(shape(s)),
+            (Scrub::Mask, Value::Number(_)) => Value::from(0),
+            (_, Value::Number(n)) => match n.as_i64() {
+                Some(i) => {
+                    let digits = shape(&i.unsigned_abs().to_string());
+                    let scrubbed: i64 = digits.parse().unwrap_or(0);
+                    Value::Number(Number::from(if i < 0 { -scrubbed } else { scrubbed }))
+                }
+                None => Value::from(0),
+            },
+        }
+    }
+}
+
+/// The fields `JsonDB::scrub` replaces, and how.
+///
+/// A rule applies to the (possibly nested, dot-separated) field in every table, or in a single table.
+#[derive(Clone, Debug, Default)]
+pub struct ScrubPolicy {
+    rules: Vec<(Option<String>, String, Scrub)>,
+}
+
+impl ScrubPolicy {
+    /// Creates a new policy replacing nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scrubs `field` in every table, e.g. `.field("email", Scrub::Email)`.
+    pub fn field(mut self, field: &str, scrub: Scrub) -> Self {
+        self.rules.push((None, field.to_string(), scrub));
+        self
+    }
+
+    /// Scrubs `field` in `table` only.
+    pub fn table_field(mut self, table: &str, field: &str, scrub: Scrub) -> Self {
+        self.rules
+            .push((Some(table.to_string()), field.to_string(), scrub));
+        self
+    }
+
+    /// Scrubs the user data of a record of `table`, returning whether it changed.
+    pub(crate) fn apply(&self, table: &str, data: &mut Value) -> bool {
+        let mut changed = false;
+
+        for (_, field, scrub) in self
+            .rules
+            .iter()
+            .filter(|(only, _, _)| only.as_deref().is_none_or(|only| only == table))
+        {
+            if let Some(value) = get_nested_mut(data, field) {
+                let scrubbed = scrub.apply(value);
+                changed |= scrubbed != *value;
+                *value = scrubbed;
+            }
+        }
+
+        changed
+    }
+}
+
+/// Replaces letters and digits of a string with others derived from a hash of the whole string.
+fn shape(s: &str) -> String {
+    // FNV-1a, then an xorshift stream, so the replacement is stable across runs and platforms.
+    let mut state = s.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    }) | 1;
+    let mut next = |n: u64| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % n) as u8
+    };
+
+    s.chars()
+        .map(|c| match c {
+            'a'..='z' => char::from(b'a' + next(26)),
+            'A'..='Z' => char::from(b'A' + next(26)),
+            '0'..='9' => char::from(b'0' + next(10)),
+            c => c,
+        })
+        .collect()
+}
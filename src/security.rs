@@ -0,0 +1,72 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+type PolicyFn = Arc<dyn Fn(&Context, &Value) -> bool + Send + Sync>;
+
+/// Who an operation runs on behalf of, passed to row-level security policies.
+///
+/// Attach a context to an operation with `JsonDB::with_context`. Operations without one run with the
+/// default, anonymous context.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Context {
+    user: Option<String>,
+    attributes: HashMap<String, Value>,
+}
+
+impl Context {
+    /// Creates a new, anonymous context.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the user the operation runs on behalf of.
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Sets an arbitrary attribute (e.g. a tenant or a role) policies can rely on.
+    pub fn attribute<V>(mut self, name: &str, value: V) -> Self
+    where
+        V: Into<Value>,
+    {
+        self.attributes.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Returns the user the operation runs on behalf of, if any.
+    pub fn user_id(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Returns the value of an attribute, if set.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.attributes.get(name)
+    }
+}
+
+/// A row-level security policy deciding whether a context may see and modify a record.
+#[derive(Clone)]
+pub(crate) struct RowPolicy(PolicyFn);
+
+impl RowPolicy {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&Context, &Value) -> bool + Send + Sync + 'static,
+    {
+        Self(Arc::new(f))
+    }
+
+    /// Returns whether `context` may access `record`.
+    pub(crate) fn allows(&self, context: &Context, record: &Value) -> bool {
+        (self.0)(context, record)
+    }
+}
+
+impl Debug for RowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RowPolicy")
+    }
+}
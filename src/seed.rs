@@ -0,0 +1,203 @@
+use crate::error::OhMyDbError;
+use serde_json::Value;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+/// What `JsonDB::seed` does with a fixture record whose id is already stored in its table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnConflict {
+    /// The stored record is kept and the fixture record skipped, so seeding twice changes nothing.
+    #[default]
+    Skip,
+    /// The stored record is replaced with the fixture record.
+    Replace,
+    /// Seeding stops with the `OhMyDbError::RecordExists` error once the file holding the record is inserted,
+    /// keeping what was seeded until then.
+    Fail,
+}
+
+impl OnConflict {
+    /// Parses the name of a conflict handling, as given on the command line: `skip`, `replace` or `fail`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(Self::Skip),
+            "replace" => Some(Self::Replace),
+            "fail" => Some(Self::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of `JsonDB::seed`, printing as a summary.
+#[derive(Debug, Default)]
+pub struct SeedReport {
+    /// The number of fixture records inserted.
+    pub inserted: usize,
+    /// The number of stored records replaced with fixture records (`OnConflict::Replace`).
+    pub replaced: usize,
+    /// The number of fixture records skipped as their id was already stored (`OnConflict::Skip`).
+    pub skipped: usize,
+    /// The fixture records rejected by the table (constraints, checks, ...), with their table,
+    /// their index in the fixture file and the error.
+    pub failures: Vec<(String, usize, OhMyDbError)>,
+}
+
+impl Display for SeedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} inserted, {} replaced, {} skipped, {} failed",
+            self.inserted,
+            self.replaced,
+            self.skipped,
+            self.failures.len()
+        )?;
+        for (table, index, e) in &self.failures {
+            writeln!(f, "  {} #{}: {}", table, index, e)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the fixture files of `dir`, returning the records of each one with the table named after it,
+/// in the alphabetical order of the file names.
+///
+/// `.json` files hold an array of records (or a single one), `.ndjson` and `.jsonl` files one record per line,
+/// and `.csv` files a header row naming the fields followed by one record per row. Other files are ignored.
+pub(crate) async fn read_fixtures(dir: &Path) -> Result<Vec<(String, Vec<Value>)>, OhMyDbError> {
+    let mut paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        paths.push(entry.path());
+    }
+    paths.sort();
+
+    let mut fixtures = Vec::new();
+    for path in paths {
+        let (Some(table), Some(extension)) = (
+            path.file_stem().and_then(|stem| stem.to_str()),
+            path.extension().and_then(|extension| extension.to_str()),
+        ) else {
+            continue;
+        };
+        let parse = match extension {
+            "json" => parse_json,
+            "ndjson" | "jsonl" => parse_ndjson,
+            "csv" => parse_csv,
+            _ => continue,
+        };
+
+        let content = tokio::fs::read_to_string(&path).await?;
+        let records = parse(&content).map_err(|reason| {
+            OhMyDbError::InvalidRecord(format!("{}: {}", path.display(), reason))
+        })?;
+        fixtures.push((table.to_string(), records));
+    }
+
+    Ok(fixtures)
+}
+
+/// Parses a JSON fixture: an array of records, or a single record.
+fn parse_json(content: &str) -> Result<Vec<Value>, String> {
+    match serde_json::from_str(content).map_err(|e| e.to_string())? {
+        Value::Array(records) => Ok(records),
+        record => Ok(vec![record]),
+    }
+}
+
+/// Parses a newline-delimited JSON fixture, skipping blank lines.
+fn parse_ndjson(content: &str) -> Result<Vec<Value>, String> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| serde_json::from_str(line).map_err(|e| format!("line {}: {}", n + 1, e)))
+        .collect()
+}
+
+/// Parses a CSV fixture whose first row names the fields.
+///
+/// Fields may be quoted (`"a, b"`, with `""` for a quote). Unquoted values reading as JSON numbers, booleans
+/// or `null` are stored as such, every other value as a string, and empty values are left out of the record.
+fn parse_csv(content: &str) -> Result<Vec<Value>, String> {
+    let mut rows = csv_rows(content)?.into_iter();
+    let Some(header) = rows.next() else {
+        return Ok(Vec::new());
+    };
+
+    rows.enumerate()
+        .map(|(n, row)| {
+            if row.len() != header.len() {
+                return Err(format!(
+                    "row {} has {} fields, the header {}",
+                    n + 2,
+                    row.len(),
+                    header.len()
+                ));
+            }
+
+            let record = header
+                .iter()
+                .zip(row)
+                .filter(|(_, (value, quoted))| *quoted || !value.is_empty())
+                .map(|((name, _), (value, quoted))| {
+                    let value = match quoted {
+                        true => Value::String(value),
+                        false => match serde_json::from_str(&value) {
+                            Ok(value @ (Value::Number(_) | Value::Bool(_) | Value::Null)) => value,
+                            _ => Value::String(value),
+                        },
+                    };
+                    (name.clone(), value)
+                })
+                .collect();
+
+            Ok(Value::Object(record))
+        })
+        .collect()
+}
+
+/// Splits CSV content into rows of `(value, quoted)` fields, skipping blank lines.
+fn csv_rows(content: &str) -> Result<Vec<Vec<(String, bool)>>, String> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() && !quoted => {
+                quoted = true;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => field.push(c),
+                        None => return Err("unterminated quoted field".to_string()),
+                    }
+                }
+            }
+            ',' => row.push((std::mem::take(&mut field), std::mem::take(&mut quoted))),
+            '\n' => {
+                row.push((std::mem::take(&mut field), std::mem::take(&mut quoted)));
+                if row.len() > 1 || !row[0].0.is_empty() || row[0].1 {
+                    rows.push(std::mem::take(&mut row));
+                }
+                row.clear();
+            }
+            '\r' if chars.peek() == Some(&'\n') => {}
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || quoted || !row.is_empty() {
+        row.push((field, quoted));
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
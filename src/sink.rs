@@ -0,0 +1,107 @@
+use crate::error::OhMyDbError;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+
+/// The future returned by `BackupSink` methods.
+pub type SinkFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, OhMyDbError>> + Send + 'a>>;
+
+/// A destination for database backups, such as a local directory or an object storage bucket.
+///
+/// Backups are opaque named blobs; `JsonDB::backup_to` uploads them and enforces the retention rules
+/// through the listing and removal methods, so a sink only has to implement storage primitives.
+pub trait BackupSink: Send + Sync {
+    /// Stores `bytes` under `name`, replacing any existing backup with the same name.
+    fn put<'a>(&'a self, name: &'a str, bytes: Vec<u8>) -> SinkFuture<'a, ()>;
+
+    /// Returns the names of every backup stored in the sink.
+    fn list(&self) -> SinkFuture<'_, Vec<String>>;
+
+    /// Removes the backup stored under `name`.
+    fn remove<'a>(&'a self, name: &'a str) -> SinkFuture<'a, ()>;
+}
+
+/// A `BackupSink` storing backups as files of a local directory.
+#[derive(Clone, Debug)]
+pub struct DirectorySink {
+    dir: PathBuf,
+}
+
+impl DirectorySink {
+    /// Creates a new sink storing backups in `dir`, which is created on first upload.
+    pub fn new<P>(dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl BackupSink for DirectorySink {
+    fn put<'a>(&'a self, name: &'a str, bytes: Vec<u8>) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            tokio::fs::write(self.dir.join(name), bytes).await?;
+            Ok(())
+        })
+    }
+
+    fn list(&self) -> SinkFuture<'_, Vec<String>> {
+        Box::pin(async move {
+            let mut names = Vec::new();
+            let mut entries = match tokio::fs::read_dir(&self.dir).await {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+                Err(e) => return Err(e.into()),
+            };
+
+            while let Some(entry) = entries.next_entry().await? {
+                if let Some(name) = entry.file_name().to_str() {
+                    names.push(name.to_string());
+                }
+            }
+
+            Ok(names)
+        })
+    }
+
+    fn remove<'a>(&'a self, name: &'a str) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            tokio::fs::remove_file(self.dir.join(name)).await?;
+            Ok(())
+        })
+    }
+}
+
+/// How many backups `JsonDB::backup_to` keeps in a sink.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Retention {
+    keep_last: Option<usize>,
+}
+
+impl Retention {
+    /// Keeps every backup.
+    pub fn keep_all() -> Self {
+        Self::default()
+    }
+
+    /// Keeps only the `n` most recent backups, removing older ones after each upload.
+    pub fn keep_last(n: usize) -> Self {
+        Self { keep_last: Some(n) }
+    }
+
+    /// Returns the backups to remove among `names`, the timestamped names written by `JsonDB::backup_to`.
+    pub(crate) fn expired(&self, mut names: Vec<String>) -> Vec<String> {
+        let Some(keep) = self.keep_last else {
+            return Vec::new();
+        };
+
+        names.sort();
+        let expired = names.len().saturating_sub(keep);
+        names.truncate(expired);
+
+        names
+    }
+}
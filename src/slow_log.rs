@@ -0,0 +1,106 @@
+use serde_json::json;
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+/// A query that took longer than the configured slow-query threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlowQuery {
+    /// The runners pipeline of the query, as built with the fluent API.
+    pub pipeline: String,
+    /// The table the query targeted.
+    pub table: String,
+    /// How long the query took to execute.
+    pub duration: Duration,
+    /// The number of records of the table that were scanned.
+    pub scanned: usize,
+    /// The number of records that matched the query filters.
+    pub matched: usize,
+    /// When the query finished.
+    pub at: SystemTime,
+}
+
+/// Configuration and storage of the slow-query log.
+///
+/// Queries slower than `threshold` are kept in an in-memory ring buffer of `capacity` entries
+/// and, when a file is configured, appended to it as JSON lines.
+#[derive(Clone, Debug)]
+pub struct SlowQueryLog {
+    threshold: Duration,
+    capacity: usize,
+    file: Option<PathBuf>,
+    entries: VecDeque<SlowQuery>,
+}
+
+impl SlowQueryLog {
+    /// Creates a new slow-query log recording every query slower than `threshold`,
+    /// keeping the last 100 entries in memory.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            capacity: 100,
+            file: None,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Sets how many slow queries are kept in memory, the oldest ones being dropped first.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets a file every slow query is appended to, as one JSON object per line.
+    pub fn file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file = Some(path.into());
+        self
+    }
+
+    /// Returns the slow queries currently kept in memory, oldest first.
+    pub fn entries(&self) -> Vec<SlowQuery> {
+        self.entries.iter().cloned().collect()
+    }
+
+    /// Records `query` if it is slower than the threshold.
+    pub(crate) async fn record(&mut self, query: SlowQuery) {
+        if query.duration < self.threshold || self.capacity == 0 {
+            return;
+        }
+
+        if let Some(path) = &self.file {
+            // The slow-query log is a diagnostic aid, failing to write it must not fail the query itself.
+            let _ = append_line(path, &query).await;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(query);
+    }
+}
+
+async fn append_line(path: &PathBuf, query: &SlowQuery) -> std::io::Result<()> {
+    let at = query
+        .at
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let line = json!({
+        "at": at.as_millis() as u64,
+        "table": query.table,
+        "pipeline": query.pipeline,
+        "duration_ms": query.duration.as_secs_f64() * 1000.0,
+        "scanned": query.scanned,
+        "matched": query.matched,
+    });
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+
+    file.write_all(format!("{}\n", line).as_bytes()).await
+}
@@ -0,0 +1,405 @@
+use crate::types::{Comparator, MethodName, Order, Runner};
+use crate::utils::soundex;
+use serde_json::{Number, Value};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Num(Number),
+    Param(String),
+    Symbol(&'static str),
+}
+
+/// Parses a query in a small SQL subset into the runners it stands for.
+///
+/// The supported grammar is
+/// `SELECT (* | field, ... | DISTINCT field) FROM table [WHERE condition {AND | OR condition}]
+/// [ORDER BY field [ASC | DESC], ...] [LIMIT n] [OFFSET n]`, where a condition is one of
+/// `field (= | != | <> | < | <= | > | >=) literal`, `field [NOT] LIKE 'pattern'`, `field [NOT] SOUNDS LIKE 'name'`, `field [NOT] IN (literal, ...)`,
+/// `field BETWEEN literal AND literal`, `field IS [NOT] NULL` or `field [NOT] name literal` for a comparator
+/// registered with `JsonDB::register_comparator`. `AND` binds tighter than `OR`,
+/// keywords are case-insensitive and literals are strings (`'...'`), numbers, `true`, `false` or `null`.
+/// The value compared with `=`, `!=`, `<`, `<=`, `>`, `>=`, `LIKE` or `IN` can also be a `:name` parameter,
+/// bound with `Query::bind` before the query runs (`IN :name` expects an array).
+pub(crate) fn parse(sql: &str) -> Result<Vec<Runner>, String> {
+    let mut parser = Parser {
+        tokens: tokenize(sql)?,
+        pos: 0,
+        runners: Vec::new(),
+    };
+
+    parser.query()?;
+
+    Ok(parser.runners)
+}
+
+/// Builds the comparator `op` (`=`, `!=`, `<>`, `<`, `<=`, `>`, `>=`, `LIKE` or `IN`) stands for when comparing with `value`.
+pub(crate) fn comparator(op: &str, value: Value) -> Result<Comparator, String> {
+    Ok(match (op.to_ascii_uppercase().as_str(), value) {
+        ("=", value) => Comparator::Equals(value),
+        ("!=" | "<>", value) => Comparator::NotEquals(value),
+        ("<", Value::Number(n)) => Comparator::LessThan(n),
+        ("<=", Value::Number(n)) => Comparator::LessThanOrEqual(n),
+        (">", Value::Number(n)) => Comparator::GreaterThan(n),
+        (">=", Value::Number(n)) => Comparator::GreaterThanOrEqual(n),
+        ("<" | "<=" | ">" | ">=", _) => return Err(format!("{} expects a number", op)),
+        ("LIKE", Value::String(pattern)) => Comparator::Like(pattern),
+        ("LIKE", _) => return Err("LIKE expects a string pattern".to_string()),
+        ("IN", Value::Array(values)) => Comparator::In(values),
+        ("IN", _) => return Err("IN expects an array".to_string()),
+        (_, _) => return Err(format!("unexpected operator {}", op)),
+    })
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '\'' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            s.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some(c) => s.push(c),
+                        None => return Err("unterminated string literal".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.' || (c == '-' && s.is_empty())) {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                let number = serde_json::from_str::<Number>(&s)
+                    .map_err(|_| format!("invalid number {}", s))?;
+                tokens.push(Token::Num(number));
+            }
+            ':' => {
+                chars.next();
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                if name.is_empty() {
+                    return Err("expected a parameter name after ':'".to_string());
+                }
+                tokens.push(Token::Param(name));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(s));
+            }
+            _ => {
+                chars.next();
+                let next = chars.peek().copied();
+                let symbol = match (c, next) {
+                    ('!', Some('=')) | ('<', Some('>')) => "!=",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('=', _) => "=",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    (',', _) => ",",
+                    ('(', _) => "(",
+                    (')', _) => ")",
+                    ('*', _) => "*",
+                    (';', _) => ";",
+                    (c, _) => return Err(format!("unexpected character '{}'", c)),
+                };
+                if symbol.len() == 2 {
+                    chars.next();
+                }
+                tokens.push(Token::Symbol(symbol));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    runners: Vec<Runner>,
+}
+
+impl Parser {
+    fn query(&mut self) -> Result<(), String> {
+        self.expect_keyword("SELECT")?;
+        let (fields, distinct) = self.projection()?;
+        self.expect_keyword("FROM")?;
+        let table = self.identifier()?;
+        self.runners.push(Runner::Method(MethodName::Read(table)));
+
+        if self.keyword("WHERE") {
+            self.condition(false)?;
+            loop {
+                if self.keyword("AND") {
+                    self.condition(false)?;
+                } else if self.keyword("OR") {
+                    self.condition(true)?;
+                } else {
+                    break;
+                }
+            }
+        }
+
+        if self.keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let field = self.identifier()?;
+                let order = match (self.keyword("ASC"), self.keyword("DESC")) {
+                    (_, true) => Order::Desc,
+                    _ => Order::Asc,
+                };
+                self.runners.push(Runner::OrderBy(field, order));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        if self.keyword("LIMIT") {
+            let n = self.count()?;
+            self.runners.push(Runner::Limit(n));
+        }
+        if self.keyword("OFFSET") {
+            let n = self.count()?;
+            self.runners.push(Runner::Skip(n));
+        }
+
+        if let Some(field) = distinct {
+            self.runners.push(Runner::Distinct(field));
+        }
+        if let Some(fields) = fields {
+            self.runners.push(Runner::Select(fields));
+        }
+
+        self.symbol(";");
+        match self.tokens.get(self.pos) {
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Ok(()),
+        }
+    }
+
+    /// Parses the selected fields, returning them (`None` for `*`) and the `DISTINCT` field, if any.
+    fn projection(&mut self) -> Result<(Option<Vec<String>>, Option<String>), String> {
+        if self.symbol("*") {
+            return Ok((None, None));
+        }
+        if self.keyword("DISTINCT") {
+            let field = self.identifier()?;
+            return Ok((Some(vec![field.clone()]), Some(field)));
+        }
+
+        let mut fields = vec![self.identifier()?];
+        while self.symbol(",") {
+            fields.push(self.identifier()?);
+        }
+
+        Ok((Some(fields), None))
+    }
+
+    fn condition(&mut self, or: bool) -> Result<(), String> {
+        let field = self.identifier()?;
+        self.runners.push(match or {
+            true => Runner::OrWhere(field),
+            false => Runner::Where(field),
+        });
+
+        let negate = self.keyword("NOT");
+        let runner = if self.keyword("LIKE") {
+            self.operand("LIKE")?
+        } else if self.keyword("SOUNDS") {
+            self.expect_keyword("LIKE")?;
+            match self.literal()? {
+                Value::String(name) => Runner::Compare(Comparator::SoundsLike(soundex(&name))),
+                _ => return Err("SOUNDS LIKE expects a string".to_string()),
+            }
+        } else if self.keyword("IN") {
+            match self.param() {
+                Some(name) => Runner::Param {
+                    op: "IN".to_string(),
+                    name,
+                },
+                None => {
+                    self.expect_symbol("(")?;
+                    let mut values = vec![self.literal()?];
+                    while self.symbol(",") {
+                        values.push(self.literal()?);
+                    }
+                    self.expect_symbol(")")?;
+                    Runner::Compare(Comparator::In(values))
+                }
+            }
+        } else if self.keyword("BETWEEN") {
+            let start = self.literal()?;
+            self.expect_keyword("AND")?;
+            let end = self.literal()?;
+            Runner::Compare(match (start, end) {
+                (Value::Number(start), Value::Number(end)) => Comparator::Between((start, end)),
+                (Value::String(start), Value::String(end)) => Comparator::BetweenStr((start, end)),
+                _ => return Err("BETWEEN expects two numbers or two strings".to_string()),
+            })
+        } else if !negate && self.keyword("IS") {
+            let comparator = match self.keyword("NOT") {
+                true => Comparator::IsSet,
+                false => Comparator::IsNull,
+            };
+            self.expect_keyword("NULL")?;
+            Runner::Compare(comparator)
+        } else if let Some(Token::Word(name)) = self.tokens.get(self.pos) {
+            let name = name.clone();
+            self.pos += 1;
+            Runner::Custom(name, self.literal()?)
+        } else if !negate {
+            let op = match self.tokens.get(self.pos) {
+                Some(Token::Symbol(op)) => *op,
+                other => return Err(format!("expected a comparison operator, found {:?}", other)),
+            };
+            self.pos += 1;
+            self.operand(op)?
+        } else {
+            return Err(
+                "NOT must be followed by LIKE, SOUNDS LIKE, IN, BETWEEN or a custom comparator"
+                    .to_string(),
+            );
+        };
+
+        if negate {
+            self.runners.push(Runner::Not);
+        }
+        self.runners.push(runner);
+
+        Ok(())
+    }
+
+    /// Parses the value compared by `op`, either a literal or a `:name` parameter.
+    fn operand(&mut self, op: &str) -> Result<Runner, String> {
+        match self.param() {
+            Some(name) => Ok(Runner::Param {
+                op: op.to_string(),
+                name,
+            }),
+            None => Ok(Runner::Compare(comparator(op, self.literal()?)?)),
+        }
+    }
+
+    /// Consumes the next token if it is a `:name` parameter, returning its name.
+    fn param(&mut self) -> Option<String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Param(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn literal(&mut self) -> Result<Value, String> {
+        let value = match self.tokens.get(self.pos) {
+            Some(Token::Str(s)) => Value::String(s.clone()),
+            Some(Token::Num(n)) => Value::Number(n.clone()),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("true") => Value::Bool(true),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("false") => Value::Bool(false),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("null") => Value::Null,
+            other => return Err(format!("expected a literal, found {:?}", other)),
+        };
+        self.pos += 1;
+
+        Ok(value)
+    }
+
+    fn count(&mut self) -> Result<usize, String> {
+        match self.literal()? {
+            Value::Number(n) => n
+                .as_u64()
+                .map(|n| n as usize)
+                .ok_or_else(|| format!("expected a non-negative integer, found {}", n)),
+            other => Err(format!("expected a non-negative integer, found {}", other)),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(w)) => {
+                self.pos += 1;
+                Ok(w.clone())
+            }
+            other => Err(format!("expected an identifier, found {:?}", other)),
+        }
+    }
+
+    /// Consumes the next token if it is the provided keyword.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let matches = matches!(
+            self.tokens.get(self.pos),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword)
+        );
+        if matches {
+            self.pos += 1;
+        }
+
+        matches
+    }
+
+    /// Consumes the next token if it is the provided symbol.
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let matches = matches!(self.tokens.get(self.pos), Some(Token::Symbol(s)) if *s == symbol);
+        if matches {
+            self.pos += 1;
+        }
+
+        matches
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        match self.keyword(keyword) {
+            true => Ok(()),
+            false => Err(format!(
+                "expected {}, found {:?}",
+                keyword,
+                self.tokens.get(self.pos)
+            )),
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        match self.symbol(symbol) {
+            true => Ok(()),
+            false => Err(format!(
+                "expected '{}', found {:?}",
+                symbol,
+                self.tokens.get(self.pos)
+            )),
+        }
+    }
+}
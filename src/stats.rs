@@ -0,0 +1,192 @@
+use crate::types::Comparator;
+use crate::utils::{compare_values, get_nested_ref};
+use serde_json::{Number, Value};
+use std::collections::HashSet;
+
+/// Simple statistics about the values of a single field across a table.
+///
+/// Statistics are computed lazily by `JsonDB::field_stats` and cached, the cache entry being marked stale
+/// when the table is modified and recomputed on next use or by `JsonDB::reindex`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldStats {
+    /// The number of records in the table.
+    pub total: usize,
+    /// The number of distinct non-null values of the field.
+    pub distinct_count: usize,
+    /// The smallest non-null value of the field, if any.
+    pub min: Option<Value>,
+    /// The largest non-null value of the field, if any.
+    pub max: Option<Value>,
+    /// The fraction of records (0.0 to 1.0) in which the field is missing or `null`.
+    pub null_fraction: f64,
+}
+
+impl FieldStats {
+    /// Computes the statistics of the field at `field` (a dot-separated key chain) over `records`.
+    pub(crate) fn compute<'a, I>(records: I, field: &str) -> Self
+    where
+        I: IntoIterator<Item = &'a Value>,
+    {
+        let mut total = 0;
+        let mut nulls = 0;
+        let mut distinct: HashSet<&Value> = HashSet::new();
+        let mut min: Option<&Value> = None;
+        let mut max: Option<&Value> = None;
+
+        for record in records {
+            total += 1;
+
+            match get_nested_ref(record, field) {
+                None | Some(Value::Null) => nulls += 1,
+                Some(value) => {
+                    distinct.insert(value);
+
+                    if min.is_none_or(|m| compare_values(Some(value), Some(m)).is_lt()) {
+                        min = Some(value);
+                    }
+
+                    if max.is_none_or(|m| compare_values(Some(value), Some(m)).is_gt()) {
+                        max = Some(value);
+                    }
+                }
+            }
+        }
+
+        Self {
+            total,
+            distinct_count: distinct.len(),
+            min: min.cloned(),
+            max: max.cloned(),
+            null_fraction: if total == 0 {
+                0.0
+            } else {
+                nulls as f64 / total as f64
+            },
+        }
+    }
+
+    /// Estimates the fraction of records an equality filter on this field would match,
+    /// assuming values are uniformly distributed.
+    pub fn equality_selectivity(&self) -> f64 {
+        if self.distinct_count == 0 {
+            0.0
+        } else {
+            (1.0 - self.null_fraction) / self.distinct_count as f64
+        }
+    }
+
+    /// Estimates the fraction of records a filter condition on this field would match, assuming values are
+    /// uniformly distributed (between `min` and `max` for range conditions), or `None` for conditions
+    /// the statistics say nothing about (patterns, predicates, ...).
+    pub(crate) fn selectivity(&self, comparator: &Comparator) -> Option<f64> {
+        let set = 1.0 - self.null_fraction;
+        let selectivity = match comparator {
+            Comparator::IsNull | Comparator::Equals(Value::Null) => self.null_fraction,
+            Comparator::IsSet | Comparator::NotEquals(Value::Null) => set,
+            Comparator::Equals(value) => self.equality_selectivity() * self.within(value),
+            Comparator::NotEquals(value) => 1.0 - self.equality_selectivity() * self.within(value),
+            Comparator::In(values) => self.any_of(values),
+            Comparator::NotIn(values) => 1.0 - self.any_of(values),
+            Comparator::LessThan(n) | Comparator::LessThanOrEqual(n) => set * self.below(n)?,
+            Comparator::GreaterThan(n) | Comparator::GreaterThanOrEqual(n) => {
+                set * (1.0 - self.below(n)?)
+            }
+            Comparator::Between((low, high)) => {
+                set * (self.below(high)? - self.below(low)?).max(0.0)
+            }
+            Comparator::Not(comparator) => 1.0 - self.selectivity(comparator)?,
+            _ => return None,
+        };
+
+        Some(selectivity.clamp(0.0, 1.0))
+    }
+
+    /// Returns the estimated fraction of records holding one of `values`.
+    fn any_of(&self, values: &[Value]) -> f64 {
+        values
+            .iter()
+            .map(|value| match value {
+                Value::Null => self.null_fraction,
+                value => self.equality_selectivity() * self.within(value),
+            })
+            .sum::<f64>()
+            .min(1.0)
+    }
+
+    /// Returns `0.0` if `value` is outside of the range of the field, `1.0` otherwise.
+    fn within(&self, value: &Value) -> f64 {
+        let below_min = self
+            .min
+            .as_ref()
+            .is_some_and(|min| compare_values(Some(value), Some(min)).is_lt());
+        let above_max = self
+            .max
+            .as_ref()
+            .is_some_and(|max| compare_values(Some(value), Some(max)).is_gt());
+
+        match below_min || above_max {
+            true => 0.0,
+            false => 1.0,
+        }
+    }
+
+    /// Returns the fraction of the numeric range of the field that is below `bound`, or `None` if the field
+    /// doesn't hold numbers.
+    fn below(&self, bound: &Number) -> Option<f64> {
+        let min = self.min.as_ref()?.as_f64()?;
+        let max = self.max.as_ref()?.as_f64()?;
+        let bound = bound.as_f64()?;
+
+        Some(match max > min {
+            true => ((bound - min) / (max - min)).clamp(0.0, 1.0),
+            false if bound >= min => 1.0,
+            false => 0.0,
+        })
+    }
+}
+
+/// A cache entry of the `FieldStats` of a field, kept when the table is modified so `JsonDB::reindex`
+/// knows which fields to rebuild.
+#[derive(Clone, Debug)]
+pub(crate) struct CachedStats {
+    pub(crate) stats: FieldStats,
+    /// Whether the table was modified since the statistics were computed.
+    pub(crate) stale: bool,
+}
+
+/// The health of a cached `FieldStats` entry, as reported by `JsonDB::index_stats`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexStats {
+    /// The table the statistics describe.
+    pub table: String,
+    /// The (possibly nested, dot-separated) field the statistics describe.
+    pub field: String,
+    /// The number of records the statistics were computed over.
+    pub records: usize,
+    /// The number of distinct values of the field.
+    pub entries: usize,
+    /// The approximate memory used by the cache entry, in bytes.
+    pub memory_bytes: usize,
+    /// Whether the table was modified since the statistics were computed.
+    pub stale: bool,
+}
+
+impl IndexStats {
+    pub(crate) fn new(table: &str, field: &str, cached: &CachedStats) -> Self {
+        let value_size = |value: &Option<Value>| value.as_ref().map_or(0, |v| v.to_string().len());
+        let stats = &cached.stats;
+
+        Self {
+            table: table.to_string(),
+            field: field.to_string(),
+            records: stats.total,
+            entries: stats.distinct_count,
+            memory_bytes: std::mem::size_of::<CachedStats>()
+                + table.len()
+                + field.len()
+                + value_size(&stats.min)
+                + value_size(&stats.max),
+            stale: cached.stale,
+        }
+    }
+}
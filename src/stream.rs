@@ -0,0 +1,40 @@
+use futures_core::Stream;
+use serde_json::Value;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A stream of every record of the database with the name of its table, returned by `JsonDB::iter_all`.
+///
+/// Records are produced from the in-memory data, so the stream is always ready; it borrows the database
+/// until it is dropped. Consume it with `StreamExt::next` from the `futures` crate, or `next_record`.
+pub struct RecordStream<'a> {
+    records: Box<dyn Iterator<Item = (String, Value)> + Send + 'a>,
+}
+
+impl<'a> RecordStream<'a> {
+    pub(crate) fn new<I>(records: I) -> Self
+    where
+        I: Iterator<Item = (String, Value)> + Send + 'a,
+    {
+        Self {
+            records: Box::new(records),
+        }
+    }
+
+    /// Returns the next record with the name of its table, or `None` once every record was produced.
+    pub async fn next_record(&mut self) -> Option<(String, Value)> {
+        self.records.next()
+    }
+}
+
+impl Stream for RecordStream<'_> {
+    type Item = (String, Value);
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.records.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+}
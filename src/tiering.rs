@@ -0,0 +1,36 @@
+use crate::archive;
+use crate::error::OhMyDbError;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// The reserved table listing the cold tables, with the file holding the records of each one.
+pub(crate) const COLD_TABLE: &str = "_cold";
+
+/// Returns the name of a new file holding the records of a cold table, next to the database file,
+/// e.g. `todos.cold-7281.json.gz` for the database file `todos.json`.
+pub(crate) fn cold_file_name(db_path: &Path, id: &str) -> String {
+    let stem = db_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("ohmydb");
+
+    format!("{}.cold-{}.json.gz", stem, id)
+}
+
+/// Returns the path of a file holding the records of a cold table.
+pub(crate) fn cold_path(db_path: &Path, file_name: &str) -> PathBuf {
+    db_path.with_file_name(file_name)
+}
+
+/// Writes the records of a table gzip-compressed to a cold table file.
+pub(crate) async fn write_cold(path: &Path, records: &HashSet<Value>) -> Result<(), OhMyDbError> {
+    tokio::fs::write(path, archive::encode(records)?).await?;
+
+    Ok(())
+}
+
+/// Reads the records of a table back from a cold table file.
+pub(crate) async fn read_cold(path: &Path) -> Result<HashSet<Value>, OhMyDbError> {
+    archive::decode(&tokio::fs::read(path).await?)
+}
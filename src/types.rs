@@ -1,19 +1,102 @@
 #![allow(dead_code)]
 
+use crate::merge::MergeOptions;
+use crate::update::UpdateOp;
 use crate::utils::display_object;
 use colored::customcolors::CustomColor;
 use colored::Colorize;
-use serde_json::Value;
+use serde_json::{Number, Value};
+use serde_json_path::JsonPath;
 use std::fmt::Debug;
+use std::sync::Arc;
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Comparator {
-    Equals(String),
-    NotEquals(String),
-    LessThan(u64),
-    GreaterThan(u64),
-    In(Vec<String>),
-    Between((u64, u64)),
+    Equals(Value),
+    NotEquals(Value),
+    LessThan(Number),
+    GreaterThan(Number),
+    GreaterThanOrEqual(Number),
+    LessThanOrEqual(Number),
+    In(Vec<Value>),
+    NotIn(Vec<Value>),
+    Between((Number, Number)),
+    BetweenStr((String, String)),
+    Not(Box<Comparator>),
+    Contains(Value),
+    Predicate(Predicate),
+    Like(String),
+    StartsWith(String),
+    EndsWith(String),
+    SoundsLike(String),
+    IsNull,
+    IsSet,
+    #[cfg(feature = "regex")]
+    Matches(Pattern),
+}
+
+/// A regular expression compiled once when the query is built, compared by its source pattern.
+#[cfg(feature = "regex")]
+#[derive(Clone, Debug)]
+pub struct Pattern(pub regex::Regex);
+
+#[cfg(feature = "regex")]
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+type PredicateFn = Arc<dyn Fn(&Value) -> bool + Send + Sync>;
+
+/// A comparator registered with `JsonDB::register_comparator`, called with the field value and the query argument.
+pub(crate) type CustomComparator = Arc<dyn Fn(&Value, &Value) -> bool + Send + Sync>;
+
+/// A custom filter condition provided as a closure, compared by identity.
+#[derive(Clone)]
+pub struct Predicate(pub PredicateFn);
+
+impl PartialEq for Predicate {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for Predicate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Predicate")
+    }
+}
+
+/// The part of a record a filter condition applies to.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Field {
+    /// A (possibly nested, dot-separated) key chain.
+    Key(String),
+    /// A JSONPath expression, the condition holding if any selected node satisfies it.
+    Path(JsonPath),
+    /// The whole record.
+    Record,
+}
+
+/// A table whose matching records are attached to every result of a read, see `Query::join`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Join {
+    /// The joined table.
+    pub table: String,
+    /// The (possibly nested, dot-separated) field of the queried records.
+    pub local: String,
+    /// The (possibly nested, dot-separated) field of the joined records that must equal the local field.
+    pub foreign: String,
+    /// The key the matching records are attached under.
+    pub key: String,
+}
+
+/// The direction in which `order_by` sorts the query results.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Order {
+    Asc,
+    Desc,
 }
 
 #[derive(Clone, PartialEq, Debug)]
@@ -21,10 +104,44 @@ pub enum MethodName {
     Create(String, Value, bool),
     Read(String),
     Update(String, Value),
+    Upsert(String, Value),
+    Merge(String, Value, MergeOptions),
+    Modify(String),
     Delete(String),
+    Restore(String),
+    Purge(String),
 }
 
 impl MethodName {
+    /// Returns the name of the table the method operates on.
+    pub fn table(&self) -> &str {
+        match self {
+            MethodName::Create(table, _, _)
+            | MethodName::Read(table)
+            | MethodName::Update(table, _)
+            | MethodName::Upsert(table, _)
+            | MethodName::Merge(table, _, _)
+            | MethodName::Modify(table)
+            | MethodName::Delete(table)
+            | MethodName::Restore(table)
+            | MethodName::Purge(table) => table,
+        }
+    }
+
+    /// Returns the name of the operation the method performs, e.g. `"read"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MethodName::Create(..) => "create",
+            MethodName::Read(_) => "read",
+            MethodName::Update(..) | MethodName::Modify(_) => "update",
+            MethodName::Upsert(..) => "upsert",
+            MethodName::Merge(..) => "merge",
+            MethodName::Delete(_) => "delete",
+            MethodName::Restore(_) => "restore",
+            MethodName::Purge(_) => "purge",
+        }
+    }
+
     /// Prints a message to the console based on the variant of the `MethodName` enum.
     ///
     /// This method is used to provide visual feedback to the user when performing CRUD operations on a database table.
@@ -67,7 +184,9 @@ impl MethodName {
                 lead = "🔎 Querying".custom_color(teal).bold(),
                 trail = "table...".custom_color(teal).bold()
             ),
-            MethodName::Update(table, item) => {
+            MethodName::Update(table, item)
+            | MethodName::Upsert(table, item)
+            | MethodName::Merge(table, item, _) => {
                 if let Value::Object(obj) = item {
                     println!(
                         "{lead} {} {trail}\n\n {} \n",
@@ -80,38 +199,56 @@ impl MethodName {
                     println!("Not a JSON object");
                 }
             }
+            MethodName::Modify(table) => println!(
+                "{lead} {} {trail}\n",
+                table.custom_color(gold).bold(),
+                lead = "⛁ Updating records in".custom_color(yellow).bold(),
+                trail = "table...".custom_color(yellow).bold()
+            ),
             MethodName::Delete(table) => println!(
                 "{lead} {} {trail}\n",
                 table.custom_color(gold).bold(),
                 lead = "✗ Deleting records from".custom_color(red).bold(),
                 trail = "table...".custom_color(red).bold()
             ),
+            MethodName::Restore(table) => println!(
+                "{lead} {} {trail}\n",
+                table.custom_color(gold).bold(),
+                lead = "⛁ Restoring records in".custom_color(yellow).bold(),
+                trail = "table...".custom_color(yellow).bold()
+            ),
+            MethodName::Purge(table) => println!(
+                "{lead} {} {trail}\n",
+                table.custom_color(gold).bold(),
+                lead = "✗ Purging deleted records from".custom_color(red).bold(),
+                trail = "table...".custom_color(red).bold()
+            ),
         }
     }
 }
 
 #[derive(Clone, PartialEq, Debug)]
 pub enum Runner {
-    Done,
     Method(MethodName),
     Compare(Comparator),
+    Param { op: String, name: String },
+    Custom(String, Value),
+    Operator(UpdateOp),
+    Join(Join),
+    Populate(String),
     Where(String),
-}
-
-struct MyType {
-    name: String,
-    age: u32,
-}
-
-impl From<Value> for MyType {
-    fn from(value: Value) -> Self {
-        match value {
-            Value::Object(map) => {
-                let name = map.get("name").unwrap().as_str().unwrap().to_string();
-                let age = map.get("age").unwrap().as_u64().unwrap() as u32;
-                MyType { name, age }
-            }
-            _ => panic!("Invalid value"),
-        }
-    }
+    WherePath(JsonPath),
+    OrWhere(String),
+    Not,
+    OrderBy(String, Order),
+    Skip(usize),
+    Limit(usize),
+    Select(Vec<String>),
+    Distinct(String),
+    WithMeta,
+    WithDeleted,
+    FilterWith(Predicate),
+    Filters(Vec<Vec<(Field, Comparator)>>),
+    Invalid(String),
+    InvalidPattern { pattern: String, reason: String },
 }
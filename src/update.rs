@@ -0,0 +1,137 @@
+use crate::error::OhMyDbError;
+use crate::json_patch::PatchOp;
+use crate::utils::{get_nested_entry, get_nested_mut, values_equal};
+use serde_json::{Number, Value};
+
+/// A change applied in place to every record selected by `JsonDB::update_where`, `JsonDB::update_field`
+/// or `JsonDB::apply_patch`.
+#[derive(Clone, PartialEq, Debug)]
+pub enum UpdateOp {
+    /// Sets the field to the value, creating it (and the objects it is nested in) if it is missing.
+    Set(String, Value),
+    /// Appends the value to the array field.
+    Push(String, Value),
+    /// Appends the value to the array field unless it already contains it.
+    AddToSet(String, Value),
+    /// Removes every occurrence of the values from the array field.
+    PullAll(String, Vec<Value>),
+    /// Adds the number to the numeric field, a missing field counting as `0`.
+    Increment(String, Value),
+    /// Subtracts the number from the numeric field, a missing field counting as `0`.
+    Decrement(String, Value),
+    /// Applies the JSON Patch operations in order, failing as a whole if any of them fails.
+    Patch(Vec<PatchOp>),
+}
+
+impl UpdateOp {
+    /// Applies the operator to the user data of a record.
+    ///
+    /// # Errors
+    ///
+    /// This method returns an `OhMyDbError::InvalidField` error naming the field (or the JSON Pointer
+    /// of the patch operation) that cannot be changed, and why.
+    pub(crate) fn apply(&self, record: &mut Value) -> Result<(), OhMyDbError> {
+        match self {
+            UpdateOp::Patch(ops) => {
+                for op in ops {
+                    op.apply(record)
+                        .map_err(|reason| OhMyDbError::InvalidField {
+                            field: op.path().to_string(),
+                            reason,
+                        })?;
+                }
+                Ok(())
+            }
+            UpdateOp::Set(field, _)
+            | UpdateOp::Push(field, _)
+            | UpdateOp::AddToSet(field, _)
+            | UpdateOp::PullAll(field, _)
+            | UpdateOp::Increment(field, _)
+            | UpdateOp::Decrement(field, _) => {
+                self.apply_to_field(record)
+                    .map_err(|reason| OhMyDbError::InvalidField {
+                        field: field.clone(),
+                        reason,
+                    })
+            }
+        }
+    }
+
+    fn apply_to_field(&self, record: &mut Value) -> Result<(), String> {
+        match self {
+            UpdateOp::Set(field, value) => {
+                *get_nested_entry(record, field)
+                    .ok_or("the field is nested in a value that is not an object")? = value.clone();
+            }
+            UpdateOp::Push(field, value) => {
+                let target = get_nested_entry(record, field)
+                    .ok_or("the field is nested in a value that is not an object")?;
+                if target.is_null() {
+                    *target = Value::Array(Vec::new());
+                }
+
+                target
+                    .as_array_mut()
+                    .ok_or("expected an array")?
+                    .push(value.clone());
+            }
+            UpdateOp::AddToSet(field, value) => {
+                let target = get_nested_entry(record, field)
+                    .ok_or("the field is nested in a value that is not an object")?;
+                if target.is_null() {
+                    *target = Value::Array(Vec::new());
+                }
+                let items = target.as_array_mut().ok_or("expected an array")?;
+
+                if !items.iter().any(|item| values_equal(item, value)) {
+                    items.push(value.clone());
+                }
+            }
+            UpdateOp::PullAll(field, values) => match get_nested_mut(record, field) {
+                None | Some(Value::Null) => {}
+                Some(Value::Array(items)) => {
+                    items.retain(|item| !values.iter().any(|value| values_equal(item, value)))
+                }
+                Some(_) => return Err("expected an array".to_string()),
+            },
+            UpdateOp::Increment(field, by) | UpdateOp::Decrement(field, by) => {
+                let Value::Number(by) = by else {
+                    return Err("the amount must be a number".to_string());
+                };
+                let target = get_nested_entry(record, field)
+                    .ok_or("the field is nested in a value that is not an object")?;
+                let current = match target {
+                    Value::Null => Number::from(0),
+                    Value::Number(current) => current.clone(),
+                    _ => return Err("expected a number".to_string()),
+                };
+
+                *target = Value::Number(add_numbers(
+                    &current,
+                    by,
+                    matches!(self, UpdateOp::Decrement(..)),
+                )?);
+            }
+            UpdateOp::Patch(_) => unreachable!("patches are applied by apply"),
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds (or subtracts, if `negate` is set) two JSON numbers, exactly if both are integers.
+fn add_numbers(x: &Number, y: &Number, negate: bool) -> Result<Number, String> {
+    let integer = |n: &Number| n.as_i64().map(i128::from).or(n.as_u64().map(i128::from));
+
+    if let (Some(x), Some(y)) = (integer(x), integer(y)) {
+        let sum = if negate { x - y } else { x + y };
+        return i64::try_from(sum)
+            .map(Number::from)
+            .or(u64::try_from(sum).map(Number::from))
+            .map_err(|_| "the result overflows a 64-bit integer".to_string());
+    }
+
+    let (x, y) = (x.as_f64().unwrap_or(0.0), y.as_f64().unwrap_or(0.0));
+    Number::from_f64(if negate { x - y } else { x + y })
+        .ok_or_else(|| "the result is not a finite number".to_string())
+}
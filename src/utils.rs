@@ -3,6 +3,7 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::{Map, Value as JSonValue};
 use serde_value::Value;
+use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::io::{Error, ErrorKind, Result};
 
@@ -68,9 +69,9 @@ where
 {
     let mut parts = key_chain.split('.').collect::<Vec<&str>>();
     let key = parts.remove(0);
-    let value: Value = get_field_by_name(data, key).unwrap();
+    let value: Value = get_field_by_name(data, key).ok()?;
 
-    if parts.len() > 0 {
+    if !parts.is_empty() {
         let new_key_chain = parts.join(".");
         return get_key_chain_value(value, &new_key_chain);
     }
@@ -100,7 +101,8 @@ where
     R: DeserializeOwned,
 {
     let parts: VecDeque<&str> = key_chain.split('.').collect();
-    let mut current_value = serde_value::to_value(data).unwrap();
+    let mut current_value = serde_value::to_value(data)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))?;
 
     for key in parts {
         match current_value {
@@ -125,6 +127,272 @@ where
     }
 }
 
+/// Retrieves a reference to a nested field in a JSON value.
+///
+/// The `key_chain` is a dot-separated path (e.g. `"wife.name"`). `None` is returned if any part
+/// of the key chain is missing or points into a value that is not an object.
+pub(crate) fn get_nested_ref<'a>(value: &'a JSonValue, key_chain: &str) -> Option<&'a JSonValue> {
+    key_chain
+        .split('.')
+        .try_fold(value, |current, key| current.as_object()?.get(key))
+}
+
+/// Retrieves a mutable reference to a nested field in a JSON value.
+///
+/// The `key_chain` is a dot-separated path (e.g. `"wife.name"`). `None` is returned if any part
+/// of the key chain is missing or points into a value that is not an object.
+pub(crate) fn get_nested_mut<'a>(
+    value: &'a mut JSonValue,
+    key_chain: &str,
+) -> Option<&'a mut JSonValue> {
+    key_chain
+        .split('.')
+        .try_fold(value, |current, key| current.as_object_mut()?.get_mut(key))
+}
+
+/// Retrieves a mutable reference to a nested field in a JSON value, creating it as `null` if it is missing.
+///
+/// Missing intermediate keys of the dot-separated `key_chain` are created as empty objects. `None` is returned
+/// if any part of the key chain points into a value that is not an object.
+pub(crate) fn get_nested_entry<'a>(
+    value: &'a mut JSonValue,
+    key_chain: &str,
+) -> Option<&'a mut JSonValue> {
+    key_chain.split('.').try_fold(value, |current, key| {
+        if current.is_null() {
+            *current = JSonValue::Object(Map::new());
+        }
+        Some(
+            current
+                .as_object_mut()?
+                .entry(key)
+                .or_insert(JSonValue::Null),
+        )
+    })
+}
+
+/// Returns the value of the `key` field of a record as a string.
+///
+/// Both string and numeric ids are accepted; `None` is returned if the record is not an object,
+/// lacks the field, or holds a value of another type in it.
+pub(crate) fn record_id(record: &JSonValue, key: &str) -> Option<String> {
+    match get_nested_ref(record, key)? {
+        JSonValue::String(s) => Some(s.clone()),
+        JSonValue::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Wraps a record that is not a JSON object into an object that can be stored in a table.
+///
+/// Scalars and arrays are stored as `{"id": <hash>, "value": <record>}`, where the id is a stable
+/// hash of the record content, so inserting the same value twice is detected as a duplicate.
+/// Objects are returned unchanged.
+pub(crate) fn wrap_record(record: JSonValue) -> JSonValue {
+    if record.is_object() {
+        return record;
+    }
+
+    let mut wrapped = Map::new();
+    wrapped.insert(
+        "id".to_string(),
+        JSonValue::String(format!("{:016x}", content_hash(&record))),
+    );
+    wrapped.insert("value".to_string(), record);
+
+    JSonValue::Object(wrapped)
+}
+
+/// Computes a stable FNV-1a hash of the JSON serialization of a value.
+fn content_hash(value: &JSonValue) -> u64 {
+    value
+        .to_string()
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Projects a JSON value down to the given fields.
+///
+/// Each field is a dot-separated key chain; nested fields keep their nesting in the projected
+/// value (e.g. `"wife.name"` projects to `{"wife": {"name": ...}}`). Missing fields are omitted.
+pub(crate) fn project(value: &JSonValue, fields: &[String]) -> JSonValue {
+    let mut projected = JSonValue::Object(Map::new());
+
+    for field in fields {
+        let Some(field_value) = get_nested_ref(value, field) else {
+            continue;
+        };
+
+        let mut current = &mut projected;
+        let mut parts = field.split('.').peekable();
+
+        while let Some(key) = parts.next() {
+            let Some(obj) = current.as_object_mut() else {
+                break;
+            };
+
+            if parts.peek().is_none() {
+                obj.insert(key.to_string(), field_value.clone());
+                break;
+            }
+
+            current = obj
+                .entry(key.to_string())
+                .or_insert_with(|| JSonValue::Object(Map::new()));
+        }
+    }
+
+    projected
+}
+
+/// Converts a string into a lowercase, URL-friendly slug, e.g. "Buy Groceries!" into "buy-groceries".
+pub(crate) fn slugify(s: &str) -> String {
+    s.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<&str>>()
+        .join("-")
+}
+
+/// Computes the American Soundex code of a string, e.g. "Robert" and "Rupert" into "R163".
+///
+/// Non-ASCII letters are ignored, and a string without any letter has an empty code.
+pub(crate) fn soundex(s: &str) -> String {
+    // `None` for the letters that are skipped entirely, `'0'` for the vowels that separate equal codes
+    fn code(c: char) -> Option<char> {
+        match c {
+            'b' | 'f' | 'p' | 'v' => Some('1'),
+            'c' | 'g' | 'j' | 'k' | 'q' | 's' | 'x' | 'z' => Some('2'),
+            'd' | 't' => Some('3'),
+            'l' => Some('4'),
+            'm' | 'n' => Some('5'),
+            'r' => Some('6'),
+            'h' | 'w' => None,
+            _ => Some('0'),
+        }
+    }
+
+    let mut letters = s
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase());
+    let Some(first) = letters.next() else {
+        return String::new();
+    };
+
+    let mut result = first.to_ascii_uppercase().to_string();
+    let mut last = code(first);
+
+    for current in letters.filter_map(code) {
+        if current != '0' && Some(current) != last {
+            result.push(current);
+            if result.len() == 4 {
+                break;
+            }
+        }
+        last = Some(current);
+    }
+
+    format!("{:0<4}", result)
+}
+
+/// Matches a string against a SQL `LIKE` pattern, case-sensitively.
+///
+/// `%` matches any sequence of characters (including none) and `_` matches exactly one character.
+pub(crate) fn like_match(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    let (mut p, mut i) = (0, 0);
+    // Position of the last `%` seen in the pattern, and of the string when it was seen
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while i < s.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                backtrack = Some((p, i));
+                p += 1;
+            }
+            Some('_') => {
+                p += 1;
+                i += 1;
+            }
+            Some(c) if *c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match backtrack {
+                // Let the last `%` swallow one more character and retry
+                Some((bp, bi)) => {
+                    backtrack = Some((bp, bi + 1));
+                    p = bp + 1;
+                    i = bi + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+/// Returns a sort key for a JSON number: its approximate `f64` value, and its exact integer value
+/// to break ties between large integers that share the same `f64` approximation.
+///
+/// Comparing keys lexicographically gives a total order, which `sort_by` requires to never panic.
+fn number_key(n: &serde_json::Number) -> (f64, i128) {
+    let approx = n.as_f64().unwrap_or_default();
+    let exact = n
+        .as_i64()
+        .map(i128::from)
+        .or_else(|| n.as_u64().map(i128::from))
+        .unwrap_or(approx as i128);
+
+    (approx, exact)
+}
+
+/// Compares two JSON numbers numerically, integers exactly and floats by value.
+pub(crate) fn compare_numbers(x: &serde_json::Number, y: &serde_json::Number) -> Ordering {
+    let (x_approx, x_exact) = number_key(x);
+    let (y_approx, y_exact) = number_key(y);
+    x_approx.total_cmp(&y_approx).then(x_exact.cmp(&y_exact))
+}
+
+/// Returns whether two JSON values are equal, comparing numbers numerically.
+pub(crate) fn values_equal(value: &JSonValue, other: &JSonValue) -> bool {
+    match (value, other) {
+        (JSonValue::Number(x), JSonValue::Number(y)) => compare_numbers(x, y).is_eq(),
+        _ => value == other,
+    }
+}
+
+/// Compares two optional JSON values for sorting.
+///
+/// Numbers are compared numerically and strings lexicographically. Missing and `null` values sort
+/// before everything else, and values of different types are ordered by type
+/// (null < bool < number < string < array < object).
+pub(crate) fn compare_values(a: Option<&JSonValue>, b: Option<&JSonValue>) -> Ordering {
+    fn rank(value: Option<&JSonValue>) -> u8 {
+        match value {
+            None | Some(JSonValue::Null) => 0,
+            Some(JSonValue::Bool(_)) => 1,
+            Some(JSonValue::Number(_)) => 2,
+            Some(JSonValue::String(_)) => 3,
+            Some(JSonValue::Array(_)) => 4,
+            Some(JSonValue::Object(_)) => 5,
+        }
+    }
+
+    match (a, b) {
+        (Some(JSonValue::Bool(x)), Some(JSonValue::Bool(y))) => x.cmp(y),
+        (Some(JSonValue::Number(x)), Some(JSonValue::Number(y))) => compare_numbers(x, y),
+        (Some(JSonValue::String(x)), Some(JSonValue::String(y))) => x.cmp(y),
+        (Some(JSonValue::Array(x)), Some(JSonValue::Array(y))) => x.len().cmp(&y.len()),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
 fn colorize_value(value: &JSonValue) -> String {
     match value {
         JSonValue::Null => "null".dimmed().to_string(),